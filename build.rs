@@ -0,0 +1,17 @@
+//! Generates the gRPC server code from `proto/places.proto` when the `grpc`
+//! feature is on. protoc comes from `protoc-bin-vendored`, so no system
+//! install is needed.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/places.proto");
+        let protoc =
+            protoc_bin_vendored::protoc_bin_path().expect("Failed to locate vendored protoc");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/places.proto"], &["proto"])
+            .expect("Failed to compile proto/places.proto");
+    }
+}
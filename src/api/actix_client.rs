@@ -1,27 +1,16 @@
 #![allow(unused_must_use)]
 
-use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
-use tracing::{error, info, warn};
-use tracing_subscriber::EnvFilter;
+use tracing::info;
 
-use std::{io::Result, time::Duration};
-
-use actix_cors::Cors;
-use actix_files::NamedFile;
-use actix_web::body::{BoxBody, EitherBody};
-use actix_web::dev::{Service, ServiceResponse};
-use actix_web::http::header;
-use actix_web::web::Data;
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
-use moka::future::Cache;
+use actix_web::{get, HttpResponse, Responder};
 use serde_json::{json, Value};
-use std::env::var;
-use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::Mutex;
 
+/// Health check with the API version.
+#[utoipa::path(
+    tag = "health",
+    responses((status = 200, description = "Service is healthy", body = Object))
+)]
 #[get("/")]
 pub async fn ping() -> impl Responder {
     let start_time: Instant = Instant::now();
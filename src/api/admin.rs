@@ -0,0 +1,292 @@
+//! Operator endpoints, mounted under the `/admin` scope.
+
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, ResponseError};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::analytics::{clear_zero_results, zero_result_count, zero_results};
+use crate::api::error::ApiError;
+use crate::api::middleware::usage::{parse_window, timeseries};
+use crate::cache::response_cache::{flush_all, flush_prefix, stats};
+use crate::config;
+use crate::logging::{current_log_level, set_log_level};
+use crate::query::zones::{self, Zone};
+use crate::report::environment_report;
+use crate::SharedCache;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LogLevelUpdate {
+    /// `EnvFilter` directive, e.g. `debug` or `places_autocomplete_rs::query=trace`.
+    pub log_level: String,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CacheFlush {
+    /// Only flush keys starting with this, e.g. `search:`. Omit to flush all.
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CacheStatsQuery {
+    /// Include up to this many cached keys in the response.
+    pub keys: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// Client to report on, see `api::middleware::usage`.
+    pub key: String,
+    /// e.g. `15m`, `1h` or `1d`. Defaults to `1h`.
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ZeroResultsQuery {
+    /// Number of queries returned, most frequent first. Defaults to 100.
+    pub limit: Option<usize>,
+}
+
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 200, description = "Active log filter", body = Object)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/log_level")]
+pub async fn get_log_level() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "log_level": current_log_level() }))
+}
+
+/// Adjusts the tracing filter at runtime. The change is not persisted, the
+/// config value applies again on the next SIGHUP or restart.
+#[utoipa::path(
+    tag = "admin",
+    request_body = LogLevelUpdate,
+    responses(
+        (status = 200, description = "New log filter", body = Object),
+        (status = 400, description = "Invalid directive", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[put("/log_level")]
+pub async fn put_log_level(body: web::Json<LogLevelUpdate>) -> impl Responder {
+    match set_log_level(&body.log_level) {
+        Ok(()) => {
+            info!("Log level changed to '{}'", body.log_level);
+            HttpResponse::Ok().json(json!({ "log_level": current_log_level() }))
+        }
+        Err(e) => {
+            warn!("Rejected log level '{}': {}", body.log_level, e);
+            ApiError::bad_request("invalid_log_level", e).error_response()
+        }
+    }
+}
+
+/// Version, uptime, active config, features and dataset numbers.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Environment report", body = Object),
+        (status = 503, description = "Data unavailable", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/info")]
+pub async fn info() -> impl Responder {
+    match environment_report() {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => ApiError::from(e).error_response(),
+    }
+}
+
+/// Flushes the response cache, entirely or by key prefix.
+#[utoipa::path(
+    tag = "admin",
+    request_body(content = Option<CacheFlush>),
+    responses(
+        (status = 200, description = "What was flushed", body = Object),
+        (status = 500, description = "Prefix flush failed", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[post("/cache/flush")]
+pub async fn cache_flush(
+    cache: Data<SharedCache>,
+    body: Option<web::Json<CacheFlush>>,
+) -> impl Responder {
+    let prefix = body.and_then(|body| body.into_inner().prefix);
+    match prefix.as_deref() {
+        None | Some("") => {
+            flush_all(&cache).await;
+            HttpResponse::Ok().json(json!({ "flushed": "all" }))
+        }
+        Some(prefix) => match flush_prefix(&cache, prefix).await {
+            Ok(()) => HttpResponse::Ok().json(json!({ "flushed": { "prefix": prefix } })),
+            Err(e) => {
+                warn!("Failed to flush cache prefix '{}': {}", prefix, e);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "cache_flush_failed", e)
+                    .error_response()
+            }
+        },
+    }
+}
+
+/// Entry count, hit ratio, evictions and a memory estimate, optionally with
+/// a sample of cached keys (`?keys=20`).
+#[utoipa::path(
+    tag = "admin",
+    params(CacheStatsQuery),
+    responses((status = 200, description = "Cache statistics", body = Object)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/cache/stats")]
+pub async fn cache_stats(
+    cache: Data<SharedCache>,
+    query: web::Query<CacheStatsQuery>,
+) -> impl Responder {
+    HttpResponse::Ok().json(stats(&cache, query.keys).await)
+}
+
+/// Bucketed requests, errors and latency of one client over `window`.
+#[utoipa::path(
+    tag = "admin",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "Usage buckets", body = Object),
+        (status = 400, description = "Invalid window", body = Object),
+        (status = 404, description = "No usage recorded for the key", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/usage/timeseries")]
+pub async fn usage_timeseries(query: web::Query<UsageQuery>) -> impl Responder {
+    let window = query.window.as_deref().unwrap_or("1h");
+    let Some(window) = parse_window(window) else {
+        return ApiError::invalid_parameters(&["window"], format!("Invalid window '{}'", window))
+            .error_response();
+    };
+    match timeseries(&query.key, window) {
+        Some(series) => HttpResponse::Ok().json(series),
+        None => ApiError::not_found("unknown_key", "No usage recorded for key").error_response(),
+    }
+}
+
+/// Queries that returned nothing, with counts and first/last seen times.
+#[utoipa::path(
+    tag = "admin",
+    params(ZeroResultsQuery),
+    responses((status = 200, description = "Zero result queries", body = Object)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/analytics/zero_results")]
+pub async fn get_zero_results(query: web::Query<ZeroResultsQuery>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "tracked": zero_result_count(),
+        "queries": zero_results(query.limit.unwrap_or(100)),
+    }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 200, description = "Zero result log cleared", body = Object)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[delete("/analytics/zero_results")]
+pub async fn delete_zero_results() -> impl Responder {
+    clear_zero_results();
+    HttpResponse::Ok().json(json!({ "cleared": true }))
+}
+
+/// Uploaded delivery zones by name, see `query::zones`.
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 200, description = "Zones by name", body = Object)),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[get("/zones")]
+pub async fn list_zones() -> impl Responder {
+    let zones = zones::current();
+    let by_name: BTreeMap<&str, &Zone> = zones.iter().collect();
+    HttpResponse::Ok().json(json!({ "zones": by_name, "count": zones.len() }))
+}
+
+/// Adds or replaces a delivery zone for `/zone_lookup`, kept in
+/// `zones_path` when configured.
+#[utoipa::path(
+    tag = "admin",
+    params(("name", description = "Zone name, e.g. `delivery_zone_a`")),
+    request_body(
+        content = Object,
+        description = "`{\"postal_prefixes\": [\"1017\"], \"polygons\": [[[4.88, 52.36], ..]]}`, either or both"
+    ),
+    responses(
+        (status = 200, description = "The stored zone", body = Object),
+        (status = 400, description = "Invalid zone", body = Object),
+        (status = 500, description = "Zones could not be saved", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[put("/zones/{name}")]
+pub async fn put_zone(name: web::Path<String>, body: web::Json<Zone>) -> impl Responder {
+    let zone = match body.into_inner().validated() {
+        Ok(zone) => zone,
+        Err(e) => {
+            warn!("Rejected zone '{}': {}", name, e);
+            return ApiError::bad_request("invalid_zone", e).error_response();
+        }
+    };
+    let path = config::current().zones_path.clone();
+    match zones::put(path.as_deref(), &name, zone.clone()) {
+        Ok(()) => {
+            info!("Stored delivery zone '{}'", name);
+            HttpResponse::Ok().json(json!({ "name": name.as_str(), "zone": zone }))
+        }
+        Err(e) => {
+            warn!("Failed to save delivery zone '{}': {}", name, e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "zones_not_saved",
+                e.to_string(),
+            )
+            .error_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(("name", description = "Zone name")),
+    responses(
+        (status = 200, description = "Zone removed", body = Object),
+        (status = 404, description = "Unknown zone", body = Object),
+        (status = 500, description = "Zones could not be saved", body = Object)
+    ),
+    security(("bearer" = []), ("api_key" = []))
+)]
+#[delete("/zones/{name}")]
+pub async fn delete_zone(name: web::Path<String>) -> impl Responder {
+    let path = config::current().zones_path.clone();
+    match zones::remove(path.as_deref(), &name) {
+        Ok(true) => {
+            info!("Removed delivery zone '{}'", name);
+            HttpResponse::Ok().json(json!({ "removed": name.as_str() }))
+        }
+        Ok(false) => ApiError::not_found("unknown_zone", "Unknown zone").error_response(),
+        Err(e) => {
+            warn!("Failed to save delivery zones: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "zones_not_saved",
+                e.to_string(),
+            )
+            .error_response()
+        }
+    }
+}
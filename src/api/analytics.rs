@@ -0,0 +1,119 @@
+//! Zero-result query tracking
+//!
+//! Searches that come back empty are the main signal for missing data and
+//! normalization bugs. They are counted per normalized query (the same
+//! normalization as the cache key, without presentation-only parameters),
+//! kept in memory and served on `/admin/analytics/zero_results`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::config;
+use crate::query::normalize;
+
+/// Parameters that change how results are rendered, not which are found.
+const PRESENTATION_PARAMS: &[&str] = &[
+    "limit",
+    "mode",
+    "timings",
+    "coordinate_precision",
+    "snap_to_street",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZeroResult {
+    pub endpoint: String,
+    /// Normalized query parameters.
+    pub query: BTreeMap<String, String>,
+    pub count: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+lazy_static::lazy_static! {
+    static ref ZERO_RESULTS: Mutex<HashMap<String, ZeroResult>> = Mutex::new(HashMap::new());
+}
+
+/// Records an empty result for `endpoint` with the raw query `params`.
+pub fn record_zero_result(endpoint: &str, params: &HashMap<String, String>) {
+    let query: BTreeMap<String, String> = normalize::params(params)
+        .into_iter()
+        .filter(|(name, _)| !PRESENTATION_PARAMS.contains(&name.as_str()))
+        .collect();
+    let key = format!(
+        "{}:{}",
+        endpoint,
+        query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&")
+    );
+
+    let capacity = config::current().analytics.zero_results_capacity;
+    let now = Utc::now();
+    let mut zero_results = ZERO_RESULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(entry) = zero_results.get_mut(&key) {
+        entry.count += 1;
+        entry.last_seen = now;
+        return;
+    }
+    if capacity == 0 {
+        return;
+    }
+    if zero_results.len() >= capacity {
+        // forget the query not seen for the longest time
+        let stalest = zero_results
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_seen)
+            .map(|(key, _)| key.clone());
+        if let Some(stalest) = stalest {
+            zero_results.remove(&stalest);
+        }
+    }
+    zero_results.insert(
+        key,
+        ZeroResult {
+            endpoint: endpoint.to_string(),
+            query,
+            count: 1,
+            first_seen: now,
+            last_seen: now,
+        },
+    );
+}
+
+/// Tracked zero-result queries, most frequent first, at most `limit`.
+pub fn zero_results(limit: usize) -> Vec<ZeroResult> {
+    let zero_results = ZERO_RESULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut entries: Vec<ZeroResult> = zero_results.values().cloned().collect();
+    entries.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| b.last_seen.cmp(&a.last_seen))
+    });
+    entries.truncate(limit);
+    entries
+}
+
+/// Number of distinct zero-result queries tracked.
+pub fn zero_result_count() -> usize {
+    ZERO_RESULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .len()
+}
+
+/// Forgets all tracked queries, e.g. after the data was fixed.
+pub fn clear_zero_results() {
+    ZERO_RESULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clear();
+}
@@ -0,0 +1,128 @@
+//! Client IP resolution behind reverse proxies
+//!
+//! The TCP peer is only replaced by a forwarded address when the peer is one
+//! of the configured trusted proxies. The forwarded chain is walked from the
+//! right, skipping trusted hops, so a client cannot spoof its address by
+//! sending its own `X-Forwarded-For`. Everything that needs "the client"
+//! (rate limiting, analytics, logging) should go through [`ClientIp`].
+
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::http::header::HeaderMap;
+use actix_web::{FromRequest, HttpRequest};
+use ipnet::IpNet;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{ready, Ready};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ip) => write!(f, "{}", ip),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+impl ClientIp {
+    pub fn from_http_request(req: &HttpRequest) -> Self {
+        let trusted = &config::current().proxy.trusted_proxies;
+        Self(resolve_client_ip(
+            req.peer_addr().map(|addr| addr.ip()),
+            req.headers(),
+            trusted,
+        ))
+    }
+
+    pub fn from_service_request(req: &ServiceRequest) -> Self {
+        Self::from_http_request(req.request())
+    }
+}
+
+impl FromRequest for ClientIp {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Self::from_http_request(req)))
+    }
+}
+
+fn is_trusted(ip: &IpAddr, trusted: &[IpNet]) -> bool {
+    trusted.iter().any(|net| net.contains(ip))
+}
+
+/// Resolves the client address from the TCP peer and the `Forwarded` (RFC
+/// 7239) or `X-Forwarded-For` header. `Forwarded` wins when both are present.
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted: &[IpNet],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !is_trusted(&peer, trusted) {
+        return Some(peer);
+    }
+
+    let chain = forwarded_chain(headers);
+    if chain.is_empty() {
+        return Some(peer);
+    }
+
+    // right-most address that is not one of our proxies is the client
+    chain
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip, trusted))
+        .or_else(|| chain.first())
+        .copied()
+}
+
+/// Addresses from the forwarding headers, left (client) to right (closest
+/// proxy). Obfuscated or unparsable identifiers are dropped.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded: Vec<&str> = headers
+        .get_all("forwarded")
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| parse_node(value))
+                        .flatten()
+                })
+            })
+            .collect();
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+/// Parses `1.2.3.4`, `1.2.3.4:80`, `"[::1]:80"` and `::1`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|rest| rest.split(']').next())
+                .and_then(|ip| ip.parse().ok())
+        })
+}
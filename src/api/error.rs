@@ -0,0 +1,177 @@
+//! Structured error responses
+//!
+//! Every error leaves the server with a status that matches it and the body
+//! `{"error": {"code": "unknown_street", "message": "Unknown street",
+//! "details": {..}}}`. `code` is stable and meant for programs, `message`
+//! for people and may change; `details` is only present when there is
+//! something to add, e.g. the parameter at fault. Errors inside a
+//! successful response, per query of a batch or per line of a stream, use
+//! the same `error` object from [`ApiError::to_json`].
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::query::error::{OptionsError, QueryError};
+use crate::query::filter::FilterError;
+use crate::query::postal_code::PostalCodeError;
+
+#[derive(Debug, Clone, Error)]
+#[error("{message}")]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    /// Required parameters are missing or do not parse.
+    pub fn invalid_parameters(parameters: &[&str], message: impl Into<String>) -> Self {
+        Self::bad_request("invalid_parameter", message)
+            .with_details(json!({ "parameters": parameters }))
+    }
+
+    /// The query ran and found nothing.
+    pub fn no_match() -> Self {
+        Self::not_found("no_match", "No matching data found")
+    }
+
+    /// A body over a configured count limit.
+    pub fn too_large(message: impl Into<String>, limit: usize) -> Self {
+        Self::new(StatusCode::PAYLOAD_TOO_LARGE, "too_large", message)
+            .with_details(json!({ "limit": limit }))
+    }
+
+    /// The `{"error": ..}` object, for responses and stream lines alike.
+    pub fn to_json(&self) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(details) = &self.details {
+            error["details"] = details.clone();
+        }
+        json!({ "error": error })
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self.to_json())
+    }
+}
+
+impl From<QueryError> for ApiError {
+    fn from(e: QueryError) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, e.kind(), e.to_string())
+    }
+}
+
+impl From<FilterError> for ApiError {
+    fn from(e: FilterError) -> Self {
+        Self::bad_request("invalid_filter", format!("Invalid filter: {}", e.message))
+            .with_details(json!({ "position": e.position }))
+    }
+}
+
+impl From<OptionsError> for ApiError {
+    fn from(e: OptionsError) -> Self {
+        match e {
+            OptionsError::Filter(e) => e.into(),
+            OptionsError::InvalidParameter { name, value } => {
+                let message = format!("Unknown value '{}' for {}", value, name);
+                Self::invalid_parameters(&[name], message)
+                    .with_details(json!({ "parameters": [name], "value": value }))
+            }
+        }
+    }
+}
+
+impl From<PostalCodeError> for ApiError {
+    fn from(e: PostalCodeError) -> Self {
+        Self::bad_request("invalid_postal_code", format!("Invalid postal code: {}", e))
+            .with_details(json!({ "reason": e.kind() }))
+    }
+}
+
+/// Turns actix's extractor errors (a malformed JSON body or query string)
+/// into [`ApiError`]s, for `JsonConfig::error_handler` and friends.
+pub fn invalid_body<E: std::fmt::Display, R>(e: E, _req: &R) -> actix_web::Error {
+    ApiError::bad_request("invalid_body", e.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::filter::Filter;
+    use crate::query::postal_code;
+
+    #[test]
+    fn details_are_left_out_when_empty() {
+        let error = ApiError::no_match();
+        assert_eq!(
+            error.to_json(),
+            json!({ "error": { "code": "no_match", "message": "No matching data found" } })
+        );
+        let error = ApiError::invalid_parameters(&["q"], "q is required");
+        assert_eq!(
+            error.to_json()["error"]["details"]["parameters"],
+            json!(["q"])
+        );
+        assert_eq!(error.error_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn parameter_errors_have_their_own_codes() {
+        let filter = ApiError::from(Filter::parse("city = ").unwrap_err());
+        assert_eq!(filter.code, "invalid_filter");
+        assert!(filter.details.unwrap()["position"].is_u64());
+
+        let parameter = ApiError::from(OptionsError::InvalidParameter {
+            name: "collapse",
+            value: "sometimes".to_string(),
+        });
+        assert_eq!(parameter.code, "invalid_parameter");
+        assert_eq!(parameter.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            parameter.details,
+            Some(json!({ "parameters": ["collapse"], "value": "sometimes" }))
+        );
+
+        let postal_code = ApiError::from(postal_code::parse("0123AB").unwrap_err());
+        assert_eq!(postal_code.code, "invalid_postal_code");
+        assert_eq!(
+            postal_code.details,
+            Some(json!({ "reason": "leading_zero" }))
+        );
+
+        let query = ApiError::from(QueryError::Poisoned);
+        assert_eq!(query.code, "data_unavailable");
+        assert_eq!(query.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,132 @@
+//! Cache lifetime hints
+//!
+//! The data only changes when a new export is loaded, and exports come on
+//! a schedule, `freshness.refresh_interval_secs`. A search answer is good
+//! until the next one is due: the newest data file's modification time
+//! plus the interval. Search responses say so in `Cache-Control: max-age`
+//! and `Expires`, and JSON bodies in a `cache` object with `max_age`
+//! (seconds) and `expires_at` (RFC 3339), for SDKs that keep results
+//! without their headers. Once the refresh is overdue the lifetime drops
+//! to `overdue_max_age_secs`, so clients pick up the new data soon after
+//! it lands. Responses to authenticated requests are `private`, so shared
+//! caches and CDNs do not hand them to other clients.
+
+use actix_web::http::header::{self, HttpDate};
+use actix_web::HttpResponseBuilder;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::time::SystemTime;
+
+use crate::config::{self, FreshnessConfig};
+use crate::query::dataset_modified_at;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freshness {
+    pub max_age_secs: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Freshness {
+    /// The hints for data last modified at `modified_at`, `None` without a
+    /// refresh interval or a modification time.
+    pub fn at(
+        modified_at: Option<DateTime<Utc>>,
+        settings: &FreshnessConfig,
+        now: DateTime<Utc>,
+    ) -> Option<Self> {
+        let interval = i64::try_from(settings.refresh_interval_secs?).ok()?;
+        let due = modified_at? + Duration::seconds(interval);
+        let max_age_secs = match (due - now).num_seconds() {
+            secs if secs > 0 => secs as u64,
+            _ => settings.overdue_max_age_secs,
+        };
+        let expires_at = now + Duration::seconds(max_age_secs as i64);
+        Some(Self {
+            max_age_secs,
+            expires_at,
+        })
+    }
+
+    /// The `cache` object of JSON bodies.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "max_age": self.max_age_secs,
+            "expires_at": self.expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+    }
+
+    /// Sets `Cache-Control` and `Expires` on `response`, `private` ones
+    /// only the client itself may keep.
+    pub fn apply(&self, response: &mut HttpResponseBuilder, private: bool) {
+        let scope = if private { "private" } else { "public" };
+        response
+            .insert_header((
+                header::CACHE_CONTROL,
+                format!("{}, max-age={}", scope, self.max_age_secs),
+            ))
+            .insert_header((
+                header::EXPIRES,
+                HttpDate::from(SystemTime::from(self.expires_at)).to_string(),
+            ));
+    }
+}
+
+/// The hints for the loaded data right now, none while the data cannot be
+/// read.
+pub fn current() -> Option<Freshness> {
+    Freshness::at(
+        dataset_modified_at().ok()?,
+        &config::current().freshness,
+        Utc::now(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifetime_runs_until_the_next_refresh() {
+        let now = Utc::now();
+        let modified = Some(now - Duration::hours(2));
+        let settings = FreshnessConfig {
+            refresh_interval_secs: Some(3 * 3600),
+            overdue_max_age_secs: 300,
+        };
+
+        let fresh = Freshness::at(modified, &settings, now).unwrap();
+        assert_eq!(fresh.max_age_secs, 3600);
+        assert_eq!(fresh.expires_at, now + Duration::hours(1));
+
+        let overdue = FreshnessConfig {
+            refresh_interval_secs: Some(3600),
+            ..settings.clone()
+        };
+        assert_eq!(
+            Freshness::at(modified, &overdue, now).unwrap().max_age_secs,
+            300
+        );
+        assert_eq!(Freshness::at(None, &settings, now), None);
+        assert_eq!(
+            Freshness::at(modified, &FreshnessConfig::default(), now),
+            None
+        );
+    }
+
+    #[test]
+    fn authenticated_responses_are_private() {
+        let freshness = Freshness {
+            max_age_secs: 60,
+            expires_at: Utc::now(),
+        };
+        for (private, expected) in [(false, "public, max-age=60"), (true, "private, max-age=60")] {
+            let mut response = actix_web::HttpResponse::Ok();
+            freshness.apply(&mut response, private);
+            let response = response.finish();
+            assert_eq!(
+                response.headers().get(header::CACHE_CONTROL).unwrap(),
+                expected
+            );
+        }
+    }
+}
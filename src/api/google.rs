@@ -0,0 +1,300 @@
+//! Google Places Autocomplete compatibility
+//!
+//! `format=google` reshapes a search response into the Places Autocomplete
+//! schema: `{"predictions": [..], "status": "OK"}` with a `description`,
+//! `place_id`, `structured_formatting`, `terms`, `matched_substrings` and
+//! `types` per prediction. Errors become the matching `status` with an
+//! `error_message` and, like Google, a 200. `/place/autocomplete/json`
+//! answers Google's autocomplete path itself, taking `input` for `q` and
+//! the API key from `key`, so a frontend built against
+//! `https://maps.googleapis.com/maps/api` only changes its base URL.
+//! `components`, `language`, `sessiontoken` and other Google parameters
+//! are ignored.
+//!
+//! `place_id` is the address `id` (`1017GE-12`) when the request asked for
+//! it with `fields=id`, the address text otherwise, or `city:<name>` for
+//! the place predictions of the `city` section.
+
+use actix_web::body::{to_bytes, BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::api::negotiate::collect_entries;
+use crate::api::version::unversioned;
+
+/// Google's autocomplete path, relative to its `/maps/api` base.
+pub const AUTOCOMPLETE_PATH: &str = "/place/autocomplete/json";
+
+/// Whether the request asked for the Google schema, by `format=google` or
+/// by its path.
+pub fn is_requested(req: &ServiceRequest) -> bool {
+    unversioned(req.path()) == AUTOCOMPLETE_PATH
+        || req
+            .query_string()
+            .split('&')
+            .any(|pair| pair.eq_ignore_ascii_case("format=google"))
+}
+
+/// Query parameter Google clients send their API key in.
+pub const KEY_PARAM: &str = "key";
+
+/// The `key` parameter of a request to [`AUTOCOMPLETE_PATH`], which Google
+/// clients send in place of an `X-Api-Key` header.
+pub fn query_key(req: &ServiceRequest) -> Option<String> {
+    if unversioned(req.path()) != AUTOCOMPLETE_PATH {
+        return None;
+    }
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()?
+        .into_inner()
+        .remove(KEY_PARAM)
+}
+
+/// `query` with the value of any `key` parameter masked, for logging.
+pub fn redact_key(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if name.eq_ignore_ascii_case(KEY_PARAM) => {
+                format!("{}=REDACTED", name)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// `(offset, length)` in characters of each word of `input` found in
+/// `text`, in order of offset, ignoring case.
+fn matched_substrings(text: &str, input: &str) -> Vec<Value> {
+    let haystack = text.to_lowercase();
+    let mut found: Vec<(usize, usize)> = input
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .filter_map(|word| {
+            let offset = haystack.find(word)?;
+            Some((haystack[..offset].chars().count(), word.chars().count()))
+        })
+        .collect();
+    found.sort_unstable();
+    found.dedup_by_key(|(offset, _)| *offset);
+    found
+        .into_iter()
+        .map(|(offset, length)| json!({ "offset": offset, "length": length }))
+        .collect()
+}
+
+fn prediction(
+    place_id: String,
+    main_text: String,
+    secondary_text: String,
+    types: &[&str],
+    input: &str,
+) -> Value {
+    let description = [main_text.as_str(), secondary_text.as_str()]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut offset = 0;
+    let terms: Vec<Value> = description
+        .split(", ")
+        .map(|value| {
+            let term = json!({ "offset": offset, "value": value });
+            offset += value.chars().count() + 2;
+            term
+        })
+        .collect();
+    json!({
+        "description": description,
+        "place_id": place_id,
+        "reference": place_id,
+        "matched_substrings": matched_substrings(&description, input),
+        "structured_formatting": {
+            "main_text": main_text,
+            "main_text_matched_substrings": matched_substrings(&main_text, input),
+            "secondary_text": secondary_text,
+        },
+        "terms": terms,
+        "types": types,
+    })
+}
+
+fn text<'a>(entry: &'a Map<String, Value>, field: &str) -> &'a str {
+    entry.get(field).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// One prediction per address of `envelope`, and one per city for entries
+/// of the `city` section.
+pub fn to_predictions(envelope: &Value, input: &str) -> Value {
+    let mut seen = HashSet::new();
+    let mut predictions = Vec::new();
+    for entry in collect_entries(envelope) {
+        let (street, house_number, city) = (
+            text(&entry, "street"),
+            text(&entry, "house_number"),
+            text(&entry, "city"),
+        );
+        let prediction = if text(&entry, "source") == "city" {
+            if !seen.insert(format!("city:{}", city)) {
+                continue;
+            }
+            let province = text(&entry, "province").to_string();
+            prediction(
+                format!("city:{}", city),
+                city.to_string(),
+                province,
+                &["locality", "political", "geocode"],
+                input,
+            )
+        } else {
+            let secondary_text = [text(&entry, "postal_code"), city]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            // without `fields=id` the address itself tells predictions apart
+            let id = match text(&entry, "id") {
+                "" => [street, house_number, &secondary_text].join(" "),
+                id => id.to_string(),
+            };
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let (main_text, types) = match house_number {
+                "" => (street.to_string(), ["route", "geocode"]),
+                _ => (
+                    format!("{} {}", street, house_number),
+                    ["street_address", "geocode"],
+                ),
+            };
+            prediction(id, main_text, secondary_text, &types, input)
+        };
+        predictions.push(prediction);
+    }
+    let status = if predictions.is_empty() {
+        "ZERO_RESULTS"
+    } else {
+        "OK"
+    };
+    json!({ "predictions": predictions, "status": status })
+}
+
+/// The Google `status` for an error response.
+fn status_of(status: StatusCode, code: &str) -> &'static str {
+    match (status, code) {
+        (_, "no_match") => "ZERO_RESULTS",
+        (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, _) => "REQUEST_DENIED",
+        (StatusCode::TOO_MANY_REQUESTS, _) => "OVER_QUERY_LIMIT",
+        (status, _) if status.is_client_error() => "INVALID_REQUEST",
+        _ => "UNKNOWN_ERROR",
+    }
+}
+
+/// Middleware for the public scopes, wrapped outside the group's middleware chain so
+/// auth and rate limit errors are reshaped too. Requests that did not ask
+/// for the Google schema, and streamed or non-JSON bodies, pass through.
+pub async fn handle<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    if !is_requested(&req) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json || res.response().body().size() == BodySize::Stream {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        let response = HttpResponse::InternalServerError().finish();
+        return Ok(ServiceResponse::new(req, response).map_into_right_body());
+    };
+    let Ok(body) = serde_json::from_slice::<Value>(&body) else {
+        let res = res.set_body(BoxBody::new(body));
+        return Ok(ServiceResponse::new(req, res).map_into_right_body());
+    };
+    let mapped = if res.status().is_success() {
+        let query = web::Query::<HashMap<String, String>>::from_query(req.query_string());
+        let input = query
+            .ok()
+            .and_then(|query| {
+                let mut query = query.into_inner();
+                query.remove("input").or_else(|| query.remove("q"))
+            })
+            .unwrap_or_default();
+        to_predictions(&body, &input)
+    } else {
+        let error = &body["error"];
+        let status = status_of(res.status(), error["code"].as_str().unwrap_or_default());
+        let message = error["message"].as_str().unwrap_or_default();
+        *res.status_mut() = StatusCode::OK;
+        match status {
+            "ZERO_RESULTS" => json!({ "predictions": [], "status": status }),
+            _ => json!({ "predictions": [], "status": status, "error_message": message }),
+        }
+    };
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    let res = res.set_body(BoxBody::new(mapped.to_string()));
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_redacted_from_queries() {
+        assert_eq!(
+            redact_key("input=kerkstraat&key=secret-1&language=nl"),
+            "input=kerkstraat&key=REDACTED&language=nl"
+        );
+        assert_eq!(redact_key("q=keyzer&monkey=1"), "q=keyzer&monkey=1");
+    }
+
+    #[test]
+    fn addresses_become_predictions() {
+        let envelope = json!({
+            "street": {
+                "entries": [{
+                    "id": "1017GE-12",
+                    "street": "Kerkstraat",
+                    "house_number": "12",
+                    "postal_code": "1017GE",
+                    "city": "Amsterdam"
+                }]
+            }
+        });
+        let response = to_predictions(&envelope, "kerkstr 12");
+        assert_eq!(response["status"], "OK");
+        let prediction = &response["predictions"][0];
+        assert_eq!(prediction["description"], "Kerkstraat 12, 1017GE Amsterdam");
+        assert_eq!(prediction["place_id"], "1017GE-12");
+        assert_eq!(
+            prediction["structured_formatting"]["secondary_text"],
+            "1017GE Amsterdam"
+        );
+        assert_eq!(
+            prediction["matched_substrings"],
+            json!([{ "offset": 0, "length": 7 }, { "offset": 11, "length": 2 }])
+        );
+        assert_eq!(
+            prediction["terms"][1],
+            json!({ "offset": 15, "value": "1017GE Amsterdam" })
+        );
+        assert_eq!(to_predictions(&json!({}), "x")["status"], "ZERO_RESULTS");
+    }
+}
@@ -0,0 +1,131 @@
+//! Readiness and dataset statistics. `/readyz` is mounted outside the
+//! route groups so probes need no token.
+
+use actix_web::{get, HttpResponse, Responder, ResponseError};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::api::error::ApiError;
+use crate::config;
+use crate::query::canary::{self, CanaryResult};
+use crate::query::dataset_summary;
+use crate::query::error::QueryError;
+
+/// How old the loaded data is against `health.max_data_age_secs`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DataAge {
+    /// Newest modification time of the loaded files.
+    pub modified_at: Option<DateTime<Utc>>,
+    pub age_secs: Option<i64>,
+    pub max_age_secs: Option<u64>,
+    /// Older than `max_age_secs`, usually a refresh job that stopped.
+    pub degraded: bool,
+}
+
+impl DataAge {
+    pub fn at(
+        modified_at: Option<DateTime<Utc>>,
+        max_age_secs: Option<u64>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let age_secs = modified_at.map(|modified| (now - modified).num_seconds().max(0));
+        Self {
+            modified_at,
+            age_secs,
+            max_age_secs,
+            degraded: matches!((age_secs, max_age_secs), (Some(age), Some(max)) if age as u64 > max),
+        }
+    }
+}
+
+/// `ready`, or why not: failing canaries first, then stale data.
+fn status(age: &DataAge, canaries: &[CanaryResult]) -> &'static str {
+    if canaries.iter().any(|canary| !canary.passed) {
+        "canary_failed"
+    } else if age.degraded {
+        "degraded"
+    } else {
+        "ready"
+    }
+}
+
+/// Age of the currently loaded data.
+pub fn data_age() -> Result<DataAge, QueryError> {
+    Ok(DataAge::at(
+        dataset_summary()?.modified_at,
+        config::current().health.max_data_age_secs,
+        Utc::now(),
+    ))
+}
+
+/// Readiness with the age of the loaded data and the canary results. 503
+/// while a canary fails or the data is older than
+/// `health.max_data_age_secs`.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready", body = Object),
+        (status = 503, description = "Canary failed or data older than its max age", body = Object)
+    ),
+    security(())
+)]
+#[get("/readyz")]
+pub async fn readyz() -> impl Responder {
+    let age = match data_age() {
+        Ok(age) => age,
+        Err(e) => return ApiError::from(e).error_response(),
+    };
+    let canaries = canary::results();
+    let status = status(&age, &canaries);
+    let body = json!({ "status": status, "data_age": age, "canaries": canaries });
+    if status != "ready" {
+        HttpResponse::ServiceUnavailable().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+/// Dataset numbers, data age and canary results. 200 unless the data
+/// cannot be read, `status` is that of `/readyz`.
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Dataset statistics", body = Object),
+        (status = 503, description = "Data unavailable", body = Object)
+    )
+)]
+#[get("/stats")]
+pub async fn stats() -> impl Responder {
+    let (age, dataset) = match data_age().and_then(|age| Ok((age, dataset_summary()?))) {
+        Ok(found) => found,
+        Err(e) => return ApiError::from(e).error_response(),
+    };
+    let canaries = canary::results();
+    HttpResponse::Ok().json(json!({
+        "status": status(&age, &canaries),
+        "data_age": age,
+        "canaries": canaries,
+        "dataset": dataset,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn degraded_past_max_age() {
+        let now = Utc::now();
+        let modified = Some(now - Duration::hours(2));
+
+        let fresh = DataAge::at(modified, Some(3 * 3600), now);
+        assert_eq!(fresh.age_secs, Some(7200));
+        assert!(!fresh.degraded);
+
+        assert!(DataAge::at(modified, Some(3600), now).degraded);
+        assert!(!DataAge::at(modified, None, now).degraded);
+        assert!(!DataAge::at(None, Some(3600), now).degraded);
+    }
+}
@@ -0,0 +1,31 @@
+//! One log line per request under the `access_log` target, so it can be
+//! filtered separately, e.g. `info,access_log=off`. A `key` query parameter
+//! is logged as `REDACTED`.
+
+use actix_web::dev::ServiceResponse;
+use std::time::Instant;
+use tracing::info;
+
+use crate::api::client_ip::ClientIp;
+use crate::api::google;
+use crate::api::middleware::auth::ApiKeyName;
+use crate::api::middleware::request_id::RequestId;
+
+pub fn log<B>(res: &ServiceResponse<B>, started: Instant, request_id: Option<&RequestId>) {
+    let req = res.request();
+    let target = match req.query_string() {
+        "" => req.path().to_string(),
+        query => format!("{}?{}", req.path(), google::redact_key(query)),
+    };
+    info!(
+        target: "access_log",
+        "{} {} {} {} ms client={} key={} request_id={}",
+        req.method(),
+        target,
+        res.status().as_u16(),
+        started.elapsed().as_millis(),
+        ClientIp::from_http_request(req),
+        ApiKeyName::of(req).as_deref().unwrap_or("-"),
+        request_id.map_or("-", |id| id.0.as_str()),
+    );
+}
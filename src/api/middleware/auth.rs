@@ -0,0 +1,175 @@
+//! Bearer token and API key auth per route group.
+
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName};
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpRequest, ResponseError};
+use tracing::warn;
+
+use crate::api::client_ip::ClientIp;
+use crate::api::error::ApiError;
+use crate::api::google;
+use crate::config::AuthConfig;
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Name of the API key a request was let in with, kept in the request
+/// extensions for the access log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyName(pub String);
+
+impl ApiKeyName {
+    pub fn of(req: &HttpRequest) -> Option<String> {
+        req.extensions()
+            .get::<ApiKeyName>()
+            .map(|name| name.0.clone())
+    }
+}
+
+/// Marks a request let in with a valid token or key, kept in the request
+/// extensions so its responses are not left to shared caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Authenticated;
+
+impl Authenticated {
+    pub fn is(req: &HttpRequest) -> bool {
+        req.extensions().contains::<Authenticated>()
+    }
+}
+
+/// Why a request was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// The group requires auth but has no tokens or keys configured.
+    NotConfigured,
+    /// No valid token or key.
+    Unauthorized,
+}
+
+impl Denied {
+    pub fn to_api_error(self) -> ApiError {
+        match self {
+            Denied::NotConfigured => ApiError::new(
+                StatusCode::FORBIDDEN,
+                "auth_not_configured",
+                "No credentials are configured for these routes",
+            ),
+            Denied::Unauthorized => {
+                ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized")
+            }
+        }
+    }
+}
+
+/// Checks a bearer token or API key against `auth`. `Ok` holds the name
+/// of the key the caller was let in with, `None` for a token or an open
+/// group. A group that `required` auth but has none configured lets
+/// nobody in.
+pub fn authorize(
+    auth: &AuthConfig,
+    required: bool,
+    token: Option<&str>,
+    key: Option<&str>,
+) -> Result<Option<String>, Denied> {
+    if auth.is_open() {
+        return if required {
+            Err(Denied::NotConfigured)
+        } else {
+            Ok(None)
+        };
+    }
+    if token.is_some_and(|token| auth.allows_token(token)) {
+        return Ok(None);
+    }
+    match key.and_then(|key| auth.api_key_name(key)) {
+        Some(name) => Ok(Some(name.to_string())),
+        None => Err(Denied::Unauthorized),
+    }
+}
+
+/// Passes the request through when it carries a valid
+/// `Authorization: Bearer <token>` or `X-Api-Key` header (or `key`
+/// parameter on the Google compatible path) or the group has neither
+/// configured and does not require auth. Otherwise answers 401, or 403
+/// for a group that requires auth but has no credentials configured.
+#[allow(clippy::result_large_err)]
+pub fn check(
+    auth: &AuthConfig,
+    required: bool,
+    req: ServiceRequest,
+) -> Result<ServiceRequest, ServiceResponse> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| google::query_key(&req));
+
+    let denied = match authorize(auth, required, token.as_deref(), key.as_deref()) {
+        Ok(name) => {
+            if let Some(name) = name {
+                req.extensions_mut().insert(ApiKeyName(name));
+            }
+            if !auth.is_open() {
+                req.extensions_mut().insert(Authenticated);
+            }
+            return Ok(req);
+        }
+        Err(denied) => denied,
+    };
+
+    warn!(
+        "Rejected unauthenticated request to {} from {}",
+        req.path(),
+        ClientIp::from_service_request(&req)
+    );
+    let response = denied.to_api_error().error_response();
+    Err(req.into_response(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> AuthConfig {
+        AuthConfig {
+            bearer_tokens: vec!["token".to_string()],
+            api_keys: [("acme".to_string(), "secret".to_string())].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn required_auth_without_credentials_lets_nobody_in() {
+        let open = AuthConfig::default();
+        assert_eq!(authorize(&open, false, None, None), Ok(None));
+        assert_eq!(
+            authorize(&open, true, Some("token"), Some("secret")),
+            Err(Denied::NotConfigured)
+        );
+    }
+
+    #[test]
+    fn tokens_and_keys_are_checked() {
+        let auth = auth();
+        assert_eq!(authorize(&auth, true, Some("token"), None), Ok(None));
+        assert_eq!(
+            authorize(&auth, true, None, Some("secret")),
+            Ok(Some("acme".to_string()))
+        );
+        assert_eq!(
+            authorize(&auth, false, Some("tokens"), Some("secre")),
+            Err(Denied::Unauthorized)
+        );
+        assert_eq!(
+            authorize(&auth, false, None, None),
+            Err(Denied::Unauthorized)
+        );
+    }
+}
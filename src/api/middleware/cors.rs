@@ -0,0 +1,18 @@
+//! CORS per route group.
+
+use actix_cors::Cors;
+
+use crate::api::middleware::RouteGroup;
+
+/// CORS for a route group. Origins are checked per request against the live
+/// config.
+pub fn cors_for(group: RouteGroup) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| group.config().cors.allows(origin))
+        })
+        .allow_any_method()
+        .allow_any_header()
+}
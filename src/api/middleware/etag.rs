@@ -0,0 +1,181 @@
+//! `ETag` and conditional `GET`
+//!
+//! The data only changes on a reload, so the same request keeps getting the
+//! same bytes back. Successful `GET` and `HEAD` responses carry an `ETag`
+//! made of the dataset version and a hash of the body, and a
+//! `Last-Modified` from the newest data file. A request whose
+//! `If-None-Match` lists the current tag gets an empty 304 instead, which
+//! lets browsers and CDNs revalidate cached autocomplete results cheaply.
+//! The 304 keeps the response's `Cache-Control` and `Expires`, so the
+//! revalidated copy gets a fresh lifetime.
+//! Hashing the body rather than the request keeps the tag honest across
+//! config reloads (fields, presets, coordinate policy). Streamed bodies
+//! (`format=ndjson`) are left untagged rather than buffered.
+
+use actix_web::body::{to_bytes, BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue, HttpDate};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+
+use crate::query::{dataset_modified_at, dataset_version};
+
+/// Whether `req` is one the tags apply to.
+pub fn applies(req: &ServiceRequest) -> bool {
+    matches!(*req.method(), Method::GET | Method::HEAD)
+}
+
+/// `None` when the dataset version cannot be read.
+fn etag(body: &[u8]) -> Option<String> {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let version = dataset_version().ok()?;
+    Some(format!("\"{}-{:016x}\"", version, hasher.finish()))
+}
+
+/// Whether an `If-None-Match` value lists `etag`, compared weakly as
+/// RFC 9110 asks for this header.
+fn none_match(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Tags the response to `req` and answers 304 when the client already has
+/// it. Error and streamed responses pass through untouched, and bodies go
+/// out untagged while the dataset version cannot be read.
+pub async fn call<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let res = next.call(req).await?;
+    if res.status() != StatusCode::OK || res.response().body().size() == BodySize::Stream {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        let response = HttpResponse::InternalServerError().finish();
+        return Ok(ServiceResponse::new(req, response).map_into_right_body());
+    };
+
+    let Some(etag) = etag(&body) else {
+        let res = res.set_body(BoxBody::new(body));
+        return Ok(ServiceResponse::new(req, res).map_into_right_body());
+    };
+    let last_modified = dataset_modified_at()
+        .ok()
+        .flatten()
+        .map(|modified| HttpDate::from(SystemTime::from(modified)).to_string());
+    let not_modified = if_none_match.is_some_and(|value| none_match(&value, &etag));
+
+    let mut validators = vec![(header::ETAG, etag)];
+    validators.extend(last_modified.map(|date| (header::LAST_MODIFIED, date)));
+    let res = if not_modified {
+        let mut response = HttpResponse::NotModified();
+        for (name, value) in validators {
+            response.insert_header((name, value));
+        }
+        for name in [header::CACHE_CONTROL, header::EXPIRES] {
+            if let Some(value) = res.headers().get(&name) {
+                response.insert_header((name, value.clone()));
+            }
+        }
+        response.finish()
+    } else {
+        for (name, value) in validators {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                res.headers_mut().insert(name, value);
+            }
+        }
+        res.set_body(BoxBody::new(body))
+    };
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+
+    #[test]
+    fn if_none_match_compares_weakly() {
+        assert!(none_match("\"v1-00ff\"", "\"v1-00ff\""));
+        assert!(none_match("\"other\", W/\"v1-00ff\"", "\"v1-00ff\""));
+        assert!(none_match("*", "\"v1-00ff\""));
+        assert!(!none_match("\"v2-00ff\"", "\"v1-00ff\""));
+    }
+
+    #[test]
+    fn only_reads_are_tagged() {
+        assert!(applies(&TestRequest::get().to_srv_request()));
+        assert!(applies(
+            &TestRequest::default().method(Method::HEAD).to_srv_request()
+        ));
+        assert!(!applies(&TestRequest::post().to_srv_request()));
+    }
+
+    #[actix_web::test]
+    async fn matching_tags_get_an_empty_304() {
+        // the tag includes the dataset version, which other tests install
+        fixtures::install().unwrap();
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(call))
+                .route(
+                    "/search",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .insert_header((header::CACHE_CONTROL, "private, max-age=60"))
+                            .body("Kerkstraat 12")
+                    }),
+                )
+                .route("/missing", web::get().to(HttpResponse::NotFound)),
+        )
+        .await;
+
+        let res = call_service(&app, TestRequest::get().uri("/search").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+
+        let req = TestRequest::get()
+            .uri("/search")
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), &etag);
+        assert_eq!(
+            res.headers().get(header::CACHE_CONTROL).unwrap(),
+            "private, max-age=60"
+        );
+        assert!(read_body(res).await.is_empty());
+
+        let req = TestRequest::get()
+            .uri("/search")
+            .insert_header((header::IF_NONE_MATCH, "\"stale\""))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "Kerkstraat 12");
+
+        let res = call_service(&app, TestRequest::get().uri("/missing").to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+}
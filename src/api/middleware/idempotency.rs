@@ -0,0 +1,457 @@
+//! `Idempotency-Key` handling for write requests
+//!
+//! A client retrying a `POST`, `PUT`, `PATCH` or `DELETE` after a timeout
+//! cannot tell whether the first attempt went through. When the request
+//! carries an `Idempotency-Key` header, the first response for that key is
+//! stored and every retry with the same key gets the stored response back
+//! (marked with `Idempotent-Replayed: true`) instead of running the handler
+//! again.
+//!
+//! Keys are scoped per caller: the API key the request was let in with,
+//! else its `Authorization` header, else the client IP, so two callers
+//! cannot see each other's responses. A key reused for a different request
+//! (method, path, query or body) is rejected with 422, and a retry that
+//! arrives while the first attempt is still running gets 409. 5xx
+//! responses are not stored, and neither is anything when the first
+//! attempt is dropped (client gone, server timeout), so those can be
+//! retried for real. Stored responses, and attempts that never finished,
+//! are kept in memory for `idempotency.ttl_secs` and start empty after a
+//! restart.
+
+use actix_web::body::{to_bytes, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage, HttpResponse, ResponseError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::api::client_ip::ClientIp;
+use crate::api::error::ApiError;
+use crate::api::middleware::auth::ApiKeyName;
+use crate::config;
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses served from the store.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest accepted key.
+const MAX_KEY_LEN: usize = 255;
+
+#[derive(Debug, Clone)]
+enum Slot {
+    InFlight {
+        fingerprint: u64,
+        started_at: Instant,
+    },
+    Done {
+        fingerprint: u64,
+        stored_at: Instant,
+        status: StatusCode,
+        content_type: Option<HeaderValue>,
+        body: Bytes,
+    },
+}
+
+impl Slot {
+    fn fingerprint(&self) -> u64 {
+        match self {
+            Slot::InFlight { fingerprint, .. } | Slot::Done { fingerprint, .. } => *fingerprint,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SLOTS: Mutex<HashMap<String, Slot>> = Mutex::new(HashMap::new());
+}
+
+fn hash<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn error(status: StatusCode, code: &'static str, message: &str) -> HttpResponse {
+    ApiError::new(status, code, message).error_response()
+}
+
+/// Drops expired responses and attempts running for longer than `ttl` and,
+/// when still over `max_entries`, the oldest stored responses.
+fn evict(slots: &mut HashMap<String, Slot>, ttl: Duration, max_entries: usize) {
+    slots.retain(|_, slot| match slot {
+        Slot::Done { stored_at, .. } => stored_at.elapsed() < ttl,
+        Slot::InFlight { started_at, .. } => started_at.elapsed() < ttl,
+    });
+    if slots.len() < max_entries {
+        return;
+    }
+    let mut done: Vec<(Instant, String)> = slots
+        .iter()
+        .filter_map(|(key, slot)| match slot {
+            Slot::Done { stored_at, .. } => Some((*stored_at, key.clone())),
+            Slot::InFlight { .. } => None,
+        })
+        .collect();
+    done.sort();
+    let excess = slots.len() + 1 - max_entries;
+    for (_, key) in done.into_iter().take(excess) {
+        slots.remove(&key);
+    }
+}
+
+/// Whose keys `req` shares: its API key's name, else a hash of its
+/// `Authorization` header, else its client IP.
+fn scope(req: &ServiceRequest) -> String {
+    if let Some(name) = req.extensions().get::<ApiKeyName>() {
+        return format!("key:{}", name.0);
+    }
+    match req.headers().get(header::AUTHORIZATION) {
+        Some(authorization) => format!("auth:{:016x}", hash(authorization.as_bytes())),
+        None => format!("ip:{}", ClientIp::from_service_request(req)),
+    }
+}
+
+/// The in-flight slot of one attempt. Dropping it frees the key for a
+/// retry, unless [`Attempt::finish`] stored the response first; the
+/// handler's future being dropped drops it too.
+struct Attempt {
+    slot_key: String,
+    started_at: Instant,
+    finished: bool,
+}
+
+impl Attempt {
+    fn finish(mut self, slot: Slot) {
+        SLOTS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(std::mem::take(&mut self.slot_key), slot);
+        self.finished = true;
+    }
+}
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let mut slots = SLOTS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // evicted as stale and taken by a retry in the meantime
+        if matches!(
+            slots.get(&self.slot_key),
+            Some(Slot::InFlight { started_at, .. }) if *started_at == self.started_at
+        ) {
+            slots.remove(&self.slot_key);
+        }
+    }
+}
+
+/// Runs `next` at most once per idempotency key. Requests without the
+/// header and safe methods pass straight through.
+pub async fn call<B: MessageBody>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| value.to_str().map(str::to_string));
+    let key = match key {
+        Some(key) if !safe => key,
+        _ => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    let key = match key {
+        Ok(key)
+            if !key.is_empty()
+                && key.len() <= MAX_KEY_LEN
+                && key.bytes().all(|b| b.is_ascii_graphic()) =>
+        {
+            key
+        }
+        _ => {
+            let response = error(
+                StatusCode::BAD_REQUEST,
+                "invalid_idempotency_key",
+                "Idempotency-Key must be 1 to 255 visible ASCII characters",
+            );
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    // the body is part of the fingerprint, put it back for the handler
+    let body = req.extract::<Bytes>().await?;
+    let fingerprint = hash((
+        req.method().as_str(),
+        req.path(),
+        req.query_string(),
+        &body[..],
+    ));
+    req.set_payload(Payload::from(body));
+
+    let slot_key = format!("{:016x}:{}", hash(scope(&req)), key);
+
+    let settings = config::current().idempotency.clone();
+    let attempt = {
+        let mut slots = SLOTS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        evict(
+            &mut slots,
+            Duration::from_secs(settings.ttl_secs),
+            settings.max_entries,
+        );
+        match slots.get(&slot_key) {
+            Some(slot) if slot.fingerprint() != fingerprint => {
+                warn!("Idempotency-Key {} reused for a different request", key);
+                let response = error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "idempotency_key_reused",
+                    "Idempotency-Key was already used for a different request",
+                );
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            Some(Slot::InFlight { .. }) => {
+                let response = error(
+                    StatusCode::CONFLICT,
+                    "idempotency_key_in_progress",
+                    "A request with this Idempotency-Key is still in progress",
+                );
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            Some(Slot::Done {
+                status,
+                content_type,
+                body,
+                ..
+            }) => {
+                info!("Replaying stored response for Idempotency-Key {}", key);
+                let mut response = HttpResponse::build(*status);
+                if let Some(content_type) = content_type {
+                    response.insert_header((header::CONTENT_TYPE, content_type.clone()));
+                }
+                response.insert_header((IDEMPOTENT_REPLAYED_HEADER, "true"));
+                let response = response.body(body.clone());
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            None => {}
+        }
+        let started_at = Instant::now();
+        slots.insert(
+            slot_key.clone(),
+            Slot::InFlight {
+                fingerprint,
+                started_at,
+            },
+        );
+        Attempt {
+            slot_key,
+            started_at,
+            finished: false,
+        }
+    };
+
+    let res = next.call(req).await?;
+    if res.status().is_server_error() {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        let response = error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Failed to read response",
+        );
+        return Ok(ServiceResponse::new(req, response).map_into_right_body());
+    };
+    attempt.finish(Slot::Done {
+        fingerprint,
+        stored_at: Instant::now(),
+        status: res.status(),
+        content_type: res.headers().get(header::CONTENT_TYPE).cloned(),
+        body: body.clone(),
+    });
+    let res = res.set_body(BoxBody::new(body));
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::rt::time::timeout;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    static HANG: AtomicBool = AtomicBool::new(true);
+
+    async fn create(body: Bytes) -> HttpResponse {
+        let call = CALLS.fetch_add(1, Ordering::SeqCst);
+        if &body[..] == b"fail" {
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+        HttpResponse::Created().body(format!("call {}", call))
+    }
+
+    /// Never answers the first time it is called.
+    async fn slow() -> HttpResponse {
+        if HANG.swap(false, Ordering::SeqCst) {
+            std::future::pending::<()>().await;
+        }
+        HttpResponse::Created().finish()
+    }
+
+    fn post(key: &str, body: &'static str) -> TestRequest {
+        post_to("/items", key, body)
+    }
+
+    fn post_to(path: &str, key: &str, body: &'static str) -> TestRequest {
+        TestRequest::post()
+            .uri(path)
+            .insert_header((IDEMPOTENCY_KEY_HEADER, key))
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn retries_replay_the_stored_response() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(call))
+                .route("/items", web::post().to(create)),
+        )
+        .await;
+
+        let first = call_service(&app, post("replay", "a").to_request()).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+        let first = read_body(first).await;
+
+        let retry = call_service(&app, post("replay", "a").to_request()).await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(
+            retry.headers().get(IDEMPOTENT_REPLAYED_HEADER).unwrap(),
+            "true"
+        );
+        assert_eq!(read_body(retry).await, first);
+
+        let other = call_service(&app, post("replay", "b").to_request()).await;
+        assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn conflicts_and_failures_are_not_replayed() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(call))
+                .route("/items", web::post().to(create)),
+        )
+        .await;
+
+        // a first attempt still running
+        let fingerprint = hash(("POST", "/items", "", &b"a"[..]));
+        let scope = scope(&post("in-flight", "a").to_srv_request());
+        SLOTS.lock().unwrap().insert(
+            format!("{:016x}:{}", hash(scope), "in-flight"),
+            Slot::InFlight {
+                fingerprint,
+                started_at: Instant::now(),
+            },
+        );
+        let busy = call_service(&app, post("in-flight", "a").to_request()).await;
+        assert_eq!(busy.status(), StatusCode::CONFLICT);
+
+        let failed = call_service(&app, post("failing", "fail").to_request()).await;
+        assert_eq!(failed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let calls = CALLS.load(Ordering::SeqCst);
+        let retried = call_service(&app, post("failing", "fail").to_request()).await;
+        assert_eq!(retried.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(CALLS.load(Ordering::SeqCst) > calls);
+
+        for key in ["", "two words"] {
+            let invalid = call_service(&app, post(key, "a").to_request()).await;
+            assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_web::test]
+    async fn dropped_attempts_free_their_key() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(call))
+                .route("/slow", web::post().to(slow)),
+        )
+        .await;
+
+        // the client gives up and the handler's future is dropped
+        let first = call_service(&app, post_to("/slow", "dropped", "a").to_request());
+        assert!(timeout(Duration::from_millis(50), first).await.is_err());
+
+        let retry = call_service(&app, post_to("/slow", "dropped", "a").to_request()).await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+    }
+
+    #[test]
+    fn stale_attempts_expire() {
+        let ttl = Duration::from_secs(60);
+        let mut slots = HashMap::from([
+            (
+                "stale".to_string(),
+                Slot::InFlight {
+                    fingerprint: 1,
+                    started_at: Instant::now() - 2 * ttl,
+                },
+            ),
+            (
+                "running".to_string(),
+                Slot::InFlight {
+                    fingerprint: 2,
+                    started_at: Instant::now(),
+                },
+            ),
+        ]);
+        evict(&mut slots, ttl, 100);
+        assert!(!slots.contains_key("stale"));
+        assert!(slots.contains_key("running"));
+    }
+
+    #[test]
+    fn keys_are_scoped_per_caller() {
+        let with_key = |name: &str| {
+            let req = TestRequest::post().to_srv_request();
+            req.extensions_mut().insert(ApiKeyName(name.to_string()));
+            scope(&req)
+        };
+        let from = |ip: &str| {
+            let req = TestRequest::post()
+                .peer_addr(format!("{}:443", ip).parse().unwrap())
+                .to_srv_request();
+            scope(&req)
+        };
+        let bearer = TestRequest::post()
+            .insert_header((header::AUTHORIZATION, "Bearer one"))
+            .to_srv_request();
+
+        assert_eq!(with_key("tenant-a"), with_key("tenant-a"));
+        assert_ne!(with_key("tenant-a"), with_key("tenant-b"));
+        assert_ne!(from("10.0.0.1"), from("10.0.0.2"));
+        assert!(scope(&bearer).starts_with("auth:"));
+    }
+}
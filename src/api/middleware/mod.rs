@@ -0,0 +1,119 @@
+//! HTTP middleware
+//!
+//! Routes are split into groups (public autocomplete, admin) and each group
+//! gets its own middleware chain, [`handle`], configured per group: request
+//! ids, auth, rate limiting, `Idempotency-Key` replay, `ETag`s, the
+//! `X-Response-Time` header, access logging, usage accounting and the
+//! query log run as one `from_fn` middleware, CORS as the
+//! `actix-cors` transform from [`cors::cors_for`]. Settings are read from the
+//! live config per request, so a SIGHUP reload takes effect immediately.
+//! App wide middleware (the `Server` header) lives in [`server_header`].
+
+pub mod access_log;
+pub mod auth;
+pub mod cors;
+pub mod etag;
+pub mod idempotency;
+pub mod rate_limit;
+pub mod request_id;
+pub mod server_header;
+pub mod usage;
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::config::{self, RouteGroupConfig};
+use crate::query_log;
+
+pub use cors::cors_for;
+
+/// Wall time spent in the stack and the handlers below it.
+pub const RESPONSE_TIME_HEADER: HeaderName = HeaderName::from_static("x-response-time");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+    Public,
+    Admin,
+}
+
+impl RouteGroup {
+    /// The group's settings from the active config.
+    pub fn config(&self) -> RouteGroupConfig {
+        let config = config::current();
+        match self {
+            RouteGroup::Public => config.routes.public.clone(),
+            RouteGroup::Admin => config.routes.admin.clone(),
+        }
+    }
+
+    /// Whether the group stays closed until credentials are configured,
+    /// rather than open to anyone.
+    pub fn requires_auth(&self) -> bool {
+        matches!(self, RouteGroup::Admin)
+    }
+}
+
+/// Per-group middleware chain, wrapped around a scope with
+/// `from_fn(|req, next| middleware::handle(group, req, next))`. Runs the
+/// steps in order: assign a request id, check auth and the client's rate
+/// limit, call the service (tagging reads with an `ETag`, or replaying the
+/// response stored for a write's idempotency key), echo the id, set
+/// `X-Response-Time`, write the access log line, count the request for its
+/// client and append it to the query log. Each step is switched by the
+/// group's config.
+pub async fn handle<B: MessageBody>(
+    group: RouteGroup,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let config = group.config();
+    let started = Instant::now();
+    let request_id = config.request_id.then(|| request_id::assign(&req));
+
+    let idempotency = config.idempotency;
+    let etag = config.etag;
+    let call = |req: ServiceRequest, next: Next<B>| async move {
+        if etag && etag::applies(&req) {
+            etag::call(req, next).await
+        } else if idempotency {
+            idempotency::call(req, next).await
+        } else {
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+    };
+    let admitted = match auth::check(&config.auth, group.requires_auth(), req) {
+        Ok(req) => rate_limit::check(group, &config.rate_limit, req),
+        rejected => rejected,
+    };
+    let result = match admitted {
+        Ok(req) => call(req, next).await,
+        Err(rejected) => Ok(rejected.map_into_right_body()),
+    };
+
+    let mut res = result?;
+    if let Some(request_id) = &request_id {
+        request_id::echo(&mut res, request_id);
+    }
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if let Ok(value) = HeaderValue::from_str(&format!("{:.3}ms", elapsed_ms)) {
+        res.headers_mut().insert(RESPONSE_TIME_HEADER, value);
+    }
+    if config.access_log {
+        access_log::log(&res, started, request_id.as_ref());
+    }
+    if config.usage {
+        usage::record(&res, started);
+    }
+    if config.query_log {
+        query_log::record(&res);
+    }
+    Ok(res)
+}
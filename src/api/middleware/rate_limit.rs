@@ -0,0 +1,279 @@
+//! Per-client rate limiting
+//!
+//! A token bucket per client and route group: each request takes a token,
+//! tokens come back at `rate_limit.per_second` up to `rate_limit.burst`. A
+//! client with an empty bucket gets 429 with a `Retry-After` until the next
+//! token is due. Meant for the expensive endpoints, by default only
+//! `/search_by_coordinates`, and off until `per_second` is set. Buckets are
+//! process local, at most [`MAX_CLIENTS`] of them, and start full after a
+//! restart.
+
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, ResponseError};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::api::client_ip::ClientIp;
+use crate::api::error::ApiError;
+use crate::api::middleware::auth::ApiKeyName;
+use crate::api::middleware::RouteGroup;
+use crate::api::version::unversioned;
+use crate::config::{RateLimitConfig, RateLimitKey};
+
+/// Clients tracked at most. Reaching it drops the full buckets and then
+/// the longest idle ones.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Room [`evict`] makes, so it scans once per this many new clients rather
+/// than on every request.
+const EVICT_BATCH: usize = MAX_CLIENTS / 10;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, per_second: f64, burst: f64) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(burst);
+        self.updated = now;
+    }
+
+    /// Takes a token, or says how long until one is available.
+    fn take(&mut self, now: Instant, per_second: f64, burst: f64) -> Result<(), Duration> {
+        self.refill(now, per_second, burst);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<String, Bucket>> = Mutex::new(HashMap::new());
+}
+
+/// Drops buckets that have refilled, which start over full anyway, and then
+/// the least recently used ones until `max - batch` are left.
+fn evict(
+    buckets: &mut HashMap<String, Bucket>,
+    max: usize,
+    batch: usize,
+    now: Instant,
+    per_second: f64,
+    burst: f64,
+) {
+    buckets.retain(|_, bucket| {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens + elapsed * per_second < burst
+    });
+    let excess = (buckets.len() + batch).saturating_sub(max);
+    if excess == 0 {
+        return;
+    }
+    let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+    let cutoff = *updated.select_nth_unstable(excess - 1).1;
+    buckets.retain(|_, bucket| bucket.updated > cutoff);
+}
+
+/// The bucket of a client: its API key name when `config.key` says so and
+/// it has one, its address otherwise.
+pub fn client_key(config: &RateLimitConfig, key_name: Option<&str>, ip: &ClientIp) -> String {
+    match key_name.filter(|_| config.key == RateLimitKey::ApiKey) {
+        Some(name) => format!("key:{}", name),
+        None => format!("ip:{}", ip),
+    }
+}
+
+/// Takes a token from `client`'s bucket for `route`, or says how long
+/// until one is due. Routes that are not limited always pass.
+pub fn take(
+    group: RouteGroup,
+    config: &RateLimitConfig,
+    route: &str,
+    client: &str,
+) -> Result<(), Duration> {
+    let Some(per_second) = config.per_second.filter(|rate| *rate > 0.0) else {
+        return Ok(());
+    };
+    if !config.paths.is_empty() && !config.paths.iter().any(|path| path == route) {
+        return Ok(());
+    }
+
+    let burst = config.burst.max(1.0);
+    let now = Instant::now();
+    let mut buckets = BUCKETS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let key = format!("{:?}:{}", group, client);
+    if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(&key) {
+        evict(
+            &mut buckets,
+            MAX_CLIENTS,
+            EVICT_BATCH,
+            now,
+            per_second,
+            burst,
+        );
+    }
+    buckets
+        .entry(key)
+        .or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        })
+        .take(now, per_second, burst)
+}
+
+/// Passes the request through when limiting is off for its path or the
+/// client has a token left, otherwise answers 429.
+#[allow(clippy::result_large_err)]
+pub fn check(
+    group: RouteGroup,
+    config: &RateLimitConfig,
+    req: ServiceRequest,
+) -> Result<ServiceRequest, ServiceResponse> {
+    let key_name = req
+        .extensions()
+        .get::<ApiKeyName>()
+        .map(|name| name.0.clone());
+    let client = client_key(
+        config,
+        key_name.as_deref(),
+        &ClientIp::from_service_request(&req),
+    );
+
+    match take(group, config, unversioned(req.path()), &client) {
+        Ok(()) => Ok(req),
+        Err(wait) => {
+            warn!("Rate limited {} on {}", client, req.path());
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "Too many requests",
+            )
+            .with_details(json!({ "retry_after_secs": retry_after }))
+            .error_response();
+            if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+                response.headers_mut().insert(header::RETRY_AFTER, value);
+            }
+            Err(req.into_response(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn limited(per_second: f64, burst: f64) -> RateLimitConfig {
+        RateLimitConfig {
+            per_second: Some(per_second),
+            burst,
+            key: RateLimitKey::Ip,
+            paths: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn buckets_refill_up_to_the_burst() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            updated: start,
+        };
+        assert_eq!(bucket.take(start, 2.0, 2.0), Ok(()));
+        assert_eq!(bucket.take(start, 2.0, 2.0), Ok(()));
+        let wait = bucket.take(start, 2.0, 2.0).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.take(later, 2.0, 2.0), Ok(()));
+        bucket.refill(later + Duration::from_secs(60), 2.0, 2.0);
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn busy_clients_are_capped_by_idle_time() {
+        let start = Instant::now();
+        // all drained, none refills within the test
+        let mut buckets: HashMap<String, Bucket> = (0..10)
+            .map(|i| {
+                let bucket = Bucket {
+                    tokens: 0.0,
+                    updated: start + Duration::from_secs(i),
+                };
+                (format!("ip:10.0.0.{}", i), bucket)
+            })
+            .collect();
+        evict(
+            &mut buckets,
+            10,
+            3,
+            start + Duration::from_secs(10),
+            0.001,
+            5.0,
+        );
+        assert_eq!(buckets.len(), 7);
+        assert!(!buckets.contains_key("ip:10.0.0.2"));
+        assert!(buckets.contains_key("ip:10.0.0.3"));
+    }
+
+    #[test]
+    fn clients_are_limited_on_listed_paths_only() {
+        let config = RateLimitConfig {
+            paths: vec!["/search_by_coordinates".to_string()],
+            ..limited(0.001, 1.0)
+        };
+        let client = "ip:test-paths";
+        let group = RouteGroup::Public;
+        assert_eq!(
+            take(group, &config, "/search_by_coordinates", client),
+            Ok(())
+        );
+        assert!(take(group, &config, "/search_by_coordinates", client).is_err());
+        assert_eq!(take(group, &config, "/search", client), Ok(()));
+
+        let off = RateLimitConfig {
+            per_second: None,
+            ..config
+        };
+        assert_eq!(take(group, &off, "/search_by_coordinates", client), Ok(()));
+    }
+
+    #[test]
+    fn api_keys_get_their_own_bucket_when_configured() {
+        let ip = ClientIp(Some([10, 0, 0, 1].into()));
+        let mut config = limited(1.0, 1.0);
+        assert_eq!(client_key(&config, Some("acme"), &ip), "ip:10.0.0.1");
+        config.key = RateLimitKey::ApiKey;
+        assert_eq!(client_key(&config, Some("acme"), &ip), "key:acme");
+        assert_eq!(client_key(&config, None, &ip), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn empty_buckets_answer_429_with_retry_after() {
+        let config = limited(0.001, 1.0);
+        let request = || {
+            TestRequest::get()
+                .uri("/search")
+                .peer_addr("192.0.2.44:4000".parse().unwrap())
+                .to_srv_request()
+        };
+        assert!(check(RouteGroup::Admin, &config, request()).is_ok());
+        let rejected = check(RouteGroup::Admin, &config, request()).unwrap_err();
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rejected.headers().get(header::RETRY_AFTER).unwrap(), "1000");
+    }
+}
@@ -0,0 +1,67 @@
+//! Request ids
+//!
+//! Every request gets an id, taken from the incoming `X-Request-Id` header
+//! when it looks sane (so ids from a gateway carry through) and generated
+//! otherwise. The id is echoed in the response and available to handlers
+//! through the [`RequestId`] extractor.
+
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{ready, Ready};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Incoming ids longer than this are replaced.
+const MAX_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(pub String);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// The id assigned by the middleware, or `-` when it is disabled.
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(|| RequestId("-".to_string()));
+        ready(Ok(id))
+    }
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Picks the id for `req` and stores it in the request extensions.
+pub fn assign(req: &ServiceRequest) -> RequestId {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let id = RequestId(id);
+    req.extensions_mut().insert(id.clone());
+    id
+}
+
+/// Adds the id to the response headers.
+pub fn echo<B>(res: &mut ServiceResponse<B>, id: &RequestId) {
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+}
@@ -0,0 +1,27 @@
+//! `Server` response header, applied to the whole app. The value comes from
+//! `server_header` in the live config; `null` leaves the header out.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use tracing::warn;
+
+use crate::config;
+
+pub async fn server_header<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let mut res = next.call(req).await?;
+    if let Some(server) = &config::current().server_header {
+        match HeaderValue::from_str(server) {
+            Ok(value) => {
+                res.headers_mut().insert(header::SERVER, value);
+            }
+            Err(e) => warn!("Invalid server_header '{}': {}", server, e),
+        }
+    }
+    Ok(res)
+}
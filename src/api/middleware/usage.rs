@@ -0,0 +1,203 @@
+//! Per-client usage accounting
+//!
+//! Every request is counted in fixed time buckets per client (requests,
+//! errors, latency), kept in memory for `usage.retention_secs`. Clients are
+//! keyed by [`ClientIp`]. The buckets back `/admin/usage/timeseries`; they
+//! are process local and start empty after a restart.
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::api::client_ip::ClientIp;
+use crate::config::{self, UsageConfig};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// Unix time in seconds the bucket starts at.
+    start: u64,
+    requests: u64,
+    client_errors: u64,
+    errors: u64,
+    latency_ms_sum: f64,
+    latency_ms_max: f64,
+}
+
+impl Bucket {
+    fn add(&mut self, other: &Bucket) {
+        self.requests += other.requests;
+        self.client_errors += other.client_errors;
+        self.errors += other.errors;
+        self.latency_ms_sum += other.latency_ms_sum;
+        self.latency_ms_max = self.latency_ms_max.max(other.latency_ms_max);
+    }
+}
+
+/// One bucket of a client's series.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsagePoint {
+    /// Unix time in seconds the bucket starts at.
+    pub timestamp: u64,
+    pub requests: u64,
+    /// Responses with a 4xx status.
+    pub client_errors: u64,
+    /// Responses with a 5xx status.
+    pub errors: u64,
+    pub avg_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+impl From<Bucket> for UsagePoint {
+    fn from(bucket: Bucket) -> Self {
+        let avg_latency_ms = if bucket.requests == 0 {
+            0.0
+        } else {
+            bucket.latency_ms_sum / bucket.requests as f64
+        };
+        Self {
+            timestamp: bucket.start,
+            requests: bucket.requests,
+            client_errors: bucket.client_errors,
+            errors: bucket.errors,
+            avg_latency_ms,
+            max_latency_ms: bucket.latency_ms_max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSeries {
+    pub key: String,
+    pub window_secs: u64,
+    pub bucket_secs: u64,
+    pub series: Vec<UsagePoint>,
+}
+
+lazy_static::lazy_static! {
+    static ref USAGE: Mutex<HashMap<String, VecDeque<Bucket>>> = Mutex::new(HashMap::new());
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+fn bucket_start(secs: u64, bucket_secs: u64) -> u64 {
+    secs - secs % bucket_secs.max(1)
+}
+
+/// Drops buckets that fell out of the retention period.
+fn expire(buckets: &mut VecDeque<Bucket>, now: u64, retention_secs: u64) {
+    let oldest = now.saturating_sub(retention_secs);
+    while buckets.front().is_some_and(|bucket| bucket.start < oldest) {
+        buckets.pop_front();
+    }
+}
+
+/// Counts the request behind `res` for its client.
+pub fn record<B>(res: &ServiceResponse<B>, started: Instant) {
+    let key = ClientIp::from_http_request(res.request()).to_string();
+    count(key, res.status(), started);
+}
+
+/// Counts a request of the client `key` answered with `status`, for
+/// callers outside actix.
+pub fn count(key: String, status: StatusCode, started: Instant) {
+    let config = config::current();
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let sample = Bucket {
+        start: bucket_start(now_secs(), config.usage.bucket_secs),
+        requests: 1,
+        client_errors: status.is_client_error() as u64,
+        errors: status.is_server_error() as u64,
+        latency_ms_sum: latency_ms,
+        latency_ms_max: latency_ms,
+    };
+    add(&config.usage, key, sample);
+}
+
+fn add(config: &UsageConfig, key: String, sample: Bucket) {
+    let mut clients = USAGE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = now_secs();
+    if !clients.contains_key(&key) && clients.len() >= config.max_clients {
+        // make room by forgetting clients without recent traffic
+        clients.retain(|_, buckets| {
+            expire(buckets, now, config.retention_secs);
+            !buckets.is_empty()
+        });
+        if clients.len() >= config.max_clients {
+            return;
+        }
+    }
+
+    let buckets = clients.entry(key).or_default();
+    expire(buckets, now, config.retention_secs);
+    match buckets.back_mut() {
+        Some(last) if last.start == sample.start => last.add(&sample),
+        _ => buckets.push_back(sample),
+    }
+}
+
+/// The series of `key` over the last `window`, one point per bucket with
+/// empty buckets included, oldest first. `None` for an unknown client.
+pub fn timeseries(key: &str, window: Duration) -> Option<UsageSeries> {
+    let config = config::current();
+    let bucket_secs = config.usage.bucket_secs.max(1);
+    let window_secs = window
+        .as_secs()
+        .clamp(1, config.usage.retention_secs.max(1));
+    let now = now_secs();
+    let first = bucket_start(now.saturating_sub(window_secs - 1), bucket_secs);
+    let last = bucket_start(now, bucket_secs);
+
+    let clients = USAGE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let buckets = clients.get(key)?;
+
+    // stored buckets are regrouped, `bucket_secs` may have changed on reload
+    let mut series: Vec<Bucket> = (first..=last)
+        .step_by(bucket_secs as usize)
+        .map(|start| Bucket {
+            start,
+            ..Bucket::default()
+        })
+        .collect();
+    for bucket in buckets.iter().filter(|bucket| bucket.start >= first) {
+        let index = ((bucket_start(bucket.start, bucket_secs) - first) / bucket_secs) as usize;
+        if let Some(point) = series.get_mut(index) {
+            point.add(bucket);
+        }
+    }
+
+    Some(UsageSeries {
+        key: key.to_string(),
+        window_secs,
+        bucket_secs,
+        series: series.into_iter().map(UsagePoint::from).collect(),
+    })
+}
+
+/// Parses windows like `90s`, `15m`, `1h` or `7d`. A bare number is seconds.
+pub fn parse_window(window: &str) -> Option<Duration> {
+    let window = window.trim();
+    let split = window
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(window.len());
+    let (amount, unit) = window.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let unit_secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    amount.checked_mul(unit_secs).map(Duration::from_secs)
+}
@@ -1 +1,16 @@
-pub mod actix_client;
\ No newline at end of file
+pub mod actix_client;
+pub mod admin;
+pub mod analytics;
+pub mod client_ip;
+pub mod error;
+pub mod freshness;
+pub mod google;
+pub mod health;
+pub mod middleware;
+pub mod ndjson;
+pub mod negotiate;
+pub mod openapi;
+pub mod photon;
+pub mod sse;
+pub mod version;
+pub mod ws;
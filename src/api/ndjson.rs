@@ -0,0 +1,103 @@
+//! Newline delimited JSON bodies
+//!
+//! Streaming endpoints receive their body in arbitrary chunks. [`Lines`]
+//! collects them and hands out complete lines, so a handler can answer
+//! what has arrived while the client is still sending. [`stream`] goes the
+//! other way and writes a response a few lines at a time.
+
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::Value;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Longest line accepted, so a body without newlines cannot grow the
+/// buffer without bound.
+pub const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Lines serialized per body chunk.
+const LINES_PER_CHUNK: usize = 256;
+
+#[derive(Debug, Default)]
+pub struct Lines {
+    buffer: Vec<u8>,
+}
+
+impl Lines {
+    /// Adds a chunk and returns the lines it completes, without their line
+    /// endings. `None` when the unfinished line exceeds [`MAX_LINE_BYTES`].
+    pub fn push(&mut self, chunk: &[u8]) -> Option<Vec<String>> {
+        self.buffer.extend_from_slice(chunk);
+        let Some(end) = self.buffer.iter().rposition(|&b| b == b'\n') else {
+            return (self.buffer.len() <= MAX_LINE_BYTES).then(Vec::new);
+        };
+        let complete: Vec<u8> = self.buffer.drain(..=end).collect();
+        if self.buffer.len() > MAX_LINE_BYTES {
+            return None;
+        }
+        Some(split(&complete))
+    }
+
+    /// The last line when the body did not end with a newline.
+    pub fn finish(&mut self) -> Vec<String> {
+        split(&std::mem::take(&mut self.buffer))
+    }
+}
+
+/// Non-blank lines of `bytes`, trimmed of `\r` and surrounding whitespace.
+fn split(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Serializes values one per line.
+pub fn to_bytes<'a>(values: impl IntoIterator<Item = &'a Value>) -> Bytes {
+    let mut out = Vec::new();
+    for value in values {
+        // serializing a Value into a Vec cannot fail
+        serde_json::to_writer(&mut out, value).expect("Failed to serialize NDJSON line");
+        out.push(b'\n');
+    }
+    Bytes::from(out)
+}
+
+/// Body writing `values` one per line, serializing each chunk only when the
+/// client is ready for it, so a large result is never held as one buffer.
+/// Values are dropped as they are written.
+pub fn stream(
+    values: impl IntoIterator<Item = Value> + 'static,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    stream::iter(values)
+        .chunks(LINES_PER_CHUNK)
+        .map(|chunk| Ok(to_bytes(&chunk)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_split_across_chunks() {
+        let mut lines = Lines::default();
+        assert_eq!(
+            lines.push(b"{\"a\":1}\n{\"b\""),
+            Some(vec!["{\"a\":1}".to_string()])
+        );
+        assert_eq!(
+            lines.push(b":2}\r\n\n"),
+            Some(vec!["{\"b\":2}".to_string()])
+        );
+        assert_eq!(lines.push(b"{\"c\":3}"), Some(vec![]));
+        assert_eq!(lines.finish(), vec!["{\"c\":3}".to_string()]);
+    }
+
+    #[test]
+    fn overlong_line_is_rejected() {
+        let mut lines = Lines::default();
+        assert_eq!(lines.push(&vec![b'x'; MAX_LINE_BYTES + 1]), None);
+    }
+}
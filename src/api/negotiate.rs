@@ -0,0 +1,284 @@
+//! Content negotiation for search responses
+//!
+//! Every search endpoint builds the same JSON envelope. This module turns
+//! that envelope into whatever the client asked for in `Accept`:
+//! `application/geo+json` gives a GeoJSON `FeatureCollection`, `text/csv`
+//! one row per entry, `application/x-ndjson` (or `format=ndjson`) one entry
+//! per line as a streamed body, anything else the JSON envelope as is.
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde_json::{json, Map, Value};
+
+use crate::api::freshness;
+use crate::api::middleware::auth::Authenticated;
+use crate::api::ndjson::{self, NDJSON};
+use crate::fields::Field;
+use crate::query::row_id;
+
+pub const GEO_JSON: &str = "application/geo+json";
+pub const CSV: &str = "text/csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    GeoJson,
+    Csv,
+    Ndjson,
+}
+
+impl ResponseFormat {
+    /// `format=ndjson` in the query, JSON for `format=google` and
+    /// `format=photon` (reshaped by `google::handle` and `photon::handle`),
+    /// otherwise the format with the highest `q` in the
+    /// `Accept` header. Ties go to the first listed media type, unknown
+    /// types are ignored.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let format = req
+            .query_string()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("format="));
+        match format {
+            Some(format) if format.eq_ignore_ascii_case("ndjson") => return ResponseFormat::Ndjson,
+            Some(format)
+                if format.eq_ignore_ascii_case("google")
+                    || format.eq_ignore_ascii_case("photon") =>
+            {
+                return ResponseFormat::Json
+            }
+            _ => {}
+        }
+
+        let Some(accept) = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return ResponseFormat::Json;
+        };
+
+        let mut best: Option<(ResponseFormat, f32)> = None;
+        for media_range in accept.split(',') {
+            let mut parts = media_range.split(';');
+            let media_type = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality: f32 = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+
+            let format = match media_type.as_str() {
+                GEO_JSON => ResponseFormat::GeoJson,
+                CSV => ResponseFormat::Csv,
+                NDJSON => ResponseFormat::Ndjson,
+                "application/json" | "application/*" | "*/*" => ResponseFormat::Json,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((format, quality));
+            }
+        }
+
+        best.map_or(ResponseFormat::Json, |(format, _)| format)
+    }
+}
+
+/// Renders a search envelope in the negotiated format with a 200 status and
+/// the `freshness` hints, in the body as well for JSON.
+pub fn respond(req: &HttpRequest, mut envelope: Value) -> HttpResponse {
+    let freshness = freshness::current();
+    let mut response = HttpResponse::Ok();
+    if let Some(freshness) = &freshness {
+        freshness.apply(&mut response, Authenticated::is(req));
+    }
+    match ResponseFormat::from_request(req) {
+        ResponseFormat::Json => {
+            if let (Some(freshness), Some(envelope)) = (freshness, envelope.as_object_mut()) {
+                envelope.insert("cache".to_string(), freshness.to_json());
+            }
+            response.json(envelope)
+        }
+        ResponseFormat::GeoJson => response
+            .content_type(GEO_JSON)
+            .body(to_geojson(&envelope).to_string()),
+        ResponseFormat::Csv => response
+            .content_type("text/csv; charset=utf-8")
+            .body(to_csv(&envelope)),
+        ResponseFormat::Ndjson => response
+            .content_type(NDJSON)
+            .streaming(ndjson::stream(to_ndjson_lines(envelope))),
+    }
+}
+
+/// One line per entry and a final `summary` line with the number of
+/// entries and whether any section was truncated.
+pub fn to_ndjson_lines(envelope: Value) -> Vec<Value> {
+    let sections: Vec<&Value> = match envelope.as_object() {
+        Some(_) if envelope.get("truncated").is_some() => vec![&envelope],
+        Some(sections) => sections.values().collect(),
+        None => Vec::new(),
+    };
+    let truncated = sections
+        .iter()
+        .any(|section| section["truncated"].as_bool().unwrap_or(false));
+
+    let mut lines: Vec<Value> = collect_entries(&envelope)
+        .into_iter()
+        .map(Value::Object)
+        .collect();
+    let summary = json!({
+        "summary": {
+            "returned": lines.len(),
+            "truncated": truncated,
+        }
+    });
+    lines.push(summary);
+    lines
+}
+
+/// Flattens an envelope into plain entry objects. Handles the `entries`
+/// list, the `{entry, distance}` wrapper of coordinate searches and the
+/// single `entry` + `house_numbers` postal code shape. Entries under a named
+/// section (`postal_code`, `street`) get a `source` property unless they
+/// already carry `sources` from cross-section dedup.
+pub fn collect_entries(envelope: &Value) -> Vec<Map<String, Value>> {
+    fn from_section(section: &Value, source: Option<&str>, out: &mut Vec<Map<String, Value>>) {
+        let mut push = |mut entry: Map<String, Value>| {
+            if let Some(source) = source.filter(|_| !entry.contains_key("sources")) {
+                entry.insert("source".to_string(), json!(source));
+            }
+            out.push(entry);
+        };
+
+        if let Some(entries) = section.get("entries").and_then(Value::as_array) {
+            for item in entries {
+                let Some(object) = item.as_object() else {
+                    continue;
+                };
+                match object.get("entry").and_then(Value::as_object) {
+                    Some(inner) => {
+                        let mut entry = inner.clone();
+                        if let Some(distance) = object.get("distance") {
+                            entry.insert("distance".to_string(), distance.clone());
+                        }
+                        push(entry);
+                    }
+                    None => push(object.clone()),
+                }
+            }
+        } else if let Some(entry) = section.get("entry").and_then(Value::as_object) {
+            let house_numbers = section.get("house_numbers").and_then(Value::as_array);
+            match house_numbers {
+                Some(house_numbers) if !house_numbers.is_empty() => {
+                    for house_number in house_numbers {
+                        let mut entry = entry.clone();
+                        // ids only go out when the entry has one
+                        if let (Some(postal_code), Some(house_number)) = (
+                            entry
+                                .get("postal_code")
+                                .and_then(Value::as_str)
+                                .filter(|_| entry.contains_key("id")),
+                            house_number.as_str(),
+                        ) {
+                            entry
+                                .insert("id".to_string(), json!(row_id(postal_code, house_number)));
+                        }
+                        entry.insert("house_number".to_string(), house_number.clone());
+                        push(entry);
+                    }
+                }
+                _ => push(entry.clone()),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    if envelope.get("entries").is_some() || envelope.get("entry").is_some() {
+        from_section(envelope, None, &mut out);
+    } else if let Some(sections) = envelope.as_object() {
+        for (name, section) in sections {
+            from_section(section, Some(name), &mut out);
+        }
+    }
+    out
+}
+
+/// GeoJSON `FeatureCollection` with one `Point` per entry. Entries without
+/// coordinates (e.g. when the fields are not exposed) get a `null` geometry.
+pub fn to_geojson(envelope: &Value) -> Value {
+    let features: Vec<Value> = collect_entries(envelope)
+        .into_iter()
+        .map(|mut properties| {
+            let latitude = properties.remove("latitude").and_then(|v| v.as_f64());
+            let longitude = properties.remove("longitude").and_then(|v| v.as_f64());
+            let geometry = match (latitude, longitude) {
+                (Some(lat), Some(lon)) => json!({ "type": "Point", "coordinates": [lon, lat] }),
+                _ => Value::Null,
+            };
+            json!({
+                "type": "Feature",
+                "id": properties.get("id").cloned().unwrap_or(Value::Null),
+                "geometry": geometry,
+                "properties": properties
+            })
+        })
+        .collect();
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// CSV with a fixed column order: `id`, the row fields, then any extra keys
+/// (`distance`, `source`, ...) in first-seen order. Each reference system in
+/// `crs` becomes a `<code>_x` and a `<code>_y` column.
+pub fn to_csv(envelope: &Value) -> String {
+    let mut entries = collect_entries(envelope);
+    for entry in &mut entries {
+        if let Some(Value::Object(systems)) = entry.remove("crs") {
+            for (code, position) in systems {
+                entry.insert(format!("{}_x", code), position["x"].clone());
+                entry.insert(format!("{}_y", code), position["y"].clone());
+            }
+        }
+    }
+
+    let mut columns: Vec<String> = Field::ALL
+        .iter()
+        .map(Field::name)
+        .filter(|column| entries.iter().any(|entry| entry.contains_key(*column)))
+        .map(str::to_string)
+        .collect();
+    for entry in &entries {
+        for key in entry.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&columns).ok();
+    for entry in &entries {
+        let record: Vec<String> = columns
+            .iter()
+            .map(|column| match entry.get(column) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .map_or_else(|| value.to_string(), str::to_string)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                Some(value) => value.to_string(),
+            })
+            .collect();
+        writer.write_record(&record).ok();
+    }
+
+    writer
+        .into_inner()
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
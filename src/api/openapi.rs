@@ -0,0 +1,127 @@
+//! OpenAPI description of the HTTP API
+//!
+//! Handlers read their query strings into a `HashMap`, so the parameters
+//! they share are described here once as [`IntoParams`] types and listed in
+//! each handler's `#[utoipa::path]`. The structs only document, nothing
+//! deserializes into them. The spec itself is assembled in `main.rs`, where
+//! the public handlers live, and served at `/openapi.json` with a Swagger UI
+//! under `/docs/`.
+
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi};
+
+/// Query parameters every search route reads through
+/// `QueryOptions::from_params`, apart from [`HouseNumberParams`].
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryOptionsParams {
+    /// Filter expression, e.g. `city:Amsterdam AND house_number:[10 TO 20]`.
+    pub filter: Option<String>,
+    /// Name of a configured filter preset, ANDed with `filter`.
+    pub preset: Option<String>,
+    /// Only addresses in this municipality.
+    pub municipality: Option<String>,
+    /// Only addresses in this province.
+    pub province: Option<String>,
+    /// Rank streets in this city higher.
+    pub city_bias: Option<String>,
+    /// Keep only the first address per street name.
+    pub unique_street_only: Option<bool>,
+    /// Typos tolerated per street query token.
+    pub max_edits: Option<u8>,
+    /// Decimals of the returned coordinates.
+    pub coordinate_precision: Option<u8>,
+    /// Return the street's position instead of the address's.
+    pub snap_to_street: Option<bool>,
+    /// Comma separated reference systems to add as `crs`, e.g.
+    /// `EPSG:28992` for RD New. Coordinate endpoints also read `x` and `y`
+    /// in it when it names a single system.
+    pub crs: Option<String>,
+    /// Add stage durations as `timings`; such responses bypass the cache.
+    pub timings: Option<bool>,
+    /// Entry fields to return, e.g. `id,street,city,latitude,longitude`.
+    /// `id` is only returned when listed here or with `mode=compact`,
+    /// fields not allowed by config are left out.
+    pub fields: Option<String>,
+    /// `ndjson` streams one entry per line and a closing `summary` line,
+    /// whatever the `Accept` header says. `google` answers in the Google
+    /// Places Autocomplete schema, see `api::google`, `photon` in Photon's
+    /// GeoJSON with Pelias' labels, see `api::photon`.
+    pub format: Option<String>,
+    /// Postal code result shape: `auto` folds a single street into `entry`
+    /// with `house_numbers`, `always` gives one entry per street with its
+    /// `house_numbers`, `never` one entry per address.
+    pub collapse: Option<String>,
+}
+
+/// The house number filter, kept apart from [`QueryOptionsParams`] for
+/// `/units`, which reads `house_number` itself.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HouseNumberParams {
+    /// Only addresses with this house number, e.g. `12` or `12A`.
+    pub house_number: Option<String>,
+    /// Let a bare `house_number` also match its suffixed addresses.
+    pub any_suffix: Option<bool>,
+}
+
+/// Result size and shape of routes returning entries.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResultParams {
+    /// Entries per section, capped by the route's configured maximum.
+    pub limit: Option<usize>,
+    /// `compact` returns `[display, id]` pairs instead of full entries.
+    pub mode: Option<String>,
+}
+
+/// Headers that bound how long a scan may run, see `deadline`.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Header)]
+pub struct DeadlineHeaders {
+    /// Absolute deadline in unix milliseconds.
+    #[param(rename = "X-Request-Deadline")]
+    pub deadline: Option<u64>,
+    /// Time budget in milliseconds.
+    #[param(rename = "X-Request-Timeout")]
+    pub timeout: Option<u64>,
+}
+
+/// Adds the bearer token and API key schemes `api::middleware::auth`
+/// checks when a route group has them configured.
+pub struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        );
+    }
+}
+
+/// Routes of the `/admin` scope, nested by the public spec.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::api::admin::get_log_level,
+        crate::api::admin::put_log_level,
+        crate::api::admin::info,
+        crate::api::admin::cache_flush,
+        crate::api::admin::cache_stats,
+        crate::api::admin::usage_timeseries,
+        crate::api::admin::get_zero_results,
+        crate::api::admin::delete_zero_results,
+        crate::api::admin::list_zones,
+        crate::api::admin::put_zone,
+        crate::api::admin::delete_zone,
+    ),
+    components(schemas(crate::api::admin::LogLevelUpdate, crate::api::admin::CacheFlush)),
+    tags((name = "admin", description = "Operator endpoints"))
+)]
+pub struct AdminApi;
@@ -0,0 +1,361 @@
+//! Photon and Pelias compatible output
+//!
+//! `format=photon` reshapes a search response into the GeoJSON Photon
+//! answers with: a `FeatureCollection` of `Point` features whose
+//! `properties` carry `osm_key`, `osm_value`, `type`, `name`,
+//! `housenumber`, `street`, `postcode`, `city`, `district`, `county`,
+//! `state`, `countrycode` and, for streets and cities, an `extent` of
+//! `[min_lon, max_lat, max_lon, min_lat]`. Pelias clients find their
+//! `label`, `layer` and `gid` in the same properties, `gid` being the
+//! address `id` when the request asked for it and the label otherwise. Nothing found is an
+//! empty collection with a 200, as Photon does; other errors keep their
+//! status with Photon's `{"message": ..}` body.
+//!
+//! `/api` and `/reverse` answer Photon's own paths, `/api?q=` like
+//! `/autocomplete` and `/reverse?lat=&lon=` like `/reverse_geocode`, so
+//! Leaflet geocoder plugins only need the base URL. Photon's `lang`,
+//! `osm_tag`, `layer` and location bias parameters are ignored.
+//!
+//! There is no OpenStreetMap data behind the addresses: `osm_key` and
+//! `osm_value` are the tags Photon gives the same kind of place, and there
+//! is no `osm_id`.
+
+use actix_web::body::{to_bytes, BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::api::negotiate::collect_entries;
+use crate::api::version::unversioned;
+use crate::query::{display_address, DEFAULT_COUNTRY};
+
+/// Photon's forward search path.
+pub const SEARCH_PATH: &str = "/api";
+/// Photon's reverse geocoding path.
+pub const REVERSE_PATH: &str = "/reverse";
+
+/// Whether the request asked for Photon's schema, by `format=photon` or by
+/// its path.
+pub fn is_requested(req: &ServiceRequest) -> bool {
+    let path = unversioned(req.path());
+    path == SEARCH_PATH
+        || path == REVERSE_PATH
+        || req
+            .query_string()
+            .split('&')
+            .any(|pair| pair.eq_ignore_ascii_case("format=photon"))
+}
+
+fn text<'a>(entry: &'a Map<String, Value>, field: &str) -> &'a str {
+    entry.get(field).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn position(entry: &Map<String, Value>) -> Option<(f64, f64)> {
+    Some((
+        entry.get("longitude")?.as_f64()?,
+        entry.get("latitude")?.as_f64()?,
+    ))
+}
+
+/// Bounding box of the positions seen so far.
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    min_lon: f64,
+    min_lat: f64,
+    max_lon: f64,
+    max_lat: f64,
+}
+
+impl Extent {
+    fn add(extent: Option<Extent>, (lon, lat): (f64, f64)) -> Extent {
+        match extent {
+            None => Extent {
+                min_lon: lon,
+                min_lat: lat,
+                max_lon: lon,
+                max_lat: lat,
+            },
+            Some(e) => Extent {
+                min_lon: e.min_lon.min(lon),
+                min_lat: e.min_lat.min(lat),
+                max_lon: e.max_lon.max(lon),
+                max_lat: e.max_lat.max(lat),
+            },
+        }
+    }
+
+    /// Photon's order, which is not GeoJSON's `bbox` order; `None` for a
+    /// single point.
+    fn to_json(self) -> Option<Value> {
+        (self.min_lon < self.max_lon || self.min_lat < self.max_lat)
+            .then(|| json!([self.min_lon, self.max_lat, self.max_lon, self.min_lat]))
+    }
+}
+
+fn street_key(entry: &Map<String, Value>) -> String {
+    format!("{}\n{}", text(entry, "street"), text(entry, "city"))
+}
+
+fn city_key(entry: &Map<String, Value>) -> String {
+    format!("\n{}", text(entry, "city"))
+}
+
+/// What a feature stands for, by the entry's section and house number.
+enum Kind {
+    House,
+    Street,
+    City,
+}
+
+impl Kind {
+    fn of(entry: &Map<String, Value>) -> Self {
+        if text(entry, "source") == "city" {
+            Kind::City
+        } else if text(entry, "house_number").is_empty() {
+            Kind::Street
+        } else {
+            Kind::House
+        }
+    }
+
+    /// `osm_key`, `osm_value`, Photon's `type` and Pelias' `layer`.
+    fn tags(&self) -> [&'static str; 4] {
+        match self {
+            Kind::House => ["place", "house", "house", "address"],
+            Kind::Street => ["highway", "residential", "street", "street"],
+            Kind::City => ["place", "city", "city", "locality"],
+        }
+    }
+
+    /// Entries sharing the key make up the extent, houses have none.
+    fn extent_key(&self, entry: &Map<String, Value>) -> Option<String> {
+        match self {
+            Kind::House => None,
+            Kind::Street => Some(street_key(entry)),
+            Kind::City => Some(city_key(entry)),
+        }
+    }
+}
+
+/// One feature per address of `envelope`, one per street without a house
+/// number and one per city of the `city` section. Streets and cities get
+/// the extent of the response's entries on them.
+pub fn to_features(envelope: &Value) -> Value {
+    let entries = collect_entries(envelope);
+    let mut extents: HashMap<String, Extent> = HashMap::new();
+    for entry in &entries {
+        let Some(position) = position(entry) else {
+            continue;
+        };
+        for key in [street_key(entry), city_key(entry)] {
+            let extent = Extent::add(extents.get(&key).copied(), position);
+            extents.insert(key, extent);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut features = Vec::new();
+    for entry in &entries {
+        let kind = Kind::of(entry);
+        let (street, house_number, postal_code, city) = (
+            text(entry, "street"),
+            text(entry, "house_number"),
+            text(entry, "postal_code"),
+            text(entry, "city"),
+        );
+        let (name, gid, label) = match kind {
+            Kind::City => {
+                let label = [city, text(entry, "province")]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (city.to_string(), format!("city:{}", city), label)
+            }
+            _ => {
+                let name = [street, house_number]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let label = display_address(
+                    Some(street),
+                    Some(house_number).filter(|number| !number.is_empty()),
+                    Some(postal_code).filter(|code| !code.is_empty()),
+                    Some(city),
+                );
+                // without `fields=id` the label tells addresses apart
+                let gid = match text(entry, "id") {
+                    "" => label.clone(),
+                    id => id.to_string(),
+                };
+                (name, gid, label)
+            }
+        };
+        if !seen.insert(gid.clone()) {
+            continue;
+        }
+
+        let [osm_key, osm_value, kind_name, layer] = kind.tags();
+        let mut properties = json!({
+            "osm_key": osm_key,
+            "osm_value": osm_value,
+            "type": kind_name,
+            "name": name,
+            "countrycode": entry
+                .get("country")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_COUNTRY),
+            "label": label,
+            "layer": layer,
+            "gid": gid,
+        });
+        let (house_number, street) = match kind {
+            Kind::City => ("", ""),
+            _ => (house_number, street),
+        };
+        let parts = [
+            ("housenumber", house_number),
+            ("street", street),
+            ("postcode", postal_code),
+            ("city", city),
+            ("district", text(entry, "neighborhood")),
+            ("county", text(entry, "municipality")),
+            ("state", text(entry, "province")),
+        ];
+        for (name, value) in parts {
+            if !value.is_empty() {
+                properties[name] = json!(value);
+            }
+        }
+        let extent = kind
+            .extent_key(entry)
+            .and_then(|key| extents.get(&key))
+            .and_then(|extent| extent.to_json());
+        if let Some(extent) = extent {
+            properties["extent"] = extent;
+        }
+        // reverse geocoding puts these next to its one entry
+        let fields = [(entry, "distance"), (entry, "elevation_m")]
+            .into_iter()
+            .filter_map(|(entry, field)| Some((field, entry.get(field)?)))
+            .chain(
+                ["distance_m", "confidence"]
+                    .into_iter()
+                    .filter_map(|field| Some((field, envelope.get(field)?))),
+            );
+        for (field, value) in fields {
+            properties[field] = value.clone();
+        }
+
+        let geometry = match position(entry) {
+            Some((lon, lat)) => json!({ "type": "Point", "coordinates": [lon, lat] }),
+            None => Value::Null,
+        };
+        features.push(json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": properties,
+        }));
+    }
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Middleware for the public scopes, wrapped outside the group's middleware chain
+/// like `google::handle`. Requests that did not ask for Photon's schema,
+/// and streamed or non-JSON bodies, pass through.
+pub async fn handle<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    if !is_requested(&req) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json || res.response().body().size() == BodySize::Stream {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        let response = HttpResponse::InternalServerError().finish();
+        return Ok(ServiceResponse::new(req, response).map_into_right_body());
+    };
+    let Ok(body) = serde_json::from_slice::<Value>(&body) else {
+        let res = res.set_body(BoxBody::new(body));
+        return Ok(ServiceResponse::new(req, res).map_into_right_body());
+    };
+    let mapped = if res.status().is_success() {
+        to_features(&body)
+    } else {
+        let error = &body["error"];
+        match error["code"].as_str().unwrap_or_default() {
+            "no_match" | "out_of_range" => {
+                *res.status_mut() = StatusCode::OK;
+                json!({ "type": "FeatureCollection", "features": [] })
+            }
+            _ => json!({ "message": error["message"] }),
+        }
+    };
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    let res = res.set_body(BoxBody::new(mapped.to_string()));
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_become_photon_features() {
+        let envelope = json!({
+            "street": {
+                "entries": [
+                    {
+                        "id": "1017GE-12",
+                        "street": "Kerkstraat",
+                        "house_number": "12",
+                        "postal_code": "1017GE",
+                        "city": "Amsterdam",
+                        "latitude": 52.36,
+                        "longitude": 4.88
+                    },
+                    {
+                        "id": "1017GE-",
+                        "street": "Kerkstraat",
+                        "postal_code": "1017GE",
+                        "city": "Amsterdam",
+                        "latitude": 52.37,
+                        "longitude": 4.89
+                    }
+                ]
+            }
+        });
+        let response = to_features(&envelope);
+        let house = &response["features"][0];
+        assert_eq!(house["geometry"]["coordinates"], json!([4.88, 52.36]));
+        assert_eq!(house["properties"]["osm_key"], "place");
+        assert_eq!(house["properties"]["name"], "Kerkstraat 12");
+        assert_eq!(house["properties"]["postcode"], "1017GE");
+        assert_eq!(
+            house["properties"]["label"],
+            "Kerkstraat 12, 1017GE Amsterdam"
+        );
+        assert!(house["properties"].get("extent").is_none());
+        let street = &response["features"][1]["properties"];
+        assert_eq!(street["osm_key"], "highway");
+        assert_eq!(street["extent"], json!([4.88, 52.37, 4.89, 52.36]));
+        assert_eq!(to_features(&json!({}))["features"], json!([]));
+    }
+}
@@ -0,0 +1,116 @@
+//! Server-Sent Events autocomplete sessions
+//!
+//! A type-ahead client opens one `text/event-stream` and keeps it for the
+//! whole input: the first event names the session, every keystroke is sent
+//! as a small update for that session and the answer comes back as an event
+//! on the open stream. Updates arriving while a query runs are coalesced,
+//! only the newest text is answered. Sessions end when the client
+//! disconnects or sends nothing for `autocomplete_stream.idle_timeout_secs`.
+
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::config;
+
+pub const EVENT_STREAM: &str = "text/event-stream";
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<String, watch::Sender<String>>> = Mutex::new(HashMap::new());
+}
+
+fn sessions() -> std::sync::MutexGuard<'static, HashMap<String, watch::Sender<String>>> {
+    SESSIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An open session, closed when dropped together with its stream.
+#[derive(Debug)]
+pub struct Session {
+    pub id: String,
+    queries: watch::Receiver<String>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        sessions().remove(&self.id);
+        info!("Closed autocomplete stream {}", self.id);
+    }
+}
+
+/// Opens a session, or `None` when `max_sessions` are already open.
+pub fn open() -> Option<Session> {
+    let max_sessions = config::current().autocomplete_stream.max_sessions;
+    let mut sessions = sessions();
+    if sessions.len() >= max_sessions {
+        return None;
+    }
+    let id = Uuid::new_v4().to_string();
+    let (sender, queries) = watch::channel(String::new());
+    sessions.insert(id.clone(), sender);
+    info!("Opened autocomplete stream {}", id);
+    Some(Session { id, queries })
+}
+
+/// Hands `q` to the session's stream. `false` when there is no such
+/// session.
+pub fn update(id: &str, q: &str) -> bool {
+    match sessions().get(id) {
+        Some(sender) => {
+            sender.send_replace(q.to_string());
+            true
+        }
+        None => false,
+    }
+}
+
+/// One event in the `text/event-stream` format.
+pub fn event(name: &str, data: &Value) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// Body of a session: a `session` event with the id, then one event per
+/// query update as named and built by `answer`, with keep-alive comments
+/// in between.
+pub fn stream<F>(session: Session, answer: F) -> impl Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    F: Fn(&str) -> (&'static str, Value) + 'static,
+{
+    let settings = config::current().autocomplete_stream.clone();
+    let keep_alive = Duration::from_secs(settings.keep_alive_secs.max(1));
+    let idle_timeout = Duration::from_secs(settings.idle_timeout_secs);
+    let opened = event("session", &json!({ "session": session.id }));
+
+    let state = (session, answer, Instant::now());
+    let updates = stream::unfold(
+        state,
+        move |(mut session, answer, last_update)| async move {
+            let idle_left = idle_timeout.saturating_sub(last_update.elapsed());
+            if idle_left.is_zero() {
+                info!("Autocomplete stream {} went idle", session.id);
+                return None;
+            }
+            tokio::select! {
+                changed = session.queries.changed() => {
+                    changed.ok()?;
+                    let q = session.queries.borrow_and_update().clone();
+                    let (name, data) = answer(&q);
+                    let chunk = event(name, &data);
+                    Some((Ok(chunk), (session, answer, Instant::now())))
+                }
+                _ = tokio::time::sleep(keep_alive.min(idle_left)) => {
+                    let chunk = Bytes::from_static(b": keep-alive\n\n");
+                    Some((Ok(chunk), (session, answer, last_update)))
+                }
+            }
+        },
+    );
+    stream::once(async { Ok(opened) }).chain(updates)
+}
@@ -0,0 +1,140 @@
+//! Versioned routing
+//!
+//! Every endpoint is served under its version's prefix, `/v1/search`, and
+//! for existing clients also at the unversioned path, which answers exactly
+//! like `/v1`. Handlers produce one response shape. A breaking change to a
+//! shape ships as a new [`ApiVersion`]: add the variant, mount the routes
+//! under its prefix in `main` and give it a [`ResponseMapper`] that turns
+//! the `/v1` body into the new one. Responses say which version answered
+//! in `API-Version`.
+
+use actix_web::body::{to_bytes, BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use serde_json::Value;
+
+/// Version that answered a request.
+pub const VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+
+/// Turns a `/v1` JSON body of the route at `path` (unversioned, e.g.
+/// `/search`) into another version's shape.
+pub type ResponseMapper = fn(path: &str, body: Value) -> Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    /// Every served version, oldest first.
+    pub const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+    /// What the unversioned paths answer as.
+    pub const LEGACY: ApiVersion = ApiVersion::V1;
+
+    pub fn number(&self) -> u32 {
+        match self {
+            ApiVersion::V1 => 1,
+        }
+    }
+
+    /// Path prefix the version's routes are mounted under.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+        }
+    }
+
+    /// Mapper from the handlers' `/v1` bodies, `None` when the version
+    /// serves them as is.
+    pub fn mapper(&self) -> Option<ResponseMapper> {
+        match self {
+            ApiVersion::V1 => None,
+        }
+    }
+
+    /// The version a request path is served as, [`Self::LEGACY`] for
+    /// unversioned paths.
+    pub fn of_path(path: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|version| strip(path, version.prefix()).is_some())
+            .unwrap_or(Self::LEGACY)
+    }
+}
+
+fn strip<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+/// `path` without its version prefix, so `/v1/search` and `/search` are the
+/// same route for settings that list paths.
+pub fn unversioned(path: &str) -> &str {
+    match strip(path, ApiVersion::of_path(path).prefix()) {
+        Some("") => "/",
+        Some(rest) => rest,
+        None => path,
+    }
+}
+
+/// Middleware for the scopes of `version`, wrapped inside the group's
+/// middleware chain so `ETag`s and logs see the mapped body:
+/// `from_fn(move |req, next| version::handle(version, req, next))`.
+/// Successful JSON bodies go through the version's mapper, streamed and
+/// other bodies pass through.
+pub async fn handle<B: MessageBody>(
+    version: ApiVersion,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let number = HeaderValue::from(version.number());
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let mapper = version
+        .mapper()
+        .filter(|_| res.status().is_success() && is_json)
+        .filter(|_| res.response().body().size() != BodySize::Stream);
+    let Some(mapper) = mapper else {
+        let mut res = res.map_into_left_body();
+        res.headers_mut().insert(VERSION_HEADER, number);
+        return Ok(res);
+    };
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        let response = HttpResponse::InternalServerError().finish();
+        return Ok(ServiceResponse::new(req, response).map_into_right_body());
+    };
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => {
+            let mapped = mapper(unversioned(req.path()), value);
+            serde_json::to_vec(&mapped).map_or(body, Into::into)
+        }
+        Err(_) => body,
+    };
+    res.headers_mut().insert(VERSION_HEADER, number);
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    let res = res.set_body(BoxBody::new(body));
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_prefixes_are_stripped_whole() {
+        assert_eq!(unversioned("/v1/search"), "/search");
+        assert_eq!(unversioned("/v1"), "/");
+        assert_eq!(unversioned("/search"), "/search");
+        assert_eq!(unversioned("/v1x/search"), "/v1x/search");
+        assert_eq!(unversioned("/v10/search"), "/v10/search");
+    }
+}
@@ -0,0 +1,206 @@
+//! WebSocket autocomplete sessions
+//!
+//! `/ws/autocomplete` keeps one socket open for a whole type-ahead input.
+//! The client sends JSON messages tagged with `type`:
+//!
+//! - `{"type": "query", "q": "kerkstr", "id": 7}` asks for suggestions,
+//!   answered with `{"type": "suggestions", "id": 7, "q", "result"}`;
+//! - `{"type": "select", "street": "Kerkstraat", "city": "Amsterdam"}`
+//!   records the suggestion the user picked, later queries rank that street
+//!   (and city) first;
+//! - `{"type": "set", "country": "NL", "city_bias": "Utrecht"}` sets the
+//!   session's biases, a field left out or `null` clears its bias.
+//!
+//! The biases live as long as the socket. Limits and timeouts are shared
+//! with the Server-Sent Events streams, see `autocomplete_stream`.
+
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::api::error::ApiError;
+use crate::config;
+use crate::query::normalize;
+use crate::query::rank::{Preferring, Ranker};
+
+static OPEN: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Query {
+        q: String,
+        #[serde(default)]
+        id: Value,
+    },
+    Select {
+        street: String,
+        city: Option<String>,
+    },
+    Set {
+        country: Option<String>,
+        city_bias: Option<String>,
+    },
+}
+
+/// What a session remembers between queries, all normalized.
+#[derive(Debug, Clone, Default)]
+pub struct SessionState {
+    pub country: Option<String>,
+    pub city_bias: Option<String>,
+    /// Street of the last selected suggestion.
+    pub selected_street: Option<String>,
+}
+
+impl SessionState {
+    /// `ranker` with the session's street and country preferences, or
+    /// `ranker` itself when it has none.
+    pub fn ranker(&self, ranker: Arc<dyn Ranker>) -> Arc<dyn Ranker> {
+        if self.selected_street.is_none() && self.country.is_none() {
+            return ranker;
+        }
+        Arc::new(Preferring {
+            inner: ranker,
+            street: self.selected_street.clone(),
+            country: self.country.clone(),
+        })
+    }
+}
+
+fn normalized(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| normalize::text(&value))
+        .filter(|value| !value.is_empty())
+}
+
+/// A slot among `max_sessions`, given back when dropped.
+#[derive(Debug)]
+pub struct Slot {
+    pub id: String,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        OPEN.fetch_sub(1, Ordering::SeqCst);
+        info!("Closed autocomplete socket {}", self.id);
+    }
+}
+
+/// Takes a session slot, or `None` when `max_sessions` sockets are open.
+pub fn open() -> Option<Slot> {
+    let max_sessions = config::current().autocomplete_stream.max_sessions;
+    OPEN.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+        (open < max_sessions).then_some(open + 1)
+    })
+    .ok()?;
+    let id = Uuid::new_v4().to_string();
+    info!("Opened autocomplete socket {}", id);
+    Some(Slot { id })
+}
+
+/// Serves one socket until the client closes it or goes idle. `answer`
+/// runs a query with the session's state: the `/autocomplete` body, `None`
+/// when nothing matched, or the error to send back.
+pub async fn run<F>(slot: Slot, mut session: Session, mut messages: MessageStream, answer: F)
+where
+    F: Fn(&str, &SessionState) -> Result<Option<Value>, ApiError>,
+{
+    let settings = config::current().autocomplete_stream.clone();
+    let keep_alive = Duration::from_secs(settings.keep_alive_secs.max(1));
+    let idle_timeout = Duration::from_secs(settings.idle_timeout_secs);
+    let mut state = SessionState::default();
+    let mut last_message = Instant::now();
+
+    let opened = json!({ "type": "session", "session": slot.id });
+    if session.text(opened.to_string()).await.is_err() {
+        return;
+    }
+
+    let reason = loop {
+        let idle_left = idle_timeout.saturating_sub(last_message.elapsed());
+        if idle_left.is_zero() {
+            info!("Autocomplete socket {} went idle", slot.id);
+            break Some(CloseReason::from(CloseCode::Normal));
+        }
+        let message = tokio::select! {
+            message = messages.next() => message,
+            _ = tokio::time::sleep(keep_alive.min(idle_left)) => {
+                if session.ping(b"").await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let reply = match message {
+            None => return,
+            Some(Err(e)) => {
+                warn!("Autocomplete socket {} failed: {}", slot.id, e);
+                break Some(CloseReason::from(CloseCode::Protocol));
+            }
+            Some(Ok(Message::Ping(bytes))) => {
+                if session.pong(&bytes).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            Some(Ok(Message::Close(reason))) => break reason,
+            Some(Ok(Message::Text(text))) => {
+                last_message = Instant::now();
+                handle(&text, &mut state, &answer)
+            }
+            Some(Ok(_)) => continue,
+        };
+        if let Some(reply) = reply {
+            if session.text(reply.to_string()).await.is_err() {
+                return;
+            }
+        }
+    };
+    session.close(reason).await.ok();
+}
+
+/// Applies one client message, returning the reply if it gets one.
+fn handle<F>(text: &str, state: &mut SessionState, answer: &F) -> Option<Value>
+where
+    F: Fn(&str, &SessionState) -> Result<Option<Value>, ApiError>,
+{
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(e) => {
+            let mut reply = ApiError::bad_request("invalid_message", e.to_string()).to_json();
+            reply["type"] = json!("error");
+            return Some(reply);
+        }
+    };
+    match message {
+        ClientMessage::Query { q, id } => Some(match answer(&q, state) {
+            Ok(result) => json!({ "type": "suggestions", "id": id, "q": q, "result": result }),
+            Err(error) => {
+                let mut reply = error.to_json();
+                reply["type"] = json!("error");
+                reply["id"] = id;
+                reply["q"] = json!(q);
+                reply
+            }
+        }),
+        ClientMessage::Select { street, city } => {
+            state.selected_street = normalized(Some(street));
+            if let Some(city) = normalized(city) {
+                state.city_bias = Some(city);
+            }
+            None
+        }
+        ClientMessage::Set { country, city_bias } => {
+            state.country = normalized(country);
+            state.city_bias = normalized(city_bias);
+            None
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use places_autocomplete_rs::{cli, config};
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    match config::load_from_file(config::config_path()) {
+        Ok(loaded) => {
+            config::set(loaded);
+        }
+        Err(e) => eprintln!("Failed to read config, using defaults: {}", e),
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = match cli::dispatch(&args).await {
+        Some(code) => code,
+        None => {
+            eprintln!("{}", cli::USAGE);
+            2
+        }
+    };
+    std::process::exit(code);
+}
@@ -1 +1,24 @@
-pub mod redis_client;
\ No newline at end of file
+#[cfg(feature = "redis")]
+pub mod redis_client;
+pub mod response_cache;
+
+/// Stand-in for builds without the `redis` feature: never configured, so
+/// the response cache stays process local.
+#[cfg(not(feature = "redis"))]
+pub mod redis_client {
+    use serde_json::Value;
+
+    pub struct RedisCache;
+
+    pub static REDIS_CACHE: Option<RedisCache> = None;
+
+    impl RedisCache {
+        pub async fn get(&self, _key: &str) -> Option<Value> {
+            None
+        }
+
+        pub async fn set(&self, _key: &str, _value: &Value) {}
+
+        pub async fn delete_prefix(&self, _prefix: &str) {}
+    }
+}
@@ -0,0 +1,185 @@
+//! Redis backed tier of the response cache.
+//!
+//! Values are stored as JSON strings with `SETEX`. Every call is bounded by
+//! `timeout_ms` and failures are logged and reported as a miss, so an
+//! unreachable Redis degrades to the in-process cache instead of failing
+//! requests. After a failed connect the next attempt waits [`RETRY_AFTER`].
+
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde_json::Value;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::config::{self, RedisCacheConfig};
+
+/// Pause between connection attempts after Redis was unreachable.
+pub const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Time budget for a flush, which scans the keyspace and is not on the
+/// request path.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct RedisCache {
+    client: redis::Client,
+    config: RedisCacheConfig,
+    connection: Mutex<Option<MultiplexedConnection>>,
+    last_failure: Mutex<Option<Instant>>,
+}
+
+lazy_static::lazy_static! {
+    /// L2 tier built from `cache.redis` in the config, `None` when unset or
+    /// when the URL does not parse.
+    pub static ref REDIS_CACHE: Option<RedisCache> = config::current()
+        .cache
+        .redis
+        .clone()
+        .and_then(|config| match RedisCache::new(config) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("Redis cache disabled: {}", e);
+                None
+            }
+        });
+}
+
+impl RedisCache {
+    pub fn new(config: RedisCacheConfig) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        Ok(Self {
+            client,
+            config,
+            connection: Mutex::new(None),
+            last_failure: Mutex::new(None),
+        })
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.config.timeout_ms)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.config.key_prefix, key)
+    }
+
+    async fn connection(&self) -> Option<MultiplexedConnection> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Some(connection.clone());
+        }
+
+        let mut last_failure = self.last_failure.lock().await;
+        if last_failure.is_some_and(|at| at.elapsed() < RETRY_AFTER) {
+            return None;
+        }
+
+        match timeout(
+            self.timeout(),
+            self.client.get_multiplexed_async_connection(),
+        )
+        .await
+        {
+            Ok(Ok(connected)) => {
+                info!("Connected to Redis cache at {}", self.config.url);
+                *last_failure = None;
+                *connection = Some(connected.clone());
+                Some(connected)
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to connect to Redis cache: {}", e);
+                *last_failure = Some(Instant::now());
+                None
+            }
+            Err(_) => {
+                warn!("Timed out connecting to Redis cache");
+                *last_failure = Some(Instant::now());
+                None
+            }
+        }
+    }
+
+    /// Runs `call` bounded by `limit`, dropping the connection on error so
+    /// the next call reconnects.
+    async fn run<T, F, Fut>(&self, what: &str, limit: Duration, call: F) -> Option<T>
+    where
+        F: FnOnce(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = redis::RedisResult<T>>,
+    {
+        let connection = self.connection().await?;
+        match timeout(limit, call(connection)).await {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(e)) => {
+                warn!("Redis cache {} failed: {}", what, e);
+                self.connection.lock().await.take();
+                None
+            }
+            Err(_) => {
+                warn!("Redis cache {} timed out", what);
+                None
+            }
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Value> {
+        let key = self.key(key);
+        let raw: Option<String> = self
+            .run("get", self.timeout(), |mut connection| async move {
+                connection.get(key).await
+            })
+            .await
+            .flatten();
+        raw.and_then(|raw| serde_json::from_str(&raw).ok())
+    }
+
+    pub async fn set(&self, key: &str, value: &Value) {
+        let key = self.key(key);
+        let value = value.to_string();
+        let ttl = self.config.ttl_secs;
+        self.run("set", self.timeout(), |mut connection| async move {
+            connection.set_ex::<_, _, ()>(key, value, ttl).await
+        })
+        .await;
+    }
+
+    /// Deletes every key starting with `prefix` (after the configured key
+    /// prefix). An empty `prefix` clears this deployment's keys.
+    pub async fn delete_prefix(&self, prefix: &str) {
+        let pattern = format!("{}*", escape_pattern(&self.key(prefix)));
+        self.run("flush", FLUSH_TIMEOUT, |mut connection| async move {
+            let mut cursor: u64 = 0;
+            loop {
+                let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(500)
+                    .query_async(&mut connection)
+                    .await?;
+                if !keys.is_empty() {
+                    connection.del::<_, ()>(keys).await?;
+                }
+                if next == 0 {
+                    return Ok(());
+                }
+                cursor = next;
+            }
+        })
+        .await;
+    }
+}
+
+/// Escapes glob characters so a prefix matches literally in `SCAN MATCH`.
+fn escape_pattern(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
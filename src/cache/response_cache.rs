@@ -0,0 +1,297 @@
+//! Response cache
+//!
+//! Search envelopes are cached before content negotiation, keyed by
+//! endpoint, the sorted query parameters and the dataset version
+//! (`search:limit=5&street=kalver@1f0c...`). The endpoint prefix lets
+//! operators flush one endpoint at a time. The version suffix means a
+//! response cached for one dataset is never served for another, also by a
+//! replica still running the old data against a shared Redis, and the
+//! whole cache is still dropped on reload since config changes count too.
+//!
+//! There are two tiers: the in-process moka cache (L1) and, when
+//! `cache.redis` is configured, a Redis cache shared by all replicas (L2).
+//! Writes go to both tiers, an L1 miss falls through to L2 and an L2 hit is
+//! copied back into L1. Each tier has its own TTL.
+//!
+//! moka does not keep statistics itself, so hits, misses and evictions are
+//! counted here and reported through [`stats`].
+
+use moka::future::Cache;
+use moka::notification::RemovalCause;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::cache::redis_client::REDIS_CACHE;
+use crate::config;
+use crate::query::error::QueryError;
+use crate::query::{dataset_version, normalize};
+use crate::SharedCache;
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static L2_HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+static INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+static VERSION_COUNTS: std::sync::Mutex<BTreeMap<String, VersionCounts>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+lazy_static::lazy_static! {
+    /// Process wide response cache, shared with the handlers via `app_data`.
+    pub static ref RESPONSE_CACHE: SharedCache = Arc::new(Mutex::new(
+        Cache::builder()
+            .time_to_live(Duration::from_secs(config::current().cache.l1_ttl_secs))
+            .support_invalidation_closures()
+            .weigher(weigh)
+            .eviction_listener(count_removal)
+            .build(),
+    ));
+}
+
+/// Rough size of an entry: its key plus the length of its value as JSON,
+/// measured when the entry is inserted. moka keeps the running total, so
+/// [`stats`] never has to look at the entries.
+// moka passes the key as it is stored
+#[allow(clippy::ptr_arg)]
+fn weigh(key: &String, value: &Value) -> u32 {
+    u32::try_from(key.len() + json_len(value)).unwrap_or(u32::MAX)
+}
+
+/// Length of `value` serialized as compact JSON, not counting escapes,
+/// without serializing it.
+fn json_len(value: &Value) -> usize {
+    match value {
+        Value::Null => 4,
+        Value::Bool(true) => 4,
+        Value::Bool(false) => 5,
+        Value::Number(number) => number.to_string().len(),
+        Value::String(text) => text.len() + 2,
+        Value::Array(items) => {
+            2 + items.len().saturating_sub(1) + items.iter().map(json_len).sum::<usize>()
+        }
+        Value::Object(fields) => {
+            2 + fields.len().saturating_sub(1)
+                + fields
+                    .iter()
+                    .map(|(key, value)| key.len() + 3 + json_len(value))
+                    .sum::<usize>()
+        }
+    }
+}
+
+fn count_removal(_key: Arc<String>, _value: Value, cause: RemovalCause) {
+    match cause {
+        RemovalCause::Expired | RemovalCause::Size => {
+            EVICTIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        RemovalCause::Explicit => {
+            INVALIDATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        RemovalCause::Replaced => {}
+    }
+}
+
+/// Lookups for keys of one dataset version.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct VersionCounts {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entry_count: u64,
+    /// Lookups answered by either tier.
+    pub hits: u64,
+    /// Lookups that missed L1 and were answered by Redis.
+    pub l2_hits: u64,
+    pub misses: u64,
+    /// `hits / (hits + misses)`, `None` before the first lookup.
+    pub hit_ratio: Option<f64>,
+    /// Entries dropped by TTL or capacity.
+    pub evictions: u64,
+    /// Entries dropped by a flush.
+    pub invalidations: u64,
+    /// Rough size of keys plus values as JSON in L1, totalled on insert.
+    pub estimated_memory_bytes: u64,
+    /// Whether the Redis tier is configured.
+    pub redis: bool,
+    /// Hits and misses per dataset version, to verify a data swap moved
+    /// traffic to fresh keys. A reload drops the versions no longer loaded.
+    pub versions: BTreeMap<String, VersionCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys_sample: Option<Vec<String>>,
+}
+
+/// Cache key for an endpoint and its query parameters under the active
+/// dataset version. Parameters go through `query::normalize`, so
+/// `?postal_code=1017 ge&limit=5` and `?limit=5&postal_code=1017GE` share
+/// an entry.
+pub fn cache_key(endpoint: &str, params: &HashMap<String, String>) -> Result<String, QueryError> {
+    let query = normalize::params(params)
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    Ok(format!("{}:{}@{}", endpoint, query, dataset_version()?))
+}
+
+/// Drops the lookup counts of every dataset version but the loaded one,
+/// called after a data swap.
+pub fn retain_loaded_version() -> Result<(), QueryError> {
+    let loaded = dataset_version()?;
+    VERSION_COUNTS
+        .lock()
+        .expect("Failed to acquire version counts lock")
+        .retain(|version, _| *version == loaded);
+    Ok(())
+}
+
+/// Counts a lookup of `key` for the version after its last `@`.
+fn count_version(key: &str, hit: bool) {
+    let version = key.rsplit_once('@').map_or("", |(_, version)| version);
+    let mut counts = VERSION_COUNTS
+        .lock()
+        .expect("Failed to acquire version counts lock");
+    let counts = counts.entry(version.to_string()).or_default();
+    if hit {
+        counts.hits += 1;
+    } else {
+        counts.misses += 1;
+    }
+}
+
+pub async fn get(cache: &SharedCache, key: &str) -> Option<Value> {
+    if let Some(value) = cache.lock().await.get(key).await {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        count_version(key, true);
+        return Some(value);
+    }
+
+    let value = match REDIS_CACHE.as_ref() {
+        Some(redis) => redis.get(key).await,
+        None => None,
+    };
+    count_version(key, value.is_some());
+    match &value {
+        Some(value) => {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            L2_HITS.fetch_add(1, Ordering::Relaxed);
+            cache
+                .lock()
+                .await
+                .insert(key.to_string(), value.clone())
+                .await;
+        }
+        None => {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    value
+}
+
+/// Writes through to both tiers.
+pub async fn insert(cache: &SharedCache, key: String, value: Value) {
+    if let Some(redis) = REDIS_CACHE.as_ref() {
+        redis.set(&key, &value).await;
+    }
+    cache.lock().await.insert(key, value).await;
+}
+
+/// Drops every cached response.
+pub async fn flush_all(cache: &SharedCache) {
+    {
+        let cache = cache.lock().await;
+        cache.invalidate_all();
+        cache.run_pending_tasks().await;
+    }
+    if let Some(redis) = REDIS_CACHE.as_ref() {
+        redis.delete_prefix("").await;
+    }
+    info!("Flushed response cache");
+}
+
+/// Drops cached responses whose key starts with `prefix`, e.g. `search:` or
+/// `search_by_coordinates:`.
+pub async fn flush_prefix(cache: &SharedCache, prefix: &str) -> Result<(), String> {
+    {
+        let cache = cache.lock().await;
+        let owned_prefix = prefix.to_string();
+        cache
+            .invalidate_entries_if(move |key, _| key.starts_with(&owned_prefix))
+            .map_err(|e| e.to_string())?;
+        cache.run_pending_tasks().await;
+    }
+    if let Some(redis) = REDIS_CACHE.as_ref() {
+        redis.delete_prefix(prefix).await;
+    }
+    info!("Flushed response cache entries with prefix '{}'", prefix);
+    Ok(())
+}
+
+/// Counters and size of the cache. `sample` adds up to that many keys.
+pub async fn stats(cache: &SharedCache, sample: Option<usize>) -> CacheStats {
+    let cache = cache.lock().await;
+    cache.run_pending_tasks().await;
+
+    let keys_sample = sample.map(|count| {
+        cache
+            .iter()
+            .take(count)
+            .map(|(key, _)| key.to_string())
+            .collect()
+    });
+
+    let hits = HITS.load(Ordering::Relaxed);
+    let misses = MISSES.load(Ordering::Relaxed);
+    let lookups = hits + misses;
+    CacheStats {
+        entry_count: cache.entry_count(),
+        hits,
+        l2_hits: L2_HITS.load(Ordering::Relaxed),
+        misses,
+        hit_ratio: (lookups > 0).then(|| hits as f64 / lookups as f64),
+        evictions: EVICTIONS.load(Ordering::Relaxed),
+        invalidations: INVALIDATIONS.load(Ordering::Relaxed),
+        estimated_memory_bytes: cache.weighted_size(),
+        redis: REDIS_CACHE.is_some(),
+        versions: VERSION_COUNTS
+            .lock()
+            .expect("Failed to acquire version counts lock")
+            .clone(),
+        keys_sample,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_len_matches_serialized_length() {
+        let value = json!({
+            "entries": [{ "street": "Kerkstraat", "house_number": "12", "latitude": 52.365 }],
+            "total_entries": 1,
+            "truncated": false,
+            "timings": null,
+            "empty": [],
+            "nested": {}
+        });
+        assert_eq!(json_len(&value), value.to_string().len());
+    }
+
+    #[tokio::test]
+    async fn stats_report_the_weighted_size() {
+        let cache: SharedCache = Arc::new(Mutex::new(Cache::builder().weigher(weigh).build()));
+        let value = json!({ "street": "Kerkstraat" });
+        let size = ("key".len() + value.to_string().len()) as u64;
+        cache.lock().await.insert("key".to_string(), value).await;
+        assert_eq!(stats(&cache, None).await.estimated_memory_bytes, size);
+    }
+}
@@ -0,0 +1,40 @@
+//! Offline commands, available as `places-cli <command>` and as the first
+//! argument to the server binary.
+
+use crate::{diff, snapshot, validate};
+
+pub const USAGE: &str = "usage: places-cli <command> [args]
+
+commands:
+  replay         send a query log to an instance and report changed responses
+  diff-results   compare query results between two dataset versions
+  validate       load a data folder with all checks and report problems
+  snapshot       write a data folder as a binary snapshot, or upgrade one";
+
+/// Names [`dispatch`] answers to.
+pub const COMMANDS: [&str; 4] = ["replay", "diff-results", "validate", "snapshot"];
+
+/// Whether `args` starts with one of [`COMMANDS`].
+pub fn is_command(args: &[String]) -> bool {
+    args.first()
+        .is_some_and(|command| COMMANDS.contains(&command.as_str()))
+}
+
+/// Runs the command named by `args[0]`. `None` when `args` does not start
+/// with a known command, otherwise the exit code.
+pub async fn dispatch(args: &[String]) -> Option<i32> {
+    let (command, args) = args.split_first()?;
+    match command.as_str() {
+        #[cfg(feature = "replay")]
+        "replay" => Some(crate::replay::run(args).await),
+        #[cfg(not(feature = "replay"))]
+        "replay" => {
+            eprintln!("replay is not available, this binary was built without the replay feature");
+            Some(2)
+        }
+        "diff-results" => Some(diff::run(args)),
+        "validate" => Some(validate::run(args)),
+        "snapshot" => Some(snapshot::run(args)),
+        _ => None,
+    }
+}
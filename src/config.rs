@@ -0,0 +1,912 @@
+//! Runtime configuration
+//!
+//! The config is a JSON file (path from `XLX_PLACES_AUTOCOMPLETE_CONFIG`,
+//! defaulting to `./config.json`). Every field has a default, so a missing
+//! file or a partial file is fine. The active config lives behind a global
+//! `RwLock<Arc<Config>>` so handlers can grab a cheap snapshot and SIGHUP can
+//! swap in a freshly read copy.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env::var;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::fields::Field;
+use ipnet::IpNet;
+
+/// Environment variable holding the path to the config file.
+pub const CONFIG_PATH_ENV: &str = "XLX_PLACES_AUTOCOMPLETE_CONFIG";
+
+/// Config file used when `XLX_PLACES_AUTOCOMPLETE_CONFIG` is not set.
+pub const DEFAULT_CONFIG_PATH: &str = "./config.json";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// `EnvFilter` directive, e.g. `info` or `places_autocomplete_rs=debug`.
+    pub log_level: String,
+    /// Folder with the CSV shards loaded into `LocationData`.
+    pub data_dir: String,
+    /// Reload `data_dir` as well when the config is re-read on SIGHUP.
+    pub reload_data_on_sighup: bool,
+    /// Fail loading on malformed rows, duplicate ids, missing coordinates
+    /// or unexpected columns instead of skipping them. `--strict` on the
+    /// command line turns it on regardless of this value.
+    pub strict_loading: bool,
+    /// Value of the `Server` response header. `null` leaves the header out.
+    pub server_header: Option<String>,
+    /// CSV file (`old,new`) mapping merged or renamed municipalities to
+    /// their current name, see `query::aliases`.
+    pub municipality_aliases_path: Option<String>,
+    /// CSV file (`spelling,variant,whole_word`) of interchangeable street
+    /// name spellings, see `query::variants`. `null` uses the built-in
+    /// `ij`/`y` and `sint`/`st` rules.
+    pub street_variants_path: Option<String>,
+    /// JSON file the delivery zones uploaded under `/admin/zones` are kept
+    /// in, see `query::zones`. `null` keeps them in memory only.
+    pub zones_path: Option<String>,
+    pub limits: LimitsConfig,
+    pub routes: RoutesConfig,
+    pub proxy: ProxyConfig,
+    pub fields: FieldsConfig,
+    pub coordinates: CoordinatesConfig,
+    pub cache: CacheConfig,
+    pub search: SearchConfig,
+    pub usage: UsageConfig,
+    pub analytics: AnalyticsConfig,
+    pub query_log: QueryLogConfig,
+    pub idempotency: IdempotencyConfig,
+    pub autocomplete_stream: AutocompleteStreamConfig,
+    pub health: HealthConfig,
+    pub freshness: FreshnessConfig,
+    pub grpc: GrpcConfig,
+    pub elevation: ElevationConfig,
+    /// Named filters clients can apply with `preset=<name>`.
+    pub presets: BTreeMap<String, PresetConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Number of entries returned when the client does not pass `limit`.
+    pub default_limit: usize,
+    /// Upper bound for a client supplied `limit`.
+    pub max_limit: usize,
+    /// Server side time budget per query in milliseconds. Client deadlines
+    /// can only shorten it.
+    pub request_timeout_ms: Option<u64>,
+    /// `unique_street_only` when the client does not pass it.
+    pub unique_street_only: bool,
+    /// Number of unique streets `/search_by_coordinates` returns.
+    pub coordinate_search_cap: usize,
+    /// Largest `radius_m` accepted by `/search_within_radius`.
+    pub max_radius_m: f64,
+    /// Rows a postal code prefix search collects before it stops, so a
+    /// one digit prefix does not gather a tenth of the country. The total is
+    /// then estimated and the section marked `truncated`.
+    pub postal_prefix_cap: usize,
+    /// Farthest an address may be from the position for
+    /// `/reverse_geocode` to return it. Clients can only lower it with
+    /// `max_distance_km`.
+    pub reverse_max_distance_km: f64,
+    /// Largest number of queries accepted by `POST /search/batch`.
+    pub max_batch_size: usize,
+    /// Largest number of points accepted by `POST /reverse/bulk`.
+    pub max_reverse_bulk_points: usize,
+    /// Time budget of one `POST /reverse/bulk/stream` request in
+    /// milliseconds, in place of `request_timeout_ms`. Client deadlines can
+    /// only shorten it.
+    pub stream_time_budget_ms: u64,
+    /// Overrides per route, keyed by route name (`search`,
+    /// `search_by_coordinates`, `search_within_radius`, `search_in_bbox`,
+    /// `search_by_neighborhood`, `autocomplete`).
+    pub routes: BTreeMap<String, RouteLimitsConfig>,
+}
+
+/// Per-route overrides of [`LimitsConfig`]; unset fields fall back to it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RouteLimitsConfig {
+    pub default_limit: Option<usize>,
+    pub max_limit: Option<usize>,
+    pub unique_street_only: Option<bool>,
+    pub coordinate_search_cap: Option<usize>,
+}
+
+/// Effective limits for one route, see [`LimitsConfig::for_route`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteLimits {
+    pub default_limit: usize,
+    pub max_limit: usize,
+    pub unique_street_only: bool,
+    pub coordinate_search_cap: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Reverse proxies whose `X-Forwarded-For`/`Forwarded` headers are
+    /// believed, e.g. `["10.0.0.0/8", "127.0.0.1/32"]`.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// CORS, auth and logging per route group, see `api::middleware`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RoutesConfig {
+    /// Autocomplete and search endpoints.
+    pub public: RouteGroupConfig,
+    /// Everything under `/admin`.
+    pub admin: RouteGroupConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RouteGroupConfig {
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    /// Assign and echo `X-Request-Id`.
+    pub request_id: bool,
+    /// Log one line per request under the `access_log` target.
+    pub access_log: bool,
+    /// Count requests per client for `/admin/usage/timeseries`.
+    pub usage: bool,
+    /// Append requests to `query_log.path`, when set.
+    pub query_log: bool,
+    /// Replay stored responses for retried write requests carrying an
+    /// `Idempotency-Key` header, see `idempotency`.
+    pub idempotency: bool,
+    /// Tag `GET` responses with `ETag` and `Last-Modified` and answer
+    /// `If-None-Match` with 304, see `etag`.
+    pub etag: bool,
+    /// Token bucket per client, see `rate_limit`.
+    pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Tokens a client gets back per second. `None` turns limiting off.
+    pub per_second: Option<f64>,
+    /// Bucket size, the number of requests a client can make in a burst.
+    pub burst: f64,
+    /// What a client is: its IP or the name of its `X-Api-Key`.
+    pub key: RateLimitKey,
+    /// Paths the limit applies to, by default `/search_by_coordinates`.
+    /// Empty limits every path in the group.
+    /// Paths are unversioned and also cover their `/v1` form.
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    Ip,
+    /// The API key name, falling back to the IP for requests without one.
+    ApiKey,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the group cross-origin. `"*"` allows any
+    /// origin, an empty list allows none.
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Accepted `Authorization: Bearer` tokens. The public group is open
+    /// when neither these nor `api_keys` are set; the admin group is closed.
+    pub bearer_tokens: Vec<String>,
+    /// Accepted `X-Api-Key` values by caller name, which the access log
+    /// shows instead of the key. Keys from `api_keys_path` and from
+    /// `XLX_PLACES_AUTOCOMPLETE_<GROUP>_API_KEYS` (`name=key,...`) are
+    /// added when the config is set. Never serialized, so keys stay out of
+    /// `/admin/info` and the startup log.
+    #[serde(skip_serializing)]
+    pub api_keys: BTreeMap<String, String>,
+    /// CSV file with a `name,key` header adding to `api_keys`, re-read on
+    /// SIGHUP.
+    pub api_keys_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FieldsConfig {
+    /// Fields included in responses by default. `None` means all allowed fields.
+    pub default: Option<Vec<Field>>,
+    /// Fields that may be returned at all. `None` means every field.
+    pub allowed: Option<Vec<Field>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CoordinatesConfig {
+    /// Maximum number of decimals in returned coordinates. `None` keeps full
+    /// precision; 3 decimals is roughly 100 m.
+    pub precision: Option<u32>,
+    /// Report the street centroid instead of the address position.
+    pub snap_to_street_centroid: bool,
+    /// Reference systems added to every response as `crs`, e.g.
+    /// `["EPSG:28992"]` for RD New, see `crs`. Clients can add more with the
+    /// `crs` parameter.
+    pub crs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Particles that are optional in street queries and skipped when
+    /// ranking prefix matches, e.g. `"van"` in `Van Rijnstraat`.
+    pub stopwords: Vec<String>,
+    /// Typos tolerated per street query token when the client does not
+    /// pass `max_edits`. 0 turns typo tolerance off.
+    pub default_max_edits: usize,
+    /// Upper bound for a client supplied `max_edits`.
+    pub max_edits_limit: usize,
+    /// Token characters needed per tolerated typo, so with 4 `"dam"` must
+    /// match exactly and `"kalverstaat"` may contain two typos.
+    pub chars_per_edit: usize,
+    /// Lowercase abbreviations written out at the end of a compound word or
+    /// as a word with a period: `"str"` turns `kerkstr` and `str.` into
+    /// `straat`.
+    pub abbreviations: BTreeMap<String, String>,
+    /// Lowercase abbreviations only written out as a whole word followed by
+    /// a period: `"burg"` turns `burg.` into `burgemeester`.
+    pub word_abbreviations: BTreeMap<String, String>,
+    /// Registered `Normalizer` folding names, see `query::registry`.
+    /// Applies to the data from the next reload.
+    pub normalizer: String,
+    /// Registered `Tokenizer` splitting names, `dutch` or `simple` built
+    /// in. Applies to the data from the next reload.
+    pub tokenizer: String,
+    pub ranking: RankingConfig,
+    /// Shape of postal code results when the client passes no `collapse`.
+    pub collapse: Collapse,
+}
+
+/// Whether postal code results fold the rows of one street into a single
+/// entry with its `house_numbers`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Collapse {
+    /// `entry` + `house_numbers` when every row is on one street, `entries`
+    /// otherwise.
+    #[default]
+    Auto,
+    /// `entries` with one entry per street, each with its `house_numbers`.
+    Always,
+    /// `entries` with one entry per row.
+    Never,
+}
+
+impl Collapse {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Collapse::Auto),
+            "always" => Some(Collapse::Always),
+            "never" => Some(Collapse::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Which `query::rank::Ranker` orders street results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingStrategy {
+    /// Match quality only.
+    #[default]
+    Lexical,
+    /// Match quality, then distance to `ranking.origin`.
+    Distance,
+    /// Match quality, then the number of addresses on the street.
+    Popularity,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RankingConfig {
+    pub strategy: RankingStrategy,
+    /// `[latitude, longitude]` the `distance` strategy favours streets
+    /// near. Without it `distance` ranks like `lexical`.
+    pub origin: Option<(f64, f64)>,
+    /// Distance in km that weighs as much as one match kind step, e.g. an
+    /// exact match this far away ties with a prefix match next door.
+    pub distance_scale_km: f64,
+    /// Penalty of a street with a single address under `popularity`,
+    /// shrinking as the street has more.
+    pub popularity_weight: f64,
+}
+
+/// In-memory per-client request accounting, see `api::middleware::usage`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// Width of one bucket in seconds.
+    pub bucket_secs: u64,
+    /// How long buckets are kept, also the largest queryable window.
+    pub retention_secs: u64,
+    /// Clients tracked at once. New clients beyond this are not counted
+    /// until idle ones expire.
+    pub max_clients: usize,
+}
+
+/// In-memory query analytics, see `api::analytics`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Distinct zero-result queries kept. When full, the one not seen for
+    /// the longest time is dropped. 0 turns tracking off.
+    pub zero_results_capacity: usize,
+}
+
+/// Replayable request log, see `query_log`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QueryLogConfig {
+    /// JSONL file requests are appended to. `None` turns the log off.
+    pub path: Option<String>,
+}
+
+/// Stored responses for `Idempotency-Key` retries.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdempotencyConfig {
+    /// How long a stored response is replayed for a retried key, and how
+    /// long an attempt that never finished keeps its key busy.
+    pub ttl_secs: u64,
+    /// Stored responses kept. When full, the oldest is dropped.
+    pub max_entries: usize,
+}
+
+/// Autocomplete sessions over Server-Sent Events and WebSockets, see
+/// `api::sse` and `api::ws`. `max_sessions` applies to each separately.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AutocompleteStreamConfig {
+    /// Open streams at once. Further clients get 503.
+    pub max_sessions: usize,
+    /// Seconds between keep-alive comments, so proxies do not cut an idle
+    /// stream.
+    pub keep_alive_secs: u64,
+    /// A stream without query updates for this many seconds is closed.
+    pub idle_timeout_secs: u64,
+}
+
+/// Readiness checks, see `api::health`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Loaded data older than this many seconds reports as degraded, with
+    /// a 503 on `/readyz`. Catches refresh jobs that stopped running.
+    /// `None` turns the check off.
+    pub max_data_age_secs: Option<u64>,
+    /// Queries with known answers, run after every load and reload. While
+    /// any fails `/readyz` answers 503, see `query::canary`.
+    pub canaries: Vec<CanaryConfig>,
+}
+
+/// How long clients may cache search responses, see `api::freshness`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FreshnessConfig {
+    /// How often new data is published, e.g. `86400` for a nightly export.
+    /// Responses may be cached until the data is that old. `None` leaves
+    /// the hints out.
+    pub refresh_interval_secs: Option<u64>,
+    /// Lifetime given once the expected refresh is overdue, so clients
+    /// check back soon.
+    pub overdue_max_age_secs: u64,
+}
+
+/// One canary: a postal code lookup or a street search, and what its
+/// results must contain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CanaryConfig {
+    /// Shown in logs and on `/readyz`. Defaults to the query.
+    pub name: Option<String>,
+    /// Full postal code to look up, e.g. `1017GE`.
+    pub postal_code: Option<String>,
+    /// Street search query, used when `postal_code` is not set.
+    pub street: Option<String>,
+    /// Some result must be on this street.
+    pub expect_street: Option<String>,
+    /// Some result must be in this city.
+    pub expect_city: Option<String>,
+    /// Fewest results the query must return.
+    pub min_results: usize,
+}
+
+/// gRPC server, see `grpc`. Only used by binaries built with the `grpc`
+/// feature. Calls are authenticated, rate limited and counted with the
+/// `routes.public` settings.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    /// Address to serve on, e.g. `0.0.0.0:50051`. `None` turns the server
+    /// off. Read at startup only.
+    pub listen: Option<String>,
+}
+
+/// Elevation of reverse geocoded addresses and units, see `elevation`.
+/// Only used by binaries built with the `elevation` feature. Read at startup
+/// only.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ElevationConfig {
+    /// Folder of ESRI ASCII grid tiles (`.asc`) in WGS84 degrees, e.g. an
+    /// AHN export. Asked first.
+    pub tiles_dir: Option<String>,
+    /// Elevation API asked for points outside the tiles, with `{latitude}`
+    /// and `{longitude}` in place of the position, e.g.
+    /// `https://api.open-elevation.com/api/v1/lookup?locations={latitude},{longitude}`.
+    pub url: Option<String>,
+    /// JSON pointer to the metres in the API's response.
+    pub pointer: String,
+    /// Time the API gets per point in milliseconds.
+    pub timeout_ms: u64,
+    /// Positions whose elevation is kept in memory.
+    pub cache_capacity: u64,
+    /// How long a looked up elevation is kept in seconds.
+    pub cache_ttl_secs: u64,
+}
+
+/// Server-side filter referenced by name. All given parts must match.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PresetConfig {
+    /// Row city must be one of these.
+    pub cities: Vec<String>,
+    /// Row postal code must start with one of these, e.g. `"1017"`.
+    pub postal_prefixes: Vec<String>,
+    /// Any further condition in the `filter` syntax, see `query::filter`.
+    pub filter: Option<String>,
+}
+
+/// Response cache tiers, see `cache::response_cache`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// TTL of the in-process (L1) tier in seconds.
+    pub l1_ttl_secs: u64,
+    /// Shared Redis (L2) tier. `None` keeps the cache process local.
+    pub redis: Option<RedisCacheConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RedisCacheConfig {
+    /// e.g. `redis://127.0.0.1:6379/0`. Read once, on first use.
+    pub url: String,
+    /// TTL of the Redis (L2) tier in seconds.
+    pub ttl_secs: u64,
+    /// Prepended to every key so several deployments can share a Redis.
+    pub key_prefix: String,
+    /// Redis calls slower than this count as a miss instead of stalling
+    /// the request.
+    pub timeout_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            data_dir: "./data_split".to_string(),
+            reload_data_on_sighup: false,
+            strict_loading: false,
+            server_header: Some("XYLEX/0".to_string()),
+            municipality_aliases_path: None,
+            street_variants_path: None,
+            zones_path: None,
+            limits: LimitsConfig::default(),
+            routes: RoutesConfig::default(),
+            proxy: ProxyConfig::default(),
+            fields: FieldsConfig::default(),
+            coordinates: CoordinatesConfig::default(),
+            cache: CacheConfig::default(),
+            search: SearchConfig::default(),
+            usage: UsageConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            query_log: QueryLogConfig::default(),
+            idempotency: IdempotencyConfig::default(),
+            autocomplete_stream: AutocompleteStreamConfig::default(),
+            health: HealthConfig::default(),
+            freshness: FreshnessConfig::default(),
+            grpc: GrpcConfig::default(),
+            elevation: ElevationConfig::default(),
+            presets: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for FreshnessConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_secs: None,
+            overdue_max_age_secs: 300,
+        }
+    }
+}
+
+impl Default for ElevationConfig {
+    fn default() -> Self {
+        Self {
+            tiles_dir: None,
+            url: None,
+            pointer: "/elevation".to_string(),
+            timeout_ms: 500,
+            cache_capacity: 100_000,
+            cache_ttl_secs: 86_400,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            stopwords: [
+                "van", "de", "den", "der", "des", "het", "'t", "'s", "ten", "ter",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            default_max_edits: 1,
+            max_edits_limit: 2,
+            chars_per_edit: 4,
+            abbreviations: [
+                ("str", "straat"),
+                ("ln", "laan"),
+                ("pln", "plein"),
+                ("gr", "gracht"),
+                ("sngl", "singel"),
+                ("kd", "kade"),
+                ("wg", "weg"),
+            ]
+            .into_iter()
+            .map(|(abbreviation, word)| (abbreviation.to_string(), word.to_string()))
+            .collect(),
+            word_abbreviations: [
+                ("burg", "burgemeester"),
+                ("st", "sint"),
+                ("mr", "meester"),
+                ("dr", "doctor"),
+                ("prof", "professor"),
+                ("ir", "ingenieur"),
+                ("jhr", "jonkheer"),
+            ]
+            .into_iter()
+            .map(|(abbreviation, word)| (abbreviation.to_string(), word.to_string()))
+            .collect(),
+            normalizer: "default".to_string(),
+            tokenizer: "dutch".to_string(),
+            ranking: RankingConfig::default(),
+            collapse: Collapse::default(),
+        }
+    }
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            strategy: RankingStrategy::Lexical,
+            origin: None,
+            distance_scale_km: 10.0,
+            popularity_weight: 2.0,
+        }
+    }
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            bucket_secs: 60,
+            retention_secs: 24 * 60 * 60,
+            max_clients: 10_000,
+        }
+    }
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            zero_results_capacity: 10_000,
+        }
+    }
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            postal_code: None,
+            street: None,
+            expect_street: None,
+            expect_city: None,
+            min_results: 1,
+        }
+    }
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 86400,
+            max_entries: 10000,
+        }
+    }
+}
+
+impl Default for AutocompleteStreamConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: 1000,
+            keep_alive_secs: 15,
+            idle_timeout_secs: 300,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            l1_ttl_secs: 60 * 60 * 5000,
+            redis: None,
+        }
+    }
+}
+
+impl Default for RedisCacheConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379/0".to_string(),
+            ttl_secs: 60 * 60,
+            key_prefix: "places_autocomplete:".to_string(),
+            timeout_ms: 100,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            default_limit: 10,
+            max_limit: 1000,
+            request_timeout_ms: None,
+            unique_street_only: false,
+            coordinate_search_cap: 100,
+            max_radius_m: 5000.0,
+            postal_prefix_cap: 1000,
+            reverse_max_distance_km: 1.0,
+            max_batch_size: 500,
+            max_reverse_bulk_points: 10_000,
+            stream_time_budget_ms: 300_000,
+            routes: BTreeMap::new(),
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Resolves the effective limit for a request, falling back to
+    /// `default_limit` and capping at `max_limit`.
+    pub fn resolve(&self, requested: Option<usize>) -> usize {
+        requested.unwrap_or(self.default_limit).min(self.max_limit)
+    }
+
+    /// Limits for `route` with its overrides applied.
+    pub fn for_route(&self, route: &str) -> RouteLimits {
+        let overrides = self.routes.get(route).cloned().unwrap_or_default();
+        RouteLimits {
+            default_limit: overrides.default_limit.unwrap_or(self.default_limit),
+            max_limit: overrides.max_limit.unwrap_or(self.max_limit),
+            unique_street_only: overrides
+                .unique_street_only
+                .unwrap_or(self.unique_street_only),
+            coordinate_search_cap: overrides
+                .coordinate_search_cap
+                .unwrap_or(self.coordinate_search_cap),
+        }
+    }
+}
+
+impl RouteLimits {
+    /// Same as [`LimitsConfig::resolve`], with the route's limits.
+    pub fn resolve(&self, requested: Option<usize>) -> usize {
+        requested.unwrap_or(self.default_limit).min(self.max_limit)
+    }
+}
+
+impl Default for RoutesConfig {
+    fn default() -> Self {
+        Self {
+            public: RouteGroupConfig {
+                cors: CorsConfig {
+                    allowed_origins: vec!["*".to_string()],
+                },
+                ..RouteGroupConfig::default()
+            },
+            admin: RouteGroupConfig {
+                usage: false,
+                query_log: false,
+                idempotency: true,
+                etag: false,
+                ..RouteGroupConfig::default()
+            },
+        }
+    }
+}
+
+impl Default for RouteGroupConfig {
+    fn default() -> Self {
+        Self {
+            cors: CorsConfig::default(),
+            auth: AuthConfig::default(),
+            request_id: true,
+            access_log: true,
+            usage: true,
+            query_log: true,
+            idempotency: false,
+            etag: true,
+            rate_limit: RateLimitConfig::default(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_second: None,
+            burst: 10.0,
+            key: RateLimitKey::Ip,
+            paths: vec!["/search_by_coordinates".to_string()],
+        }
+    }
+}
+
+impl RoutesConfig {
+    fn load_api_keys(&mut self) {
+        self.public.auth.load_api_keys("PUBLIC");
+        self.admin.auth.load_api_keys("ADMIN");
+    }
+}
+
+impl AuthConfig {
+    /// Adds the keys from `api_keys_path` and the group's environment
+    /// variable. Unreadable entries are logged and skipped.
+    fn load_api_keys(&mut self, group: &str) {
+        if let Some(path) = &self.api_keys_path {
+            match read_api_keys(path) {
+                Ok(keys) => self.api_keys.extend(keys),
+                Err(e) => warn!("Failed to read API keys from {}: {}", path, e),
+            }
+        }
+        let env = format!("XLX_PLACES_AUTOCOMPLETE_{}_API_KEYS", group);
+        for entry in var(&env).unwrap_or_default().split(',') {
+            match entry.trim().split_once('=') {
+                Some((name, key)) if !name.is_empty() && !key.is_empty() => {
+                    self.api_keys.insert(name.to_string(), key.to_string());
+                }
+                _ if entry.trim().is_empty() => {}
+                _ => warn!("Ignoring malformed entry in {}, expected name=key", env),
+            }
+        }
+    }
+
+    /// Name of the caller holding `key`. Every key is compared, in
+    /// constant time, so the time taken says nothing about which keys exist
+    /// or how much of one was guessed.
+    pub fn api_key_name(&self, key: &str) -> Option<&str> {
+        self.api_keys
+            .iter()
+            .fold(None, |found, (name, allowed)| {
+                let matches = constant_time_eq(allowed.as_bytes(), key.as_bytes());
+                found.or(matches.then_some(name.as_str()))
+            })
+    }
+
+    /// Whether `token` is one of the bearer tokens, compared like
+    /// `api_key_name` compares keys.
+    pub fn allows_token(&self, token: &str) -> bool {
+        self.bearer_tokens.iter().fold(false, |found, allowed| {
+            constant_time_eq(allowed.as_bytes(), token.as_bytes()) | found
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.bearer_tokens.is_empty() && self.api_keys.is_empty()
+    }
+}
+
+/// Equality without an early return at the first differing byte. Only the
+/// length shows in the timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn read_api_keys(path: &str) -> Result<Vec<(String, String)>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut keys = Vec::new();
+    for record in reader.deserialize() {
+        let (name, key): (String, String) = record?;
+        keys.push((name.trim().to_string(), key.trim().to_string()));
+    }
+    Ok(keys)
+}
+
+impl CorsConfig {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+/// Path of the config file, taken from `XLX_PLACES_AUTOCOMPLETE_CONFIG`.
+pub fn config_path() -> String {
+    var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+}
+
+/// Reads a config file from disk. A missing file yields the defaults.
+pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn Error + Send + Sync>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        warn!("Config file {} not found, using defaults", path.display());
+        return Ok(Config::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let config: Config = serde_json::from_str(&contents)?;
+    info!("Loaded config from {}", path.display());
+    Ok(config)
+}
+
+/// Returns a snapshot of the active config.
+pub fn current() -> Arc<Config> {
+    CONFIG
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+}
+
+/// Keeps strict loading on across config reloads, see [`force_strict_loading`].
+static STRICT_LOADING_FORCED: AtomicBool = AtomicBool::new(false);
+
+/// Turns on `strict_loading` for the active config and every config set
+/// after it. Used for `--strict`.
+pub fn force_strict_loading() {
+    STRICT_LOADING_FORCED.store(true, Ordering::Relaxed);
+    set(current().as_ref().clone());
+}
+
+/// Replaces the active config, adding API keys from files and the
+/// environment.
+pub fn set(mut config: Config) -> Arc<Config> {
+    config.strict_loading |= STRICT_LOADING_FORCED.load(Ordering::Relaxed);
+    config.routes.load_api_keys();
+    let config = Arc::new(config);
+    match CONFIG.write() {
+        Ok(mut guard) => *guard = config.clone(),
+        Err(poisoned) => *poisoned.into_inner() = config.clone(),
+    }
+    config
+}
+
+/// Re-reads the config file and makes it the active config. On error the
+/// previous config stays active.
+pub fn reload() -> Result<Arc<Config>, Box<dyn Error + Send + Sync>> {
+    let config = load_from_file(config_path())?;
+    Ok(set(config))
+}
@@ -0,0 +1,344 @@
+//! Coordinates in other reference systems
+//!
+//! Rows are stored in WGS84. Responses can carry the position in further
+//! systems next to `latitude` and `longitude`, as
+//! `"crs": {"EPSG:28992": {"x": 121290.33, "y": 487362.04}}`, picked with the
+//! `crs` parameter or `coordinates.crs` in the config. The coordinate
+//! endpoints also take their input in the `crs` system, as `x` and `y`
+//! instead of `latitude` and `longitude`, see [`InputCrs`]. RD New (EPSG:28992),
+//! which most Dutch municipal systems expect, is built in. Other systems are
+//! added by implementing [`CoordinateTransform`], e.g. on top of the `proj`
+//! crate, and registering it at startup:
+//!
+//! ```ignore
+//! crs::register("EPSG:3035", Laea::new());
+//! ```
+//!
+//! Transforms get the position after the coordinate policy, so rounding and
+//! snapping to the street hold for every system.
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+/// Code of the built-in Dutch national grid.
+pub const RD_NEW: &str = "EPSG:28992";
+
+/// Transforms that can be registered, one bit each in a [`CrsSet`].
+const MAX_TRANSFORMS: usize = 32;
+
+/// Turns a WGS84 position into coordinates of another reference system.
+pub trait CoordinateTransform: Debug + Send + Sync {
+    /// `x` and `y` (or easting and northing) for `latitude` and
+    /// `longitude`, `None` when the position is outside the system's area.
+    fn transform(&self, latitude: f64, longitude: f64) -> Option<(f64, f64)>;
+
+    /// WGS84 `latitude` and `longitude` for `x` and `y`, `None` outside the
+    /// system's area or for transforms that only go one way.
+    fn inverse(&self, _x: f64, _y: f64) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+/// RD New with the approximation polynomials of Schreutelaar, accurate to
+/// about a metre, rounded to centimetres. Positions outside the
+/// Netherlands and its coastal waters have no RD coordinates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RdNew;
+
+impl RdNew {
+    /// Reference point in Amersfoort, WGS84 and RD.
+    const ORIGIN: (f64, f64) = (52.155_174_40, 5.387_206_21);
+    const ORIGIN_RD: (f64, f64) = (155_000.0, 463_000.0);
+
+    /// `(p, q, coefficient)` for x, in `dphi^p * dlambda^q`.
+    const X: [(i32, i32, f64); 8] = [
+        (0, 1, 190_094.945),
+        (1, 1, -11_832.228),
+        (2, 1, -114.221),
+        (0, 3, -32.391),
+        (1, 0, -0.705),
+        (3, 1, -2.340),
+        (1, 3, -0.608),
+        (2, 3, 0.148),
+    ];
+    /// `(p, q, coefficient)` for y.
+    const Y: [(i32, i32, f64); 10] = [
+        (1, 0, 309_056.544),
+        (0, 2, 3_638.893),
+        (2, 0, 73.077),
+        (1, 2, -157.984),
+        (3, 0, 59.788),
+        (0, 1, 0.433),
+        (2, 2, -6.439),
+        (1, 1, -0.032),
+        (0, 4, 0.092),
+        (1, 4, -0.054),
+    ];
+    /// `(p, q, coefficient)` for latitude in arc seconds, in
+    /// `dx^p * dy^q`.
+    const LATITUDE: [(i32, i32, f64); 11] = [
+        (0, 1, 3_235.653_89),
+        (2, 0, -32.582_97),
+        (0, 2, -0.247_50),
+        (2, 1, -0.849_78),
+        (0, 3, -0.065_50),
+        (2, 2, -0.017_09),
+        (1, 0, -0.007_38),
+        (4, 0, 0.005_30),
+        (2, 3, -0.000_39),
+        (4, 1, 0.000_33),
+        (1, 1, -0.000_12),
+    ];
+    /// `(p, q, coefficient)` for longitude in arc seconds.
+    const LONGITUDE: [(i32, i32, f64); 12] = [
+        (1, 0, 5_260.529_16),
+        (1, 1, 105.946_84),
+        (1, 2, 2.456_56),
+        (3, 0, -0.818_85),
+        (1, 3, 0.055_94),
+        (3, 1, -0.056_07),
+        (0, 1, 0.011_99),
+        (3, 2, -0.002_56),
+        (1, 4, 0.001_28),
+        (0, 2, 0.000_22),
+        (2, 0, -0.000_22),
+        (5, 0, 0.000_26),
+    ];
+
+    fn polynomial(terms: &[(i32, i32, f64)], a: f64, b: f64) -> f64 {
+        terms
+            .iter()
+            .map(|(p, q, c)| c * a.powi(*p) * b.powi(*q))
+            .sum()
+    }
+}
+
+impl CoordinateTransform for RdNew {
+    fn transform(&self, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+        if !(50.5..=54.0).contains(&latitude) || !(3.0..=7.6).contains(&longitude) {
+            return None;
+        }
+        let dphi = 0.36 * (latitude - Self::ORIGIN.0);
+        let dlambda = 0.36 * (longitude - Self::ORIGIN.1);
+        let round = |value: f64| (value * 100.0).round() / 100.0;
+        Some((
+            round(Self::ORIGIN_RD.0 + Self::polynomial(&Self::X, dphi, dlambda)),
+            round(Self::ORIGIN_RD.1 + Self::polynomial(&Self::Y, dphi, dlambda)),
+        ))
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        if !(-7_000.0..=300_000.0).contains(&x) || !(289_000.0..=629_000.0).contains(&y) {
+            return None;
+        }
+        let dx = (x - Self::ORIGIN_RD.0) * 1e-5;
+        let dy = (y - Self::ORIGIN_RD.1) * 1e-5;
+        Some((
+            Self::ORIGIN.0 + Self::polynomial(&Self::LATITUDE, dx, dy) / 3600.0,
+            Self::ORIGIN.1 + Self::polynomial(&Self::LONGITUDE, dx, dy) / 3600.0,
+        ))
+    }
+}
+
+type Transforms = RwLock<Vec<(String, Arc<dyn CoordinateTransform>)>>;
+
+lazy_static::lazy_static! {
+    static ref TRANSFORMS: Transforms = {
+        let rd: Arc<dyn CoordinateTransform> = Arc::new(RdNew);
+        RwLock::new(vec![(RD_NEW.to_string(), rd)])
+    };
+}
+
+/// Registers `transform` under `code`, replacing any earlier one. Codes
+/// are matched case-insensitively.
+///
+/// # Panics
+///
+/// When 32 transforms are registered already.
+pub fn register(code: &str, transform: impl CoordinateTransform + 'static) {
+    let mut transforms = TRANSFORMS.write().expect("Failed to acquire write lock");
+    let transform: Arc<dyn CoordinateTransform> = Arc::new(transform);
+    match transforms
+        .iter_mut()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+    {
+        Some(entry) => entry.1 = transform,
+        None => {
+            assert!(
+                transforms.len() < MAX_TRANSFORMS,
+                "at most {} coordinate transforms",
+                MAX_TRANSFORMS
+            );
+            transforms.push((code.to_string(), transform));
+        }
+    }
+}
+
+fn find(code: &str) -> Option<(String, Arc<dyn CoordinateTransform>)> {
+    TRANSFORMS
+        .read()
+        .expect("Failed to acquire read lock")
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code.trim()))
+        .cloned()
+}
+
+fn index_of(code: &str) -> Option<usize> {
+    TRANSFORMS
+        .read()
+        .expect("Failed to acquire read lock")
+        .iter()
+        .position(|(known, _)| known.eq_ignore_ascii_case(code.trim()))
+}
+
+/// A set of registered reference systems, small enough to copy around with
+/// the coordinate policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrsSet(u32);
+
+impl CrsSet {
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(&self, other: CrsSet) -> CrsSet {
+        CrsSet(self.0 | other.0)
+    }
+
+    /// Parses a comma separated list of codes. Returns the first unknown
+    /// code as the error.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        list.split(',')
+            .filter(|code| !code.trim().is_empty())
+            .try_fold(CrsSet(0), |set, code| match index_of(code) {
+                Some(index) => Ok(CrsSet(set.0 | 1 << index)),
+                None => Err(code.trim().to_string()),
+            })
+    }
+
+    /// The systems in `coordinates.crs`. Unknown codes are left out with a
+    /// warning.
+    pub fn from_config(codes: &[String]) -> Self {
+        codes
+            .iter()
+            .fold(CrsSet(0), |set, code| match index_of(code) {
+                Some(index) => CrsSet(set.0 | 1 << index),
+                None => {
+                    warn!("Unknown coordinate reference system '{}'", code);
+                    set
+                }
+            })
+    }
+
+    /// The position in each system of the set, by code. Systems whose area
+    /// does not cover the position are left out.
+    pub fn project(&self, latitude: f64, longitude: f64) -> Projections {
+        if self.is_empty() {
+            return Projections(Vec::new());
+        }
+        let transforms = TRANSFORMS.read().expect("Failed to acquire read lock");
+        let projected = transforms
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.0 & 1 << index != 0)
+            .filter_map(|(_, (code, transform))| {
+                let (x, y) = transform.transform(latitude, longitude)?;
+                Some((code.clone(), x, y))
+            })
+            .collect();
+        Projections(projected)
+    }
+}
+
+/// The system input positions are given in, named by the `crs` parameter.
+#[derive(Debug, Clone)]
+pub struct InputCrs {
+    pub code: String,
+    transform: Arc<dyn CoordinateTransform>,
+}
+
+impl InputCrs {
+    /// The one system in a `crs` list. Errors on unknown codes and on
+    /// lists of several systems, which leave the input ambiguous.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let codes: Vec<&str> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .collect();
+        let [code] = codes[..] else {
+            return Err(format!("x and y need a single crs, got '{}'", list));
+        };
+        let (code, transform) = find(code).ok_or_else(|| format!("unknown crs '{}'", code))?;
+        Ok(Self { code, transform })
+    }
+
+    /// WGS84 `latitude` and `longitude` for a position in this system.
+    pub fn to_wgs84(&self, x: f64, y: f64) -> Result<(f64, f64), String> {
+        self.transform.inverse(x, y).ok_or_else(|| {
+            format!(
+                "{} {} is outside {} or cannot be converted",
+                x, y, self.code
+            )
+        })
+    }
+}
+
+/// Coordinates of one position in several systems, serialized as
+/// `{"<code>": {"x": .., "y": ..}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projections(pub Vec<(String, f64, f64)>);
+
+impl Projections {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for Projections {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (code, x, y) in &self.0 {
+            map.serialize_entry(code, &json!({ "x": x, "y": y }))?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rd_new_covers_the_netherlands() {
+        assert_eq!(
+            RdNew.transform(52.155_174_40, 5.387_206_21),
+            Some((155_000.0, 463_000.0))
+        );
+        // Dam square, on the Amsterdam sheet of the RD grid.
+        let (x, y) = RdNew.transform(52.373_1, 4.892_2).unwrap();
+        assert!((121_000.0..121_500.0).contains(&x) && (487_000.0..487_500.0).contains(&y));
+        assert_eq!(RdNew.transform(48.858_4, 2.294_5), None);
+    }
+
+    #[test]
+    fn rd_new_round_trips() {
+        let (x, y) = RdNew.transform(51.922_5, 4.479_2).unwrap();
+        let (latitude, longitude) = RdNew.inverse(x, y).unwrap();
+        assert!((latitude - 51.922_5).abs() < 1e-6 && (longitude - 4.479_2).abs() < 1e-6);
+        assert_eq!(RdNew.inverse(0.0, 0.0), None);
+    }
+
+    #[test]
+    fn unknown_codes_are_rejected() {
+        assert!(!CrsSet::parse("epsg:28992").unwrap().is_empty());
+        assert_eq!(
+            CrsSet::parse("EPSG:28992,EPSG:1"),
+            Err("EPSG:1".to_string())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Request deadlines
+//!
+//! Callers can tell us how long they are willing to wait. The handler turns
+//! that into a [`Deadline`] which long scans in the query layer poll, so they
+//! can stop early and return what they have with `truncated: true` instead
+//! of blowing the caller's timeout.
+
+use actix_web::HttpRequest;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+
+/// Absolute deadline in unix milliseconds.
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Relative time budget in milliseconds.
+pub const TIMEOUT_HEADER: &str = "x-request-timeout";
+
+/// How many items a scan processes between two clock reads.
+pub const CHECK_INTERVAL: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// No deadline, scans run to completion.
+    pub fn none() -> Self {
+        Self(None)
+    }
+
+    pub fn at(instant: Instant) -> Self {
+        Self(Some(instant))
+    }
+
+    pub fn after(timeout: Duration) -> Self {
+        Self(Some(Instant::now() + timeout))
+    }
+
+    /// Converts an absolute wall-clock deadline in unix milliseconds.
+    pub fn from_unix_millis(millis: u64) -> Self {
+        let target = UNIX_EPOCH + Duration::from_millis(millis);
+        let remaining = target
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+        Self::after(remaining)
+    }
+
+    /// The earlier of both deadlines.
+    pub fn min(self, other: Deadline) -> Self {
+        match (self.0, other.0) {
+            (Some(a), Some(b)) => Self(Some(a.min(b))),
+            (a, b) => Self(a.or(b)),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn expired(&self) -> bool {
+        self.0.is_some_and(|instant| Instant::now() >= instant)
+    }
+
+    /// Cheap check for hot loops: only reads the clock every
+    /// [`CHECK_INTERVAL`] iterations.
+    pub fn expired_at(&self, iteration: usize) -> bool {
+        self.0.is_some() && iteration.is_multiple_of(CHECK_INTERVAL) && self.expired()
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|instant| instant.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Deadline the client asked for: the earlier of `X-Request-Deadline` and
+/// `X-Request-Timeout`, without the configured budget.
+pub fn client_deadline(req: &HttpRequest) -> Deadline {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    let absolute = header(DEADLINE_HEADER).map_or_else(Deadline::none, Deadline::from_unix_millis);
+    let relative = header(TIMEOUT_HEADER).map_or_else(Deadline::none, |ms| {
+        Deadline::after(Duration::from_millis(ms))
+    });
+    absolute.min(relative)
+}
+
+/// Deadline from the configured `request_timeout_ms` alone, for work not
+/// tied to one HTTP request.
+pub fn configured_deadline() -> Deadline {
+    config::current()
+        .limits
+        .request_timeout_ms
+        .map_or_else(Deadline::none, |ms| {
+            Deadline::after(Duration::from_millis(ms))
+        })
+}
+
+/// Deadline for a request: the earliest of `X-Request-Deadline`,
+/// `X-Request-Timeout` and the configured `request_timeout_ms`.
+pub fn deadline_for_request(req: &HttpRequest) -> Deadline {
+    client_deadline(req).min(configured_deadline())
+}
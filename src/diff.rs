@@ -0,0 +1,283 @@
+//! `diff-results` command
+//!
+//! Runs a query set against two dataset versions in-process and prints
+//! every query whose answer changed, as one JSON line with the row ids
+//! added, removed or changed per result section. Queries use the query log
+//! format (see [`crate::query_log`]), so a recorded log of production
+//! traffic makes a good regression set. Dataset versions are data folders
+//! as accepted by `data_dir`.
+//!
+//! ```text
+//! places-cli diff-results --old data_v1 --new data_v2 --queries queries.jsonl
+//! ```
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use crate::config;
+use crate::query::places::PlaceKind;
+use crate::query::{
+    limit_section, query_autocomplete_with, query_by_coordinates_with, query_city_with,
+    query_in_bbox_with, query_neighborhood_with, query_place_with, query_postal_code_with,
+    query_street_with, query_within_radius_with, reload_location_data, QueryOptions,
+};
+use crate::query_log::{self, QueryLogEntry};
+
+pub const USAGE: &str =
+    "usage: places-cli diff-results --old <data_dir> --new <data_dir> --queries <queries.jsonl>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffArgs {
+    pub old: String,
+    pub new: String,
+    pub queries: String,
+}
+
+impl DiffArgs {
+    /// Parses the arguments following `diff-results`.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut old = None;
+        let mut new = None;
+        let mut queries = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--old" => old = args.next().cloned(),
+                "--new" => new = args.next().cloned(),
+                "--queries" => queries = args.next().cloned(),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        Ok(Self {
+            old: old.ok_or("missing --old")?,
+            new: new.ok_or("missing --new")?,
+            queries: queries.ok_or("missing --queries")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffSummary {
+    pub compared: usize,
+    pub unchanged: usize,
+    pub changed: usize,
+    /// Queries for endpoints this command cannot run.
+    pub skipped: usize,
+}
+
+/// Row ids that differ in one result section.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Same id, different content.
+    pub changed: Vec<String>,
+}
+
+impl SectionDiff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct QueryDiff<'a> {
+    endpoint: &'a str,
+    params: &'a BTreeMap<String, String>,
+    sections: BTreeMap<String, SectionDiff>,
+}
+
+/// Runs `entry` against the loaded dataset the way its endpoint would,
+/// returning the result sections by name. `None` for unsupported endpoints.
+pub fn execute(entry: &QueryLogEntry) -> Option<BTreeMap<String, Value>> {
+    let params: HashMap<String, String> = entry.params.clone().into_iter().collect();
+    let route = entry.endpoint.trim_start_matches('/');
+    let options = QueryOptions::from_params(route, &params).ok()?;
+    let limits = config::current().limits.for_route(route);
+    let limit = limits.resolve(params.get("limit").and_then(|l| l.parse().ok()));
+    let number = |name: &str| params.get(name).and_then(|v| v.parse::<f64>().ok());
+
+    let mut sections = BTreeMap::new();
+    match route {
+        "search" => {
+            if let Some(postal_code) = params.get("postal_code") {
+                sections.insert(
+                    "postal_code".to_string(),
+                    query_postal_code_with(postal_code, &options).ok()?,
+                );
+            }
+            if let Some(street) = params.get("street") {
+                sections.insert(
+                    "street".to_string(),
+                    query_street_with(street, &options).ok()?,
+                );
+            }
+            for kind in PlaceKind::ALL {
+                if let Some(name) = params.get(kind.name()) {
+                    let city = params.get("city").map(String::as_str);
+                    sections.insert(
+                        kind.name().to_string(),
+                        query_place_with(kind, name, city, &options).ok()?,
+                    );
+                }
+            }
+            let scoping = PlaceKind::ALL
+                .iter()
+                .any(|kind| params.contains_key(kind.name()));
+            if let Some(city) = params.get("city").filter(|_| !scoping) {
+                sections.insert("city".to_string(), query_city_with(city, &options).ok()?);
+            }
+            for section in sections.values_mut() {
+                limit_section(section, limit);
+            }
+        }
+        "search_by_coordinates" => {
+            let section =
+                query_by_coordinates_with(number("latitude")?, number("longitude")?, &options)
+                    .ok()?;
+            sections.insert(route.to_string(), section);
+        }
+        "search_within_radius" => {
+            let section = query_within_radius_with(
+                number("latitude")?,
+                number("longitude")?,
+                number("radius_m")?,
+                limit,
+                &options,
+            )
+            .ok()?;
+            sections.insert(route.to_string(), section);
+        }
+        "search_in_bbox" => {
+            let section = query_in_bbox_with(
+                (number("min_lat")?, number("min_lon")?),
+                (number("max_lat")?, number("max_lon")?),
+                limit,
+                &options,
+            )
+            .ok()?;
+            sections.insert(route.to_string(), section);
+        }
+        "search_by_neighborhood" => {
+            let city = params.get("city").map(String::as_str);
+            let section =
+                query_neighborhood_with(params.get("neighborhood")?, city, limit, &options).ok()?;
+            sections.insert(route.to_string(), section);
+        }
+        "autocomplete" => {
+            let mut response = query_autocomplete_with(params.get("q")?, &options).ok()?;
+            for name in ["postal_code", "street", "city"] {
+                if let Some(mut section) = response.get_mut(name).map(Value::take) {
+                    limit_section(&mut section, limit);
+                    sections.insert(name.to_string(), section);
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(sections)
+}
+
+/// Entries of a section by row id. Entries are rows, or objects with the
+/// row under `entry` (coordinate searches).
+fn rows_by_id(section: &Value) -> BTreeMap<String, &Value> {
+    let entries = section.get("entries").and_then(Value::as_array);
+    let single = section.get("entry").into_iter();
+    entries
+        .into_iter()
+        .flatten()
+        .chain(single)
+        .filter_map(|entry| {
+            let row = entry.get("entry").unwrap_or(entry);
+            Some((row.get("id")?.as_str()?.to_string(), entry))
+        })
+        .collect()
+}
+
+pub fn diff_section(old: Option<&Value>, new: Option<&Value>) -> SectionDiff {
+    let old = old.map(rows_by_id).unwrap_or_default();
+    let new = new.map(rows_by_id).unwrap_or_default();
+    let mut diff = SectionDiff::default();
+    for (id, row) in &old {
+        match new.get(id) {
+            None => diff.removed.push(id.clone()),
+            Some(new_row) if new_row != row => diff.changed.push(id.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.added = new
+        .keys()
+        .filter(|id| !old.contains_key(*id))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn run_all(entries: &[QueryLogEntry]) -> Vec<Option<BTreeMap<String, Value>>> {
+    entries.iter().map(execute).collect()
+}
+
+/// Loads both datasets in turn, runs the queries and prints the changes to
+/// stdout, one JSON object per line.
+pub fn diff_results(args: &DiffArgs) -> Result<DiffSummary, Box<dyn Error + Send + Sync>> {
+    let entries = query_log::read(&args.queries)?;
+
+    reload_location_data(&args.old)?;
+    let old = run_all(&entries);
+    reload_location_data(&args.new)?;
+    let new = run_all(&entries);
+
+    let mut summary = DiffSummary::default();
+    for ((entry, old), new) in entries.iter().zip(old).zip(new) {
+        let (Some(old), Some(new)) = (old, new) else {
+            summary.skipped += 1;
+            continue;
+        };
+        summary.compared += 1;
+        let sections: BTreeMap<String, SectionDiff> = old
+            .keys()
+            .chain(new.keys())
+            .map(|name| (name.clone(), diff_section(old.get(name), new.get(name))))
+            .filter(|(_, diff)| !diff.is_empty())
+            .collect();
+        if sections.is_empty() {
+            summary.unchanged += 1;
+            continue;
+        }
+        summary.changed += 1;
+        let diff = QueryDiff {
+            endpoint: &entry.endpoint,
+            params: &entry.params,
+            sections,
+        };
+        println!("{}", serde_json::to_string(&diff)?);
+    }
+    Ok(summary)
+}
+
+/// Entry point for `diff-results`, returns the exit code: 0 when nothing
+/// changed, 1 when something did, 2 on errors.
+pub fn run(args: &[String]) -> i32 {
+    let args = match DiffArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    match diff_results(&args) {
+        Ok(summary) => {
+            eprintln!(
+                "compared {} queries: {} unchanged, {} changed, {} skipped",
+                summary.compared, summary.unchanged, summary.changed, summary.skipped
+            );
+            i32::from(summary.changed > 0)
+        }
+        Err(e) => {
+            eprintln!("diff-results failed: {}", e);
+            2
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Elevation enrichment
+//!
+//! `/reverse_geocode` and `/units` add `elevation_m` to their entries when
+//! the client passes `elevation=true`. The metres come from an
+//! [`ElevationProvider`]: local ESRI ASCII grid tiles ([`Tiles`]), an
+//! elevation API ([`Api`]), or both with the tiles asked first, all set up
+//! from `elevation` in the config. A deployment with another source, e.g.
+//! GeoTIFF tiles, implements the trait and registers it at startup:
+//!
+//! ```ignore
+//! elevation::register(GeoTiff::open("ahn4")?);
+//! ```
+//!
+//! Looked up positions are cached, rounded to about a metre, for
+//! `elevation.cache_ttl_secs`. Failed lookups are logged and leave
+//! `elevation_m` out rather than failing the response.
+
+use futures::future::{self, BoxFuture};
+use moka::future::Cache;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::{self, ElevationConfig};
+
+#[derive(Debug, Error)]
+pub enum ElevationError {
+    #[error("failed to read elevation tile {path}: {message}")]
+    Tile { path: String, message: String },
+    #[error("elevation API failed: {0}")]
+    Api(String),
+}
+
+/// Metres above sea level (NAP in the Netherlands) at a position.
+pub trait ElevationProvider: Debug + Send + Sync {
+    /// Elevation at `latitude` and `longitude`, `None` where the provider
+    /// has no data.
+    fn elevation(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> BoxFuture<'_, Result<Option<f64>, ElevationError>>;
+}
+
+/// One ESRI ASCII grid in WGS84 degrees.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    columns: usize,
+    rows: usize,
+    west: f64,
+    south: f64,
+    cell_size: f64,
+    no_data: Option<f64>,
+    /// Row-major, northernmost row first.
+    values: Vec<f32>,
+}
+
+impl Grid {
+    /// Parses the `ncols`, `nrows`, `xllcorner`/`xllcenter`,
+    /// `yllcorner`/`yllcenter`, `cellsize` and optional `NODATA_value`
+    /// header and the cell values.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut words = text.split_whitespace().peekable();
+        let (mut columns, mut rows, mut cell_size, mut no_data) = (None, None, None, None);
+        let (mut west, mut south, mut centered) = (None, None, false);
+        while let Some(key) = words.next_if(|word| word.parse::<f64>().is_err()) {
+            let value = words
+                .next()
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| format!("{} needs a number", key))?;
+            match key.to_ascii_lowercase().as_str() {
+                "ncols" => columns = Some(value as usize),
+                "nrows" => rows = Some(value as usize),
+                "xllcorner" => west = Some(value),
+                "yllcorner" => south = Some(value),
+                "xllcenter" => (west, centered) = (Some(value), true),
+                "yllcenter" => (south, centered) = (Some(value), true),
+                "cellsize" => cell_size = Some(value),
+                "nodata_value" => no_data = Some(value),
+                _ => return Err(format!("unknown header {}", key)),
+            }
+        }
+        let (Some(columns), Some(rows), Some(mut west), Some(mut south), Some(cell_size)) =
+            (columns, rows, west, south, cell_size)
+        else {
+            return Err("incomplete header".to_string());
+        };
+        if centered {
+            west -= cell_size / 2.0;
+            south -= cell_size / 2.0;
+        }
+        let values = words
+            .map(|word| word.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        if values.len() != columns * rows {
+            return Err(format!(
+                "expected {} values, found {}",
+                columns * rows,
+                values.len()
+            ));
+        }
+        Ok(Self {
+            columns,
+            rows,
+            west,
+            south,
+            cell_size,
+            no_data,
+            values,
+        })
+    }
+
+    /// Value of the cell holding the position, `None` outside the grid or
+    /// on a no-data cell.
+    pub fn get(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        let column = ((longitude - self.west) / self.cell_size).floor();
+        let from_south = ((latitude - self.south) / self.cell_size).floor();
+        if column < 0.0 || from_south < 0.0 {
+            return None;
+        }
+        let (column, from_south) = (column as usize, from_south as usize);
+        if column >= self.columns || from_south >= self.rows {
+            return None;
+        }
+        let value = f64::from(self.values[(self.rows - 1 - from_south) * self.columns + column]);
+        (Some(value) != self.no_data).then_some(value)
+    }
+}
+
+/// The `.asc` grids of a folder, asked in file name order.
+#[derive(Debug, Clone, Default)]
+pub struct Tiles {
+    grids: Vec<Grid>,
+}
+
+impl Tiles {
+    pub fn load(dir: &str) -> Result<Self, ElevationError> {
+        let tile_error = |path: &Path, message: String| ElevationError::Tile {
+            path: path.display().to_string(),
+            message,
+        };
+        let entries = fs::read_dir(dir).map_err(|e| tile_error(Path::new(dir), e.to_string()))?;
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "asc"))
+            .collect();
+        paths.sort();
+
+        let mut grids = Vec::with_capacity(paths.len());
+        for path in paths {
+            let text = fs::read_to_string(&path).map_err(|e| tile_error(&path, e.to_string()))?;
+            grids.push(Grid::parse(&text).map_err(|message| tile_error(&path, message))?);
+        }
+        info!("Loaded {} elevation tiles from {}", grids.len(), dir);
+        Ok(Self { grids })
+    }
+
+    fn get(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        self.grids
+            .iter()
+            .find_map(|grid| grid.get(latitude, longitude))
+    }
+}
+
+impl ElevationProvider for Tiles {
+    fn elevation(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> BoxFuture<'_, Result<Option<f64>, ElevationError>> {
+        Box::pin(future::ready(Ok(self.get(latitude, longitude))))
+    }
+}
+
+/// An HTTP elevation API answering JSON, one request per position.
+#[derive(Debug, Clone)]
+pub struct Api {
+    client: reqwest::Client,
+    /// With `{latitude}` and `{longitude}` placeholders.
+    url: String,
+    /// JSON pointer to the metres.
+    pointer: String,
+}
+
+impl Api {
+    pub fn new(url: &str, pointer: &str, timeout: Duration) -> Result<Self, ElevationError> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| ElevationError::Api(e.to_string()))?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            pointer: pointer.to_string(),
+        })
+    }
+
+    async fn get(&self, latitude: f64, longitude: f64) -> Result<Option<f64>, ElevationError> {
+        let url = self
+            .url
+            .replace("{latitude}", &latitude.to_string())
+            .replace("{longitude}", &longitude.to_string());
+        let api_error = |e: reqwest::Error| ElevationError::Api(e.to_string());
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(api_error)?;
+        let text = response.text().await.map_err(api_error)?;
+        let body: Value =
+            serde_json::from_str(&text).map_err(|e| ElevationError::Api(e.to_string()))?;
+        match body.pointer(&self.pointer) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value
+                .as_f64()
+                .map(Some)
+                .ok_or_else(|| ElevationError::Api(format!("{} is not a number", self.pointer))),
+        }
+    }
+}
+
+impl ElevationProvider for Api {
+    fn elevation(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> BoxFuture<'_, Result<Option<f64>, ElevationError>> {
+        Box::pin(self.get(latitude, longitude))
+    }
+}
+
+/// Providers asked in order until one has data.
+#[derive(Debug, Default)]
+pub struct Chain(pub Vec<Arc<dyn ElevationProvider>>);
+
+impl ElevationProvider for Chain {
+    fn elevation(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> BoxFuture<'_, Result<Option<f64>, ElevationError>> {
+        Box::pin(async move {
+            for provider in &self.0 {
+                if let Some(metres) = provider.elevation(latitude, longitude).await? {
+                    return Ok(Some(metres));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+/// Position rounded to 1e-5 degrees, about a metre.
+type CacheKey = (i64, i64);
+
+lazy_static::lazy_static! {
+    static ref PROVIDER: RwLock<Option<Arc<dyn ElevationProvider>>> = RwLock::new(None);
+    static ref CACHE: Cache<CacheKey, Option<f64>> = {
+        let settings = &config::current().elevation;
+        Cache::builder()
+            .max_capacity(settings.cache_capacity)
+            .time_to_live(Duration::from_secs(settings.cache_ttl_secs))
+            .build()
+    };
+}
+
+/// Makes `provider` the source of elevations, replacing any earlier one.
+pub fn register(provider: impl ElevationProvider + 'static) {
+    *PROVIDER.write().expect("Failed to acquire write lock") = Some(Arc::new(provider));
+}
+
+/// Registers the tiles and API of `settings`. Leaves the provider alone
+/// when neither is set, so one registered in code stays.
+pub fn init(settings: &ElevationConfig) -> Result<(), ElevationError> {
+    let mut providers: Vec<Arc<dyn ElevationProvider>> = Vec::new();
+    if let Some(dir) = &settings.tiles_dir {
+        providers.push(Arc::new(Tiles::load(dir)?));
+    }
+    if let Some(url) = &settings.url {
+        let timeout = Duration::from_millis(settings.timeout_ms);
+        providers.push(Arc::new(Api::new(url, &settings.pointer, timeout)?));
+    }
+    if !providers.is_empty() {
+        register(Chain(providers));
+    }
+    Ok(())
+}
+
+/// Whether a provider is registered.
+pub fn is_enabled() -> bool {
+    PROVIDER
+        .read()
+        .expect("Failed to acquire read lock")
+        .is_some()
+}
+
+/// Elevation at a position in metres, rounded to centimetres. `None` without
+/// a provider, where it has no data, or when it failed.
+pub async fn lookup(latitude: f64, longitude: f64) -> Option<f64> {
+    let provider = PROVIDER
+        .read()
+        .expect("Failed to acquire read lock")
+        .clone()?;
+    let key = (
+        (latitude * 1e5).round() as i64,
+        (longitude * 1e5).round() as i64,
+    );
+    if let Some(cached) = CACHE.get(&key).await {
+        return cached;
+    }
+    match provider.elevation(latitude, longitude).await {
+        Ok(metres) => {
+            let metres = metres.map(|metres| (metres * 100.0).round() / 100.0);
+            CACHE.insert(key, metres).await;
+            metres
+        }
+        Err(e) => {
+            warn!(
+                "Elevation lookup at {},{} failed: {}",
+                latitude, longitude, e
+            );
+            None
+        }
+    }
+}
+
+/// Adds `elevation_m` to `entry` and each of `entries` in `response` that
+/// has a `latitude` and `longitude`, looking the positions up concurrently.
+pub async fn enrich(response: &mut Value) {
+    let Some(response) = response.as_object_mut() else {
+        return;
+    };
+    let mut entries: Vec<&mut Value> = Vec::new();
+    for (name, value) in response.iter_mut() {
+        match (name.as_str(), value) {
+            ("entry", entry @ Value::Object(_)) => entries.push(entry),
+            ("entries", Value::Array(list)) => entries.extend(list.iter_mut()),
+            _ => {}
+        }
+    }
+    let positions: Vec<Option<(f64, f64)>> = entries
+        .iter()
+        .map(|entry| Some((entry["latitude"].as_f64()?, entry["longitude"].as_f64()?)))
+        .collect();
+    let found = future::join_all(positions.iter().map(|position| async move {
+        match position {
+            Some((latitude, longitude)) => lookup(*latitude, *longitude).await,
+            None => None,
+        }
+    }))
+    .await;
+    for (entry, metres) in entries.into_iter().zip(found) {
+        if let Some(metres) = metres {
+            entry["elevation_m"] = json!(metres);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_cells_are_read_from_the_north() {
+        let grid = Grid::parse(
+            "ncols 2\nnrows 2\nxllcorner 4.0\nyllcorner 52.0\ncellsize 0.5\n\
+             NODATA_value -9999\n1.5 -9999\n-2.25 3\n",
+        )
+        .unwrap();
+        assert_eq!(grid.get(52.75, 4.25), Some(1.5));
+        assert_eq!(grid.get(52.25, 4.25), Some(-2.25));
+        assert_eq!(grid.get(52.25, 4.75), Some(3.0));
+        assert_eq!(grid.get(52.75, 4.75), None);
+        assert_eq!(grid.get(53.5, 4.25), None);
+        assert!(Grid::parse("ncols 2\nnrows 2\n1 2 3 4").is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Response field projection
+//!
+//! Operators can configure which `Row` fields go out by default and which
+//! may go out at all. Rows are always serialized through [`Projected`], so
+//! the restriction holds for every endpoint without each handler having to
+//! strip keys from JSON. The same wrapper applies the coordinate policy
+//! (rounding, snapping to the street centroid and further reference
+//! systems).
+
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::config;
+use crate::crs::{CrsSet, Projections};
+use crate::query::Row;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// The row's `id`, e.g. `1017GE-12`. Only sent when asked for, since
+    /// it spells out the postal code and house number.
+    Id,
+    PostalCode,
+    Street,
+    HouseNumber,
+    City,
+    Area,
+    Neighborhood,
+    Municipality,
+    Province,
+    Latitude,
+    Longitude,
+    /// The row's country, [`DEFAULT_COUNTRY`](crate::query::DEFAULT_COUNTRY)
+    /// when the source has none.
+    Country,
+    /// What the coordinates point at, see [`Precision`](crate::query::Precision).
+    Precision,
+    /// Date from which the address exists, left out for rows without one.
+    ValidFrom,
+    /// Free-form source data, left out for rows without any.
+    Extra,
+}
+
+impl Field {
+    pub const ALL: [Field; 15] = [
+        Field::Id,
+        Field::PostalCode,
+        Field::Street,
+        Field::HouseNumber,
+        Field::City,
+        Field::Area,
+        Field::Neighborhood,
+        Field::Municipality,
+        Field::Province,
+        Field::Latitude,
+        Field::Longitude,
+        Field::Country,
+        Field::Precision,
+        Field::ValidFrom,
+        Field::Extra,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::PostalCode => "postal_code",
+            Field::Street => "street",
+            Field::HouseNumber => "house_number",
+            Field::City => "city",
+            Field::Area => "area",
+            Field::Neighborhood => "neighborhood",
+            Field::Municipality => "municipality",
+            Field::Province => "province",
+            Field::Latitude => "latitude",
+            Field::Longitude => "longitude",
+            Field::Country => "country",
+            Field::Precision => "precision",
+            Field::ValidFrom => "valid_from",
+            Field::Extra => "extra",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL
+            .into_iter()
+            .find(|field| field.name() == name.trim())
+    }
+
+    fn bit(&self) -> u16 {
+        1 << (*self as u16)
+    }
+}
+
+/// Set of `Row` fields, stored as a bitset so it is `Copy` and cheap to pass
+/// around per request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSet(u16);
+
+impl FieldSet {
+    pub fn all() -> Self {
+        Self::from_fields(Field::ALL)
+    }
+
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn from_fields<I: IntoIterator<Item = Field>>(fields: I) -> Self {
+        Self(fields.into_iter().fold(0, |bits, field| bits | field.bit()))
+    }
+
+    pub fn contains(&self, field: Field) -> bool {
+        self.0 & field.bit() != 0
+    }
+
+    pub fn with(&self, field: Field) -> FieldSet {
+        Self(self.0 | field.bit())
+    }
+
+    pub fn intersect(&self, other: FieldSet) -> FieldSet {
+        Self(self.0 & other.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Field> + '_ {
+        Field::ALL
+            .into_iter()
+            .filter(move |field| self.contains(*field))
+    }
+
+    /// Parses a comma separated list such as `street,city,latitude`. The
+    /// error is the first unknown name.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let fields = list
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| Field::from_name(name).ok_or_else(|| name.trim().to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_fields(fields))
+    }
+
+    /// Fields permitted by config, `allowed` caps everything else.
+    pub fn allowed() -> Self {
+        let config = config::current();
+        config
+            .fields
+            .allowed
+            .as_ref()
+            .map_or_else(Self::all, |fields| {
+                Self::from_fields(fields.iter().copied())
+            })
+    }
+
+    /// Fields a client asked for with `fields=`, limited to the allowed
+    /// ones.
+    pub fn requested(list: &str) -> Result<Self, String> {
+        Ok(Self::parse(list)?.intersect(Self::allowed()))
+    }
+
+    /// Fields returned when the request does not ask for anything specific.
+    /// Without a configured default that is every field but `id`.
+    pub fn default_for_response() -> Self {
+        let config = config::current();
+        let default = config.fields.default.as_ref().map_or_else(
+            || Self::from_fields(Field::ALL.into_iter().filter(|field| *field != Field::Id)),
+            |fields| Self::from_fields(fields.iter().copied()),
+        );
+        default.intersect(Self::allowed())
+    }
+}
+
+/// How coordinates are degraded before they leave the server, for
+/// deployments that must not expose rooftop-precision locations.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoordinatePolicy {
+    /// Number of decimals kept. `None` keeps full precision.
+    pub precision: Option<u32>,
+    /// Replace each address position with the centroid of its street.
+    pub snap_to_street: bool,
+    /// Reference systems reported as `crs` next to WGS84.
+    pub crs: CrsSet,
+}
+
+impl CoordinatePolicy {
+    pub fn from_config() -> Self {
+        let config = config::current();
+        Self {
+            precision: config.coordinates.precision,
+            snap_to_street: config.coordinates.snap_to_street_centroid,
+            crs: CrsSet::from_config(&config.coordinates.crs),
+        }
+    }
+
+    /// Applies a client requested precision. Clients can only lower the
+    /// precision, never raise it above what config allows.
+    pub fn with_precision(mut self, precision: Option<u32>) -> Self {
+        if let Some(requested) = precision {
+            self.precision = Some(self.precision.map_or(requested, |max| max.min(requested)));
+        }
+        self
+    }
+
+    /// Clients can opt into snapping, but not out of it.
+    pub fn with_snap_to_street(mut self, snap: bool) -> Self {
+        self.snap_to_street |= snap;
+        self
+    }
+
+    /// Adds client requested reference systems to the configured ones.
+    pub fn with_crs(mut self, crs: CrsSet) -> Self {
+        self.crs = self.crs.union(crs);
+        self
+    }
+
+    pub fn round(&self, value: f64) -> f64 {
+        match self.precision {
+            Some(decimals) => {
+                let factor = 10f64.powi(decimals.min(15) as i32);
+                (value * factor).round() / factor
+            }
+            None => value,
+        }
+    }
+}
+
+/// Everything that decides how a row is rendered in a response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    pub fields: FieldSet,
+    pub coordinates: CoordinatePolicy,
+}
+
+impl Projection {
+    pub fn from_config() -> Self {
+        Self {
+            fields: FieldSet::default_for_response(),
+            coordinates: CoordinatePolicy::from_config(),
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self::from_config()
+    }
+}
+
+/// A `Row` serialized with only the fields in `fields` and its coordinates
+/// passed through the coordinate policy.
+#[derive(Debug, Clone, Copy)]
+pub struct Projected<'a> {
+    pub row: &'a Row,
+    pub fields: FieldSet,
+    pub coordinates: CoordinatePolicy,
+    /// Position to report instead of the row's own, e.g. the street centroid.
+    pub position: Option<(f64, f64)>,
+}
+
+impl<'a> Projected<'a> {
+    pub fn new(row: &'a Row, projection: &Projection) -> Self {
+        Self {
+            row,
+            fields: projection.fields,
+            coordinates: projection.coordinates,
+            position: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: Option<(f64, f64)>) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Latitude and longitude as they will be serialized.
+    pub fn coordinates(&self) -> (f64, f64) {
+        let (latitude, longitude) = self
+            .position
+            .unwrap_or((self.row.latitude, self.row.longitude));
+        (
+            self.coordinates.round(latitude),
+            self.coordinates.round(longitude),
+        )
+    }
+
+    /// The reported position in the policy's other reference systems.
+    pub fn projections(&self) -> Projections {
+        let (latitude, longitude) = self.coordinates();
+        self.coordinates.crs.project(latitude, longitude)
+    }
+}
+
+impl Serialize for Projected<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let row = self.row;
+        let (latitude, longitude) = self.coordinates();
+        let mut map = serializer.serialize_map(None)?;
+        for field in self.fields.iter() {
+            match field {
+                Field::Id => map.serialize_entry(field.name(), &row.id())?,
+                Field::PostalCode => map.serialize_entry(field.name(), &row.postal_code)?,
+                Field::Street => map.serialize_entry(field.name(), &row.street)?,
+                Field::HouseNumber => map.serialize_entry(field.name(), &row.house_number)?,
+                Field::City => map.serialize_entry(field.name(), &row.city)?,
+                Field::Area => map.serialize_entry(field.name(), &row.area)?,
+                Field::Neighborhood => map.serialize_entry(field.name(), &row.neighborhood)?,
+                Field::Municipality => map.serialize_entry(field.name(), &row.municipality)?,
+                Field::Province => map.serialize_entry(field.name(), &row.province)?,
+                Field::Latitude => map.serialize_entry(field.name(), &latitude)?,
+                Field::Longitude => map.serialize_entry(field.name(), &longitude)?,
+                Field::Country => map.serialize_entry(field.name(), row.country())?,
+                Field::Precision => map.serialize_entry(field.name(), &row.precision)?,
+                Field::ValidFrom => {
+                    if let Some(valid_from) = &row.valid_from {
+                        map.serialize_entry(field.name(), valid_from)?;
+                    }
+                }
+                Field::Extra => {
+                    if let Some(extra) = &row.extra {
+                        map.serialize_entry(field.name(), extra)?;
+                    }
+                }
+            }
+        }
+        let has_position =
+            self.fields.contains(Field::Latitude) || self.fields.contains(Field::Longitude);
+        if has_position && !self.coordinates.crs.is_empty() {
+            let projections = self.projections();
+            if !projections.is_empty() {
+                map.serialize_entry("crs", &projections)?;
+            }
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn id_only_goes_out_when_asked_for() {
+        let rows = fixtures::rows();
+        let projection = Projection::from_config();
+        let entry = serde_json::to_value(Projected::new(&rows[0], &projection)).unwrap();
+        assert!(entry.get("id").is_none());
+        assert!(entry.get("postal_code").is_some());
+
+        let projection = Projection {
+            fields: FieldSet::parse("id,street").unwrap(),
+            ..projection
+        };
+        let entry = serde_json::to_value(Projected::new(&rows[0], &projection)).unwrap();
+        assert_eq!(entry["id"], rows[0].id());
+        assert_eq!(entry.as_object().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,186 @@
+//! Canonical test dataset
+//!
+//! A handful of well-known Dutch addresses built in code, so tests of this
+//! crate and of crates embedding it can run against known data without
+//! shipping CSV files. The rows never change, so neither does the dataset
+//! version and results can be compared to fixed snapshots. Only compiled
+//! for this crate's tests and with the `test-util` feature.
+//!
+//! ```ignore
+//! places_autocomplete_rs::fixtures::install()?;
+//! let result = places_autocomplete_rs::query::query_postal_code("1012JS")?;
+//! ```
+
+use crate::query::error::QueryError;
+use crate::query::{replace_location_data, LocationData, Precision, Row};
+
+/// File name the fixture rows are reported under.
+pub const NAME: &str = "fixtures.csv";
+
+#[allow(clippy::too_many_arguments)]
+fn row(
+    postal_code: &str,
+    street: &str,
+    house_number: &str,
+    city: &str,
+    area: &str,
+    neighborhood: &str,
+    municipality: &str,
+    province: &str,
+    latitude: f64,
+    longitude: f64,
+) -> Row {
+    Row {
+        postal_code: postal_code.to_string(),
+        street: street.to_string(),
+        house_number: house_number.to_string(),
+        city: city.to_string(),
+        area: area.to_string(),
+        neighborhood: neighborhood.to_string(),
+        municipality: municipality.to_string(),
+        province: province.to_string(),
+        latitude,
+        longitude,
+        country: None,
+        precision: Precision::Address,
+        valid_from: None,
+        extra: None,
+    }
+}
+
+/// The fixture addresses, in a fixed order.
+pub fn rows() -> Vec<Row> {
+    vec![
+        row(
+            "1012JS",
+            "Dam",
+            "1",
+            "Amsterdam",
+            "Centrum",
+            "Burgwallen-Oude Zijde",
+            "Amsterdam",
+            "Noord-Holland",
+            52.373_1,
+            4.892_2,
+        ),
+        row(
+            "1012LG",
+            "Damrak",
+            "1",
+            "Amsterdam",
+            "Centrum",
+            "Burgwallen-Oude Zijde",
+            "Amsterdam",
+            "Noord-Holland",
+            52.375_7,
+            4.896_0,
+        ),
+        row(
+            "1012LG",
+            "Damrak",
+            "3",
+            "Amsterdam",
+            "Centrum",
+            "Burgwallen-Oude Zijde",
+            "Amsterdam",
+            "Noord-Holland",
+            52.375_6,
+            4.895_9,
+        ),
+        row(
+            "1012PH",
+            "Kalverstraat",
+            "92",
+            "Amsterdam",
+            "Centrum",
+            "Burgwallen-Nieuwe Zijde",
+            "Amsterdam",
+            "Noord-Holland",
+            52.369_3,
+            4.891_5,
+        ),
+        row(
+            "1017GE",
+            "Kerkstraat",
+            "12",
+            "Amsterdam",
+            "Centrum",
+            "Grachtengordel-Zuid",
+            "Amsterdam",
+            "Noord-Holland",
+            52.365_0,
+            4.889_0,
+        ),
+        row(
+            "1017GE",
+            "Kerkstraat",
+            "14",
+            "Amsterdam",
+            "Centrum",
+            "Grachtengordel-Zuid",
+            "Amsterdam",
+            "Noord-Holland",
+            52.365_2,
+            4.889_3,
+        ),
+        row(
+            "1017GE",
+            "Kerkstraat",
+            "14A",
+            "Amsterdam",
+            "Centrum",
+            "Grachtengordel-Zuid",
+            "Amsterdam",
+            "Noord-Holland",
+            52.365_2,
+            4.889_3,
+        ),
+        row(
+            "3511LX",
+            "Domplein",
+            "9",
+            "Utrecht",
+            "Binnenstad",
+            "Domplein e.o.",
+            "Utrecht",
+            "Utrecht",
+            52.090_8,
+            5.122_2,
+        ),
+        row(
+            "3011AD",
+            "Coolsingel",
+            "40",
+            "Rotterdam",
+            "Centrum",
+            "Stadsdriehoek",
+            "Rotterdam",
+            "Zuid-Holland",
+            51.922_5,
+            4.479_2,
+        ),
+        row(
+            "2513AA",
+            "Binnenhof",
+            "1",
+            "'s-Gravenhage",
+            "Centrum",
+            "Kortenbos",
+            "'s-Gravenhage",
+            "Zuid-Holland",
+            52.079_9,
+            4.313_3,
+        ),
+    ]
+}
+
+/// The fixture rows with every index built.
+pub fn location_data() -> LocationData {
+    LocationData::from_rows(NAME, rows())
+}
+
+/// Makes the fixtures the dataset the query functions read, replacing
+/// whatever was loaded.
+pub fn install() -> Result<(), QueryError> {
+    replace_location_data(location_data())
+}
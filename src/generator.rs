@@ -1,19 +1,158 @@
-use std::collections::HashSet;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
-use std::io::{BufReader, Seek, SeekFrom, Write};
-use tracing::{error, info, warn};
-use tracing_subscriber::EnvFilter;
+use std::io::Write;
+use tracing::{error, info};
 
 // crate imports
 use crate::io::create::create_file_if_not_exists;
+use crate::io::list::list_matching_files;
 
 use crate::parser::csv::open_csv_and_extract_headers;
-use crate::parser::csv::{count_lines_in_csv, read_all_lines};
-use crate::parser::enumurate_house_numbers::enumerate_house_numbers;
+use crate::parser::csv::read_all_lines;
+use crate::parser::enumurate_house_numbers::{enumerate_house_numbers_with, HOUSE_NUMBER_COLUMN};
+use crate::query::postal_code;
 
-pub async fn process_csv_files(
+/// Folder the shards are written to.
+const SHARD_DIR: &str = "./data";
+/// Shard file name before its number.
+const SHARD_PREFIX: &str = "data_nl_";
+/// Lines per shard.
+const SHARD_LINES: usize = 1_000_000;
+/// File in the shard folder the [`GeneratorReport`] is written to.
+pub const REPORT_FILE_NAME: &str = "generator_report.json";
+
+const POSTAL_CODE_COLUMN: usize = 0;
+const CITY_COLUMN: usize = 3;
+const LATITUDE_COLUMN: usize = 8;
+const LONGITUDE_COLUMN: usize = 9;
+/// Decimals written for coordinates, about 1 cm.
+const COORDINATE_DECIMALS: usize = 7;
+
+/// Switches for [`process_csv_files`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeneratorOptions {
+    /// Expand suffix ranges like `12 A t/m F` and `12a-12d` into one row per
+    /// unit (`12A`, `12B`, ...) so each unit is searchable on its own.
+    pub expand_suffixes: bool,
+    /// Input files read in parallel, 0 for one per CPU.
+    pub workers: usize,
+}
+
+type GeneratorError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What a [`process_csv_files`] run did, written as [`REPORT_FILE_NAME`]
+/// next to the shards. Like the shards it is byte-stable for the same
+/// input, and `places-cli validate` checks a folder against it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorReport {
+    pub input_files: Vec<InputFileReport>,
+    /// Source rows read.
+    pub rows_in: usize,
+    /// Rows after house number enumeration, before deduplication.
+    pub rows_enumerated: usize,
+    pub duplicates_removed: usize,
+    /// Rows written to the shards.
+    pub rows_out: usize,
+    /// Source rows whose house number range was enumerated.
+    pub ranges_expanded: usize,
+    /// Source rows left out, by reason.
+    pub invalid_rows: BTreeMap<String, usize>,
+    pub shards: Vec<ShardReport>,
+    /// Rows written per city.
+    pub cities: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputFileReport {
+    pub path: String,
+    pub rows_in: usize,
+    pub rows_enumerated: usize,
+    pub ranges_expanded: usize,
+    pub invalid_rows: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShardReport {
+    /// File name in the shard folder.
+    pub file: String,
+    pub rows: usize,
+}
+
+/// Reads one input file and enumerates its house numbers. Rows that cannot
+/// be read, whose house number does not enumerate or whose postal code
+/// cannot exist are left out and counted.
+fn enumerate_file(
     file_path: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    options: &GeneratorOptions,
+) -> Result<(Vec<csv::StringRecord>, InputFileReport), GeneratorError> {
+    let mut rdr: csv::Reader<File> = csv::Reader::from_path(file_path)?;
+    let mut enumerated_records: Vec<csv::StringRecord> = Vec::new();
+    let mut report = InputFileReport {
+        path: file_path.to_string(),
+        ..InputFileReport::default()
+    };
+    for result in rdr.records() {
+        report.rows_in += 1;
+        let invalid = match result {
+            Err(_) => Some("unreadable"),
+            Ok(record) => {
+                let enumerated = enumerate_house_numbers_with(&record, options.expand_suffixes);
+                let valid_postal_code = record
+                    .get(POSTAL_CODE_COLUMN)
+                    .is_some_and(|code| postal_code::parse(code).is_ok());
+                if enumerated.is_empty() {
+                    Some("unparsable_house_number")
+                } else if !valid_postal_code {
+                    Some("invalid_postal_code")
+                } else {
+                    if enumerated.len() > 1
+                        || enumerated[0].get(HOUSE_NUMBER_COLUMN) != record.get(HOUSE_NUMBER_COLUMN)
+                    {
+                        report.ranges_expanded += 1;
+                    }
+                    report.rows_enumerated += enumerated.len();
+                    enumerated_records.extend(enumerated.iter().map(canonical_coordinates));
+                    None
+                }
+            }
+        };
+        if let Some(reason) = invalid {
+            *report.invalid_rows.entry(reason.to_string()).or_default() += 1;
+        }
+    }
+    info!(
+        "Enumerated {} records from {}",
+        enumerated_records.len(),
+        file_path
+    );
+    Ok((enumerated_records, report))
+}
+
+/// Generates the `./data` shards from every file matching `file_pattern`
+/// (a path, or a pattern like `./raw/bag_*.csv`, see [`list_matching_files`]).
+/// Files are parsed and enumerated in parallel by `options.workers`; the
+/// results are then deduplicated and written by a single writer, so shard
+/// numbering continues across input files and a line present in several
+/// files is written once.
+///
+/// The output is byte-stable for the same input: lines are sorted (see
+/// [`compare_lines`]), coordinates get a fixed number of decimals, shards
+/// are numbered from 1 and stale shards of an earlier run are removed, so
+/// shards can be checksummed and diffed between pipeline runs. What the run
+/// did is returned and written next to the shards, see [`GeneratorReport`].
+pub async fn process_csv_files(
+    file_pattern: &str,
+    options: GeneratorOptions,
+) -> Result<GeneratorReport, GeneratorError> {
+    let file_paths: Vec<String> = list_matching_files(file_pattern)?;
+    if file_paths.is_empty() {
+        return Err(format!("no input files match {}", file_pattern).into());
+    }
+    info!("Generating from {} input files", file_paths.len());
+
     let headers: Vec<&str> = vec![
         "postal_code",
         "street",
@@ -27,112 +166,174 @@ pub async fn process_csv_files(
         "longitude",
     ];
 
-    // Open CSV and extract headers
-    if let Err(e) = open_csv_and_extract_headers(file_path).await {
-        error!("Error extracting headers: {:#?}", e);
-    }
-    info!("Headers extracted successfully");
+    for file_path in &file_paths {
+        // Open CSV and extract headers
+        if let Err(e) = open_csv_and_extract_headers(file_path).await {
+            error!("Error extracting headers: {:#?}", e);
+        }
+        info!("Headers extracted successfully");
 
-    // Read all lines and process them
-    if let Err(e) = read_all_lines(file_path).await {
-        error!("Error reading lines: {:#?}", e);
+        // Read all lines and process them
         if let Err(e) = read_all_lines(file_path).await {
             error!("Error reading lines: {:#?}", e);
+            if let Err(e) = read_all_lines(file_path).await {
+                error!("Error reading lines: {:#?}", e);
 
-            // Append the error to failed_lines.txt
-            let mut failed_file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("failed_lines.txt")?;
-
-            writeln!(
-                failed_file,
-                "Error reading lines from {}: {:#?}",
-                file_path, e
-            )?;
-        }
-    }
-    info!("Lines read successfully");
-
-    fn list_files_in_directory(directory: &str) -> std::io::Result<Vec<String>> {
-        let mut file_list: Vec<String> = Vec::new();
-        for entry in std::fs::read_dir(directory)? {
-            let entry: std::fs::DirEntry = entry?;
-            let path: std::path::PathBuf = entry.path();
-            if path.is_file() {
-                if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
-                    file_list.push(file_name.to_string());
-                }
+                // Append the error to failed_lines.txt
+                let mut failed_file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open("failed_lines.txt")?;
+
+                writeln!(
+                    failed_file,
+                    "Error reading lines from {}: {:#?}",
+                    file_path, e
+                )?;
             }
         }
-        Ok(file_list)
+        info!("Lines read successfully");
     }
 
-    // Initialize the unique line count
-    let mut unique_line_count = 0;
-    let mut file_index: usize = {
-        let files: Vec<String> = list_files_in_directory("./data_split")?;
-        let mut max_index: usize = 0;
-
-        for file in files {
-            if let Some(index_str) = file.strip_prefix("part_").and_then(|s| s.strip_suffix(".csv")) {
-                if let Ok(index) = index_str.parse::<usize>() {
-                    if index > max_index {
-                        max_index = index;
-                    }
-                }
-            }
-        }
+    // Parse and enumerate the input files in parallel
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.workers)
+        .build()?;
+    let enumerated_files: Vec<(Vec<csv::StringRecord>, InputFileReport)> = pool.install(|| {
+        file_paths
+            .par_iter()
+            .map(|file_path| enumerate_file(file_path, &options))
+            .collect::<Result<_, _>>()
+    })?;
+    let mut report = GeneratorReport::default();
 
-        if max_index == 0 {
-            1
-        } else {
-            max_index + 1
+    // Deduplicate by field values, so quoting differences in the source do
+    // not count as distinct lines, and sort so the output does not depend
+    // on the order of the input
+    let mut unique_lines: HashSet<Vec<String>> = HashSet::new();
+    for (enumerated_records, file_report) in enumerated_files {
+        for enumerated_record in enumerated_records {
+            unique_lines.insert(enumerated_record.iter().map(str::to_string).collect());
         }
-    };
-
-    // Open the CSV file for reading
-    let mut rdr: csv::Reader<File> = csv::Reader::from_path(file_path)?;
-    let mut output_file_path: String = format!("./data/data_nl_{}.csv", file_index);
-    create_file_if_not_exists(&output_file_path)?;
-    let mut writer: csv::Writer<File> = csv::Writer::from_path(&output_file_path)?;
-
-    // Write headers to the output file
-    writer.write_record(&headers)?;
-
-    // Initialize a set to track unique lines
-    let mut unique_lines: HashSet<String> = HashSet::new();
+        report.rows_in += file_report.rows_in;
+        report.rows_enumerated += file_report.rows_enumerated;
+        report.ranges_expanded += file_report.ranges_expanded;
+        for (reason, count) in &file_report.invalid_rows {
+            *report.invalid_rows.entry(reason.clone()).or_default() += count;
+        }
+        report.input_files.push(file_report);
+    }
+    let mut lines: Vec<Vec<String>> = unique_lines.into_iter().collect();
+    lines.sort_by(|a, b| compare_lines(a, b));
+    report.rows_out = lines.len();
+    report.duplicates_removed = report.rows_enumerated - report.rows_out;
+    for line in &lines {
+        if let Some(city) = line.get(CITY_COLUMN) {
+            *report.cities.entry(city.clone()).or_default() += 1;
+        }
+    }
 
-    for result in rdr.records() {
-        let record: csv::StringRecord = result?;
-        let line: String = record.iter().collect::<Vec<&str>>().join(",");
-        let enumerated_lines: Vec<String> = enumerate_house_numbers(&line);
-
-        for enumerated_line in enumerated_lines {
-            if unique_lines.insert(enumerated_line.clone()) {
-                writer.write_record(enumerated_line.split(','))?;
-                unique_line_count += 1;
-
-                // Check if the file has reached the maximum line count
-                if unique_line_count >= 1_000_000 {
-                    writer.flush()?;
-                    info!("Reached maximum line count for file: {}", output_file_path);
-                    file_index += 1;
-                    output_file_path = format!("./data/data_nl_{}.csv", file_index);
-                    create_file_if_not_exists(&output_file_path)?;
-                    writer = csv::Writer::from_path(&output_file_path)?;
-                    writer.write_record(&headers)?;
-                    unique_line_count = 0;
-                }
-            }
+    // Shards are numbered from 1 on every run, an empty input still gets a
+    // header only shard
+    let shards: Vec<&[Vec<String>]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(SHARD_LINES).collect()
+    };
+    for (index, shard) in shards.iter().enumerate() {
+        let output_file_path: String = shard_path(index + 1);
+        create_file_if_not_exists(&output_file_path)?;
+        let mut writer: csv::Writer<File> = csv::Writer::from_path(&output_file_path)?;
+        writer.write_record(&headers)?;
+        for line in shard.iter() {
+            writer.write_record(line)?;
         }
+        writer.flush()?;
+        info!("Wrote {} lines to {}", shard.len(), output_file_path);
+        report.shards.push(ShardReport {
+            file: shard_file_name(index + 1),
+            rows: shard.len(),
+        });
     }
+    remove_stale_shards(shards.len())?;
+
+    let report_path = format!("{}/{}", SHARD_DIR, REPORT_FILE_NAME);
+    std::fs::write(&report_path, serde_json::to_string_pretty(&report)? + "\n")?;
+    info!("Wrote generator report to {}", report_path);
 
-    writer.flush()?;
     info!("Processing complete");
-    info!("Total unique lines written: {}", unique_lines.len());
+    info!("Total unique lines written: {}", lines.len());
 
     info!("Done!");
 
+    Ok(report)
+}
+
+fn shard_file_name(index: usize) -> String {
+    format!("{}{}.csv", SHARD_PREFIX, index)
+}
+
+fn shard_path(index: usize) -> String {
+    format!("{}/{}", SHARD_DIR, shard_file_name(index))
+}
+
+/// Removes shards numbered past `shard_count` left over from an earlier,
+/// larger run, so the shard set matches this run exactly.
+fn remove_stale_shards(shard_count: usize) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(SHARD_DIR)? {
+        let entry: std::fs::DirEntry = entry?;
+        let file_name = entry.file_name();
+        let index = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(SHARD_PREFIX))
+            .and_then(|name| name.strip_suffix(".csv"))
+            .and_then(|index| index.parse::<usize>().ok());
+        if index.is_some_and(|index| index > shard_count) {
+            std::fs::remove_file(entry.path())?;
+            info!("Removed stale shard {}", entry.path().display());
+        }
+    }
     Ok(())
 }
+
+/// Output order: postal code, street, house number by its numeric part
+/// (`2` before `10`), then the remaining fields.
+fn compare_lines(a: &[String], b: &[String]) -> Ordering {
+    fn house_number(line: &[String]) -> (u64, &str) {
+        let house_number = line.get(HOUSE_NUMBER_COLUMN).map_or("", String::as_str);
+        let digits = house_number
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(house_number.len());
+        (
+            house_number[..digits].parse().unwrap_or(u64::MAX),
+            house_number,
+        )
+    }
+    a.first()
+        .cmp(&b.first())
+        .then_with(|| a.get(1).cmp(&b.get(1)))
+        .then_with(|| house_number(a).cmp(&house_number(b)))
+        .then_with(|| a.cmp(b))
+}
+
+/// `record` with latitude and longitude written with
+/// [`COORDINATE_DECIMALS`] decimals, so `52.37` and `52.3700000` are one
+/// line and the output does not depend on how the source formats floats.
+/// Unparsable coordinates are kept as they are.
+fn canonical_coordinates(record: &csv::StringRecord) -> csv::StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            if i != LATITUDE_COLUMN && i != LONGITUDE_COLUMN {
+                return field.to_string();
+            }
+            match field.trim().parse::<f64>() {
+                Ok(value) if value.is_finite() => {
+                    format!("{:.*}", COORDINATE_DECIMALS, value)
+                }
+                _ => field.to_string(),
+            }
+        })
+        .collect()
+}
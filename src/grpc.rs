@@ -0,0 +1,412 @@
+//! gRPC server
+//!
+//! Internal services that would rather skip JSON over HTTP can use the
+//! `Places` service from `proto/places.proto`. It reads the same
+//! `LOCATION_DATA` as the HTTP handlers with the configured limits, time
+//! budget and coordinate policy, and runs on its own thread and runtime
+//! next to actix. Only compiled with the `grpc` feature.
+//!
+//! Calls go through the `routes.public` settings like the HTTP search
+//! endpoints: an interceptor checks the `authorization: Bearer` or
+//! `x-api-key` metadata against its `auth`, then each call takes a token
+//! from the `rate_limit` bucket of the HTTP route it mirrors and is counted
+//! for `/admin/usage/timeseries` when `usage` is on.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use actix_web::http::StatusCode;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+use tracing::{error, info, warn};
+
+use crate::api::client_ip::ClientIp;
+use crate::api::middleware::auth::{self, Denied, API_KEY_HEADER};
+use crate::api::middleware::{rate_limit, usage, RouteGroup};
+use crate::config;
+use crate::deadline::Deadline;
+use crate::fields::Projection;
+use crate::query::edit_distance::Fuzziness;
+use crate::query::error::QueryError;
+use crate::query::postal_code::{self, PostalQuery};
+use crate::query::regions::RegionFilter;
+use crate::query::{haversine_distance, normalize, read_data, LocationData, Row, ScanProgress};
+
+pub mod proto {
+    tonic::include_proto!("places.v1");
+}
+
+use proto::places_server::{Places, PlacesServer};
+use proto::{
+    Address, AddressList, CoordinateSearchRequest, PostalCodeLookupRequest, StreetSearchRequest,
+};
+
+/// Limits of the gRPC calls, overridable under `limits.routes.grpc`.
+const ROUTE: &str = "grpc";
+
+/// Auth, rate limit and usage settings of the gRPC calls.
+const GROUP: RouteGroup = RouteGroup::Public;
+
+#[derive(Debug, Default)]
+pub struct PlacesService;
+
+/// `limit` of a request, where 0 means the configured default.
+fn limit(requested: u32) -> usize {
+    config::current()
+        .limits
+        .for_route(ROUTE)
+        .resolve((requested > 0).then_some(requested as usize))
+}
+
+/// The configured `request_timeout_ms`, gRPC deadlines are left to the
+/// transport.
+fn deadline() -> Deadline {
+    config::current()
+        .limits
+        .request_timeout_ms
+        .map_or_else(Deadline::none, |ms| {
+            Deadline::after(Duration::from_millis(ms))
+        })
+}
+
+/// Who made a call, left in the request extensions by [`authenticate`].
+#[derive(Debug, Clone)]
+struct Caller {
+    ip: ClientIp,
+    key_name: Option<String>,
+    started: Instant,
+}
+
+impl Caller {
+    fn of<T>(request: &Request<T>) -> Caller {
+        request
+            .extensions()
+            .get::<Caller>()
+            .cloned()
+            .unwrap_or_else(|| Caller {
+                ip: ClientIp(request.remote_addr().map(|address| address.ip())),
+                key_name: None,
+                started: Instant::now(),
+            })
+    }
+
+    /// Counts the call, with the HTTP status of its outcome.
+    fn record<T>(&self, result: &Result<T, Status>) {
+        if GROUP.config().usage {
+            usage::count(self.ip.to_string(), http_status(result), self.started);
+        }
+    }
+}
+
+/// Interceptor letting in calls with a bearer token or API key accepted by
+/// the group, as `auth::check` does for HTTP requests.
+#[allow(clippy::result_large_err)]
+fn authenticate(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let caller = Caller::of(&request);
+    let metadata = request.metadata();
+    let token = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let key = metadata
+        .get(API_KEY_HEADER.as_str())
+        .and_then(|value| value.to_str().ok());
+
+    let config = GROUP.config();
+    match auth::authorize(&config.auth, GROUP.requires_auth(), token, key) {
+        Ok(key_name) => {
+            request
+                .extensions_mut()
+                .insert(Caller { key_name, ..caller });
+            Ok(request)
+        }
+        Err(denied) => {
+            warn!("Rejected unauthenticated gRPC call from {}", caller.ip);
+            let message = denied.to_api_error().message;
+            let status = match denied {
+                Denied::NotConfigured => Status::permission_denied(message),
+                Denied::Unauthorized => Status::unauthenticated(message),
+            };
+            caller.record::<()>(&Err(status.clone()));
+            Err(status)
+        }
+    }
+}
+
+/// Runs `handler` when the caller has a rate limit token for `route`, the
+/// HTTP path the call mirrors, and counts the call.
+#[allow(clippy::result_large_err)]
+fn call<T, R>(
+    request: Request<T>,
+    route: &str,
+    handler: impl FnOnce(T) -> Result<R, Status>,
+) -> Result<Response<R>, Status> {
+    let caller = Caller::of(&request);
+    let config = GROUP.config().rate_limit;
+    let client = rate_limit::client_key(&config, caller.key_name.as_deref(), &caller.ip);
+    let result = match rate_limit::take(GROUP, &config, route, &client) {
+        Ok(()) => handler(request.into_inner()),
+        Err(wait) => {
+            warn!("Rate limited {} on gRPC {}", client, route);
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            Err(Status::resource_exhausted(format!(
+                "Too many requests, retry after {}s",
+                retry_after
+            )))
+        }
+    };
+    caller.record(&result);
+    result.map(Response::new)
+}
+
+/// The HTTP status usage accounting counts a call's outcome as.
+fn http_status<T>(result: &Result<T, Status>) -> StatusCode {
+    let Err(status) = result else {
+        return StatusCode::OK;
+    };
+    match status.code() {
+        Code::InvalidArgument => StatusCode::BAD_REQUEST,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+fn unavailable(e: QueryError) -> Status {
+    error!("gRPC query failed: {}", e);
+    Status::unavailable(e.to_string())
+}
+
+fn address(data: &LocationData, row: &Row, projection: &Projection) -> Address {
+    let (latitude, longitude) = data.project(row, projection).coordinates();
+    Address {
+        id: row.id(),
+        postal_code: row.postal_code.clone(),
+        street: row.street.clone(),
+        house_number: row.house_number.clone(),
+        city: row.city.clone(),
+        area: row.area.clone(),
+        neighborhood: row.neighborhood.clone(),
+        municipality: row.municipality.clone(),
+        province: row.province.clone(),
+        latitude,
+        longitude,
+        distance_m: None,
+        score: None,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn postal_code_lookup(request: PostalCodeLookupRequest) -> Result<AddressList, Status> {
+    let query = postal_code::parse_query(&request.postal_code)
+        .map_err(|e| Status::invalid_argument(format!("Invalid postal code: {}", e)))?;
+    let postal_code = postal_code::canonical(&request.postal_code);
+
+    let data = read_data().map_err(unavailable)?;
+    let (rows, progress) = match query {
+        PostalQuery::Prefix(_) => data.postal_rows_until(
+            &postal_code,
+            &RegionFilter::default(),
+            config::current().limits.postal_prefix_cap,
+            deadline(),
+        ),
+        PostalQuery::Full(_) => {
+            let rows = data
+                .lookup_by_postal_code(&postal_code)
+                .map(|rows| rows.iter().collect())
+                .unwrap_or_default();
+            (rows, ScanProgress::complete(1))
+        }
+    };
+    // a capped prefix scan knows its total from the statistics
+    let total = if progress.is_complete() {
+        rows.len()
+    } else {
+        data.count_postal_code(&postal_code)
+    };
+
+    let projection = Projection::from_config();
+    Ok(AddressList {
+        addresses: rows
+            .iter()
+            .take(limit(request.limit))
+            .map(|row| address(&data, row, &projection))
+            .collect(),
+        total: total as u32,
+        truncated: !progress.is_complete(),
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn street_search(request: StreetSearchRequest) -> Result<AddressList, Status> {
+    if request.query.trim().is_empty() {
+        return Err(Status::invalid_argument("query is required"));
+    }
+    let city_bias = request
+        .city_bias
+        .as_deref()
+        .map(normalize::text)
+        .filter(|city| !city.is_empty());
+
+    let data = read_data().map_err(unavailable)?;
+    let (ranked, progress) = data.ranked_street_rows_until(
+        &request.query,
+        Fuzziness::from_config(),
+        &RegionFilter::default(),
+        city_bias.as_deref(),
+        deadline(),
+    );
+
+    let projection = Projection::from_config();
+    Ok(AddressList {
+        addresses: ranked
+            .iter()
+            .take(limit(request.limit))
+            .map(|(row, relevance)| Address {
+                score: Some((relevance * 10_000.0).round() / 10_000.0),
+                ..address(&data, row, &projection)
+            })
+            .collect(),
+        total: ranked.len() as u32,
+        truncated: !progress.is_complete(),
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn coordinate_search(request: CoordinateSearchRequest) -> Result<AddressList, Status> {
+    let (latitude, longitude) = (request.latitude, request.longitude);
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(Status::invalid_argument(
+            "latitude and longitude must be valid degrees",
+        ));
+    }
+    let limit = limit(request.limit);
+    let deadline = deadline();
+
+    let data = read_data().map_err(unavailable)?;
+    let projection = Projection::from_config();
+    // nearest first, one address per street like /search_by_coordinates
+    let mut seen_streets = HashSet::new();
+    let mut addresses = Vec::new();
+    let mut truncated = false;
+    for (i, row) in data.nearest_rows(latitude, longitude).enumerate() {
+        if deadline.expired_at(i) {
+            truncated = true;
+            break;
+        }
+        if addresses.len() == limit {
+            break;
+        }
+        if !seen_streets.insert(row.street_key()) {
+            continue;
+        }
+        let address = address(&data, row, &projection);
+        let distance_km =
+            haversine_distance(latitude, longitude, address.latitude, address.longitude);
+        addresses.push(Address {
+            distance_m: Some((distance_km * 1000.0).round()),
+            ..address
+        });
+    }
+
+    Ok(AddressList {
+        total: addresses.len() as u32,
+        addresses,
+        truncated,
+    })
+}
+
+#[tonic::async_trait]
+impl Places for PlacesService {
+    async fn postal_code_lookup(
+        &self,
+        request: Request<PostalCodeLookupRequest>,
+    ) -> Result<Response<AddressList>, Status> {
+        call(request, "/search", postal_code_lookup)
+    }
+
+    async fn street_search(
+        &self,
+        request: Request<StreetSearchRequest>,
+    ) -> Result<Response<AddressList>, Status> {
+        call(request, "/search", street_search)
+    }
+
+    async fn coordinate_search(
+        &self,
+        request: Request<CoordinateSearchRequest>,
+    ) -> Result<Response<AddressList>, Status> {
+        call(request, "/search_by_coordinates", coordinate_search)
+    }
+}
+
+/// Starts the gRPC server on `listen` on a thread of its own, so it neither
+/// competes with nor depends on the actix workers.
+pub fn spawn(listen: &str) -> Result<(), String> {
+    let address: SocketAddr = listen
+        .parse()
+        .map_err(|e| format!("invalid grpc.listen '{}': {}", listen, e))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("grpc")
+        .enable_all()
+        .build()
+        .map_err(|e| format!("failed to start gRPC runtime: {}", e))?;
+
+    std::thread::Builder::new()
+        .name("grpc".to_string())
+        .spawn(move || {
+            runtime.block_on(async move {
+                info!("Serving gRPC on {}", address);
+                let result = Server::builder()
+                    .add_service(PlacesServer::with_interceptor(PlacesService, authenticate))
+                    .serve(address)
+                    .await;
+                if let Err(e) = result {
+                    error!("gRPC server stopped: {}", e);
+                }
+            });
+        })
+        .map_err(|e| format!("failed to start gRPC thread: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_group_lets_calls_in_with_their_caller() {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("x-api-key", "unknown".parse().unwrap());
+        let request = authenticate(request).unwrap();
+        let caller = request.extensions().get::<Caller>().unwrap();
+        assert_eq!(caller.key_name, None);
+        assert_eq!(caller.ip.to_string(), ClientIp(None).to_string());
+    }
+
+    #[test]
+    fn outcomes_count_as_http_statuses() {
+        assert_eq!(http_status(&Ok(())), StatusCode::OK);
+        let status = |status: Status| http_status::<()>(&Err(status));
+        assert_eq!(
+            status(Status::invalid_argument("")),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(Status::unauthenticated("")),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Status::resource_exhausted("")),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(Status::unavailable("")),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
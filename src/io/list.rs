@@ -23,3 +23,65 @@ pub fn list_all_files_in_csv_data() -> io::Result<Vec<String>> {
 
     Ok(file_names)
 }
+
+/// Lists the files matching `pattern`, sorted. `*` (any run of characters)
+/// and `?` (one character) are supported in the file name part only, so
+/// `./raw/bag_*.csv` lists every `bag_` export in `./raw`. A pattern without
+/// wildcards lists that one file if it exists.
+pub fn list_matching_files(pattern: &str) -> io::Result<Vec<String>> {
+    let path: &Path = Path::new(pattern);
+    let Some(name_pattern) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let directory: &Path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut file_paths: Vec<String> = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry: fs::DirEntry = entry?;
+        if !entry.path().is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if wildcard_match(name_pattern, name) {
+                file_paths.push(directory.join(name).to_string_lossy().into_owned());
+            }
+        }
+    }
+    file_paths.sort();
+
+    Ok(file_paths)
+}
+
+/// Whether `name` matches `pattern` with `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
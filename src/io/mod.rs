@@ -1,7 +1,6 @@
 //! File saver gais
-//! 
-//! 
-
+//!
+//!
 
+pub mod create;
 pub mod list;
-pub mod create;
\ No newline at end of file
@@ -6,10 +6,30 @@ use tokio::sync::Mutex;
 
 pub mod api;
 pub mod cache;
+pub mod cli;
+pub mod config;
+pub mod crs;
+pub mod deadline;
+pub mod diff;
+#[cfg(feature = "elevation")]
+pub mod elevation;
+pub mod fields;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
 pub mod parser;
 pub mod io;
 pub mod generator;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod logging;
 pub mod query;
+pub mod query_log;
+pub mod reload;
+#[cfg(feature = "replay")]
+pub mod replay;
+pub mod report;
+pub mod snapshot;
+pub mod validate;
 
 /// Define a type alias for the shared cache
-pub type SharedCache = Arc<Mutex<Cache<String, Value>>>;
\ No newline at end of file
+pub type SharedCache = Arc<Mutex<Cache<String, Value>>>;
@@ -0,0 +1,61 @@
+//! Tracing setup with a reloadable `EnvFilter`, so the log level can change
+//! without restarting the server.
+
+use std::sync::OnceLock;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// ## Initialize Tracing
+///
+/// Sets up the tracing subscriber. `RUST_LOG` wins over `default_level` so a
+/// one-off debug run does not need a config change.
+///
+/// ### Example
+///
+/// ```no_run
+/// places_autocomplete_rs::logging::init_tracing("info");
+/// ```
+pub fn init_tracing(default_level: &str) {
+    let filter: EnvFilter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(default_level))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .init();
+
+    FILTER_HANDLE.set(handle).ok();
+}
+
+/// Applies the level from the config, read after tracing started, unless
+/// `RUST_LOG` chose one.
+pub fn set_configured_log_level(directive: &str) -> Result<(), String> {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return Ok(());
+    }
+    set_log_level(directive)
+}
+
+/// Returns the active filter directive, e.g. `info`.
+pub fn current_log_level() -> Option<String> {
+    FILTER_HANDLE
+        .get()?
+        .with_current(|filter| filter.to_string())
+        .ok()
+}
+
+/// Swaps the active filter for `directive` (e.g. `debug` or
+/// `places_autocomplete_rs::query=trace`).
+pub fn set_log_level(directive: &str) -> Result<(), String> {
+    let filter: EnvFilter = EnvFilter::try_new(directive).map_err(|e| e.to_string())?;
+    let handle = FILTER_HANDLE
+        .get()
+        .ok_or_else(|| "tracing has not been initialized".to_string())?;
+
+    handle.reload(filter).map_err(|e| e.to_string())
+}
@@ -1,243 +1,2491 @@
 #![allow(unused_must_use)]
 
-use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
 use tracing::{error, info, warn};
-use tracing_subscriber::EnvFilter;
 
-use std::{io::Result, time::Duration};
+use std::io::Result;
 
-use actix_cors::Cors;
-use actix_files::NamedFile;
-use actix_web::body::{BoxBody, EitherBody};
-use actix_web::dev::{Service, ServiceResponse};
-use actix_web::http::header;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
 use actix_web::web::Data;
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
-use moka::future::Cache;
-use serde_json::{json, Value};
+use actix_web::{
+    get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
+};
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::env::var;
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::Mutex;
+use std::future::Future;
+use std::time::Duration;
+use utoipa::OpenApi;
 
+use places_autocomplete_rs::cache::response_cache::{self, cache_key, RESPONSE_CACHE};
 use places_autocomplete_rs::SharedCache;
 
+use places_autocomplete_rs::config;
+use places_autocomplete_rs::crs::{CrsSet, InputCrs};
+use places_autocomplete_rs::logging::{init_tracing, set_configured_log_level};
+use places_autocomplete_rs::reload::spawn_sighup_listener;
+use places_autocomplete_rs::report::{log_startup_summary, mark_started};
+
 use places_autocomplete_rs::api::actix_client::ping;
+use places_autocomplete_rs::api::admin::{
+    cache_flush, cache_stats, delete_zero_results, delete_zone, get_log_level, get_zero_results,
+    info as admin_info, list_zones, put_log_level, put_zone, usage_timeseries,
+};
+use places_autocomplete_rs::api::analytics::record_zero_result;
+use places_autocomplete_rs::api::client_ip::ClientIp;
+use places_autocomplete_rs::api::error::{self as api_error, ApiError};
+use places_autocomplete_rs::api::google;
+use places_autocomplete_rs::api::health::{readyz, stats};
+use places_autocomplete_rs::api::middleware::server_header::server_header;
+use places_autocomplete_rs::api::middleware::{self, cors_for, RouteGroup};
+use places_autocomplete_rs::api::ndjson::{self, Lines, MAX_LINE_BYTES, NDJSON};
+use places_autocomplete_rs::api::negotiate::{respond, GEO_JSON};
+use places_autocomplete_rs::api::openapi::{
+    AdminApi, DeadlineHeaders, HouseNumberParams, QueryOptionsParams, ResultParams, SecuritySchemes,
+};
+use places_autocomplete_rs::api::photon;
+use places_autocomplete_rs::api::sse;
+use places_autocomplete_rs::api::version::{self as api_version, ApiVersion};
+use places_autocomplete_rs::api::ws::{self, SessionState};
+use places_autocomplete_rs::cli;
+use places_autocomplete_rs::deadline::{
+    client_deadline, configured_deadline, deadline_for_request, Deadline,
+};
+use places_autocomplete_rs::fields::Field;
+use places_autocomplete_rs::query::error::{OptionsError, QueryError};
+use places_autocomplete_rs::query::filter::Filter;
+use places_autocomplete_rs::query::house_number::HouseNumber;
+use places_autocomplete_rs::query::places::PlaceKind;
+use places_autocomplete_rs::query::postal_code::{self, PostalCodeError};
+use places_autocomplete_rs::query::variants;
+use places_autocomplete_rs::query::{aliases, canary};
 use places_autocomplete_rs::query::{
-    initialize_location_data, query_by_coordinates, query_postal_code, query_street,
+    compact_section, count_city, count_place, count_postal_code, count_street, dedup_sections,
+    initialize_location_data, limit_section, list_places, postal_code_area, postal_code_neighbors,
+    query_autocomplete_with, query_by_coordinates_with, query_city_with, query_in_bbox_with,
+    query_nearest_with, query_neighborhood_with, query_place_with, query_postal_code_with,
+    query_postal_codes_for_street, query_reverse_geocode_bulk_with, query_reverse_geocode_with,
+    query_street_line, query_street_with, query_units_with, query_within_radius_with,
+    query_zones_at, query_zones_for_postal_code, reverse_geocode_points, zones, QueryOptions,
+    Stopwatch,
 };
 
+/// Builds the per-request query options from the query parameters, see
+/// [`QueryOptions::from_params`], and the deadline headers.
+fn query_options(
+    route: &str,
+    req: &HttpRequest,
+    info: &HashMap<String, String>,
+) -> std::result::Result<QueryOptions, OptionsError> {
+    let mut options = QueryOptions::from_params(route, info)?;
+    options.deadline = deadline_for_request(req);
+    Ok(options)
+}
+
+/// `city` is a search of its own, unless it scopes a neighborhood or area
+/// query.
+fn city_query(info: &HashMap<String, String>) -> Option<&String> {
+    let scoping = PlaceKind::ALL
+        .iter()
+        .any(|kind| info.contains_key(kind.name()));
+    info.get("city").filter(|_| !scoping)
+}
+
+/// `count_only=true`: the counts of `/search`'s sections into `response`.
+fn count_sections(
+    info: &HashMap<String, String>,
+    options: &QueryOptions,
+    response: &mut Value,
+) -> std::result::Result<(), QueryError> {
+    if let Some(postal_code) = info.get("postal_code") {
+        response["postal_code"] = count_postal_code(postal_code, options)?;
+    }
+    if let Some(street) = info.get("street") {
+        response["street"] = count_street(street, options)?;
+    }
+    for kind in PlaceKind::ALL {
+        if let Some(name) = info.get(kind.name()) {
+            response[kind.name()] =
+                count_place(kind, name, info.get("city").map(String::as_str), options)?;
+        }
+    }
+    if let Some(city) = city_query(info) {
+        response["city"] = count_city(city, options)?;
+    }
+    Ok(())
+}
+
+/// Answers from the response cache, or runs `query` and caches its envelope
+/// under the route and parameters. An `Err` from `query` is returned as is
+/// and not cached. `timings=true` bypasses the cache, a cached copy would
+/// carry the timings of the original request.
+async fn cached<F, Fut>(
+    data: &Data<SharedCache>,
+    req: &HttpRequest,
+    route: &str,
+    info: &HashMap<String, String>,
+    options: &QueryOptions,
+    query: F,
+) -> HttpResponse
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = std::result::Result<Value, HttpResponse>>,
+{
+    let key = match cache_key(route, info) {
+        Ok(key) => key,
+        Err(e) => return query_error(e),
+    };
+    if !options.timings {
+        if let Some(cached) = response_cache::get(data, &key).await {
+            info!("Cache hit for {}", key);
+            return respond(req, cached);
+        }
+    }
+    let response = match query().await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+    // partial results from an expired deadline must not be served to others
+    if !options.deadline.expired() && !options.timings {
+        response_cache::insert(data, key, response.clone()).await;
+    }
+    respond(req, response)
+}
+
+/// `elevation=true`: add `elevation_m` to the entries, see `elevation`.
+fn wants_elevation(info: &HashMap<String, String>) -> bool {
+    info.get("elevation")
+        .is_some_and(|v| v.parse().unwrap_or(false))
+}
+
+fn elevation_unavailable(message: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_IMPLEMENTED,
+        "elevation_unavailable",
+        message,
+    )
+}
+
+/// Adds `elevation_m` to the entries of `response` when the client asked
+/// for it, 501 when no provider is registered.
+#[cfg(feature = "elevation")]
+async fn add_elevation(
+    info: &HashMap<String, String>,
+    response: &mut Value,
+) -> std::result::Result<(), ApiError> {
+    use places_autocomplete_rs::elevation;
+
+    if !wants_elevation(info) {
+        return Ok(());
+    }
+    if !elevation::is_enabled() {
+        return Err(elevation_unavailable("No elevation provider is configured"));
+    }
+    elevation::enrich(response).await;
+    Ok(())
+}
+
+#[cfg(not(feature = "elevation"))]
+async fn add_elevation(
+    info: &HashMap<String, String>,
+    _response: &mut Value,
+) -> std::result::Result<(), ApiError> {
+    if wants_elevation(info) {
+        return Err(elevation_unavailable(
+            "This server was built without the elevation feature",
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_options(e: OptionsError) -> HttpResponse {
+    warn!("Rejected query parameters: {}", e);
+    ApiError::from(e).error_response()
+}
+
+fn invalid_postal_code(e: PostalCodeError) -> HttpResponse {
+    warn!("Rejected postal code: {}", e);
+    ApiError::from(e).error_response()
+}
+
+fn query_error(e: QueryError) -> HttpResponse {
+    error!("Query failed: {}", e);
+    ApiError::from(e).error_response()
+}
+
+/// 503 for a streaming session over `autocomplete_stream.max_sessions`.
+fn too_many_sessions(message: &str) -> HttpResponse {
+    let limit = config::current().autocomplete_stream.max_sessions;
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "too_many_sessions",
+        message,
+    )
+    .with_details(json!({ "limit": limit }))
+    .error_response()
+}
+
+/// Neighborhoods of a city with their address counts.
+#[utoipa::path(
+    tag = "places",
+    params(("city", description = "City name")),
+    responses(
+        (status = 200, description = "Neighborhoods with address counts", body = Object),
+        (status = 404, description = "Unknown city", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/cities/{city}/neighborhoods")]
+async fn city_neighborhoods(city: web::Path<String>) -> impl Responder {
+    city_places(&city, PlaceKind::Neighborhood)
+}
+
+/// Areas of a city with their address counts.
+#[utoipa::path(
+    tag = "places",
+    params(("city", description = "City name")),
+    responses(
+        (status = 200, description = "Areas with address counts", body = Object),
+        (status = 404, description = "Unknown city", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/cities/{city}/areas")]
+async fn city_areas(city: web::Path<String>) -> impl Responder {
+    city_places(&city, PlaceKind::Area)
+}
+
+/// Neighborhoods or areas of a city with address counts, 404 for an
+/// unknown city.
+fn city_places(city: &str, kind: PlaceKind) -> HttpResponse {
+    match list_places(city, kind) {
+        Ok(Some(response)) => HttpResponse::Ok().json(response),
+        Err(e) => query_error(e),
+        Ok(None) => {
+            warn!("No {}s found for city: {}", kind.name(), city);
+            ApiError::not_found("unknown_city", "Unknown city").error_response()
+        }
+    }
+}
+
+/// Postal codes adjacent to a postal code, for expanding delivery zones.
+#[utoipa::path(
+    tag = "geo",
+    params(("code", description = "Postal code, e.g. `1017GE`")),
+    responses(
+        (status = 200, description = "Adjacent postal codes", body = Object),
+        (status = 400, description = "Invalid postal code", body = Object),
+        (status = 404, description = "Unknown postal code", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/postal_code/{code}/neighbors")]
+async fn postal_code_neighbors_route(code: web::Path<String>) -> impl Responder {
+    let code = match postal_code::parse(&code) {
+        Ok(code) => code,
+        Err(e) => return invalid_postal_code(e),
+    };
+    match postal_code_neighbors(&code) {
+        Ok(Some(response)) => HttpResponse::Ok().json(response),
+        Err(e) => query_error(e),
+        Ok(None) => {
+            warn!("No neighbors for unknown postal code: {}", code);
+            ApiError::not_found("unknown_postal_code", "Unknown postal code").error_response()
+        }
+    }
+}
+
+/// Approximate area of a postal code as a GeoJSON `Feature`, for shading
+/// postal areas on a map.
+#[utoipa::path(
+    tag = "geo",
+    params(("code", description = "Postal code, e.g. `1017GE`")),
+    responses(
+        (status = 200, description = "GeoJSON `Feature` with the area's polygon", content((Object = "application/geo+json"))),
+        (status = 400, description = "Invalid postal code", body = Object),
+        (status = 404, description = "Unknown postal code", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/postal_code/{code}/area")]
+async fn postal_code_area_route(code: web::Path<String>) -> impl Responder {
+    let code = match postal_code::parse(&code) {
+        Ok(code) => code,
+        Err(e) => return invalid_postal_code(e),
+    };
+    match postal_code_area(&code) {
+        Ok(Some(feature)) => HttpResponse::Ok()
+            .content_type(GEO_JSON)
+            .body(feature.to_string()),
+        Err(e) => query_error(e),
+        Ok(None) => {
+            warn!("No area for unknown postal code: {}", code);
+            ApiError::not_found("unknown_postal_code", "Unknown postal code").error_response()
+        }
+    }
+}
+
+/// Delivery zones an address is in, by postal code or position:
+/// `/zone_lookup?postal_code=1017GE` or `/zone_lookup?lat=52.37&lon=4.89`.
+/// Zones are uploaded under `/admin/zones`, see `query::zones`.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("postal_code" = Option<String>, Query, description = "Postal code, e.g. `1017GE`"),
+        ("latitude" = Option<f64>, Query, description = "Latitude in degrees, also as `lat`"),
+        ("longitude" = Option<f64>, Query, description = "Longitude in degrees, also as `lon`"),
+        ("x" = Option<f64>, Query, description = "Easting in the one `crs` system, e.g. RD New"),
+        ("y" = Option<f64>, Query, description = "Northing in the one `crs` system"),
+        ("crs" = Option<String>, Query, description = "System of `x` and `y`, e.g. `EPSG:28992`")
+    ),
+    responses(
+        (status = 200, description = "Zones containing the address, possibly none", body = Object),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 404, description = "Unknown postal code", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/zone_lookup")]
+async fn zone_lookup(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for zone_lookup from {} with query: {:?}",
+        client_ip, info
+    );
+
+    if let Some(postal_code) = info.get("postal_code") {
+        let postal_code = match postal_code::parse(postal_code) {
+            Ok(postal_code) => postal_code,
+            Err(e) => return invalid_postal_code(e),
+        };
+        return match query_zones_for_postal_code(&postal_code) {
+            Ok(Some(response)) => HttpResponse::Ok().json(response),
+            Err(e) => query_error(e),
+            Ok(None) => {
+                warn!("No zones for unknown postal code: {}", postal_code);
+                ApiError::not_found("unknown_postal_code", "Unknown postal code").error_response()
+            }
+        };
+    }
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected zone_lookup coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
+    let param = |names: [&str; 2]| {
+        names
+            .into_iter()
+            .find_map(|name| info.get(name))
+            .and_then(|v| v.parse::<f64>().ok())
+    };
+    let (Some(latitude), Some(longitude)) =
+        (param(["latitude", "lat"]), param(["longitude", "lon"]))
+    else {
+        warn!("Missing zone_lookup parameters: {:?}", info);
+        return ApiError::invalid_parameters(
+            &["postal_code", "latitude", "longitude"],
+            "postal_code, or latitude and longitude, is required",
+        )
+        .error_response();
+    };
+    match query_zones_at(latitude, longitude) {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => query_error(e),
+    }
+}
+
+/// A street drawn as a line through its addresses, as GeoJSON:
+/// `/street_line?street=Kerkstraat&city=Amsterdam`.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("street" = String, Query, description = "Street name"),
+        ("city" = Option<String>, Query, description = "Only the street in this city")
+    ),
+    responses(
+        (status = 200, description = "GeoJSON `FeatureCollection` of line strings", content((Object = "application/geo+json"))),
+        (status = 400, description = "Missing street", body = Object),
+        (status = 404, description = "Unknown street", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/street_line")]
+async fn street_line(web::Query(info): web::Query<HashMap<String, String>>) -> impl Responder {
+    let Some(street) = info
+        .get("street")
+        .filter(|street| !street.trim().is_empty())
+    else {
+        warn!("Missing street parameter: {:?}", info);
+        return ApiError::invalid_parameters(&["street"], "street is required").error_response();
+    };
+    let city = info.get("city").map(String::as_str);
+    match query_street_line(street, city) {
+        Ok(lines) if lines["features"].as_array().is_some_and(|f| !f.is_empty()) => {
+            HttpResponse::Ok()
+                .content_type(GEO_JSON)
+                .body(lines.to_string())
+        }
+        Ok(_) => {
+            warn!("No street line for: {:?}", info);
+            ApiError::not_found("unknown_street", "Unknown street").error_response()
+        }
+        Err(e) => query_error(e),
+    }
+}
+
+/// Lists the municipality merge/rename table, or resolves `?name=` to the
+/// current municipality name.
+#[utoipa::path(
+    tag = "places",
+    params(("name" = Option<String>, Query, description = "Former municipality name to resolve")),
+    responses((status = 200, description = "Alias table, or the resolved municipality", body = Object))
+)]
+#[get("/municipality_aliases")]
+async fn municipality_aliases(
+    web::Query(info): web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let aliases = aliases::current();
+    match info.get("name") {
+        Some(name) => HttpResponse::Ok().json(json!({
+            "name": name,
+            "municipality": aliases.resolve(name),
+        })),
+        None => HttpResponse::Ok().json(json!({
+            "aliases": aliases.iter().collect::<Vec<_>>(),
+            "count": aliases.len(),
+        })),
+    }
+}
+
+/// Unique streets nearest to a position.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("latitude" = Option<f64>, Query, description = "Latitude in degrees, required without `x`"),
+        ("longitude" = Option<f64>, Query, description = "Longitude in degrees, required without `y`"),
+        ("x" = Option<f64>, Query, description = "Easting in the one `crs` system, e.g. RD New"),
+        ("y" = Option<f64>, Query, description = "Northing in the one `crs` system"),
+        ("mode" = Option<String>, Query, description = "`compact` returns `[display, id]` pairs"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Nearest addresses", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 429, description = "Rate limited, see `Retry-After`", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
 #[get("/search_by_coordinates")]
 async fn search_by_coordinates(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for search_by_coordinates from {} with query: {:?}",
+        client_ip, info
+    );
+
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected search_by_coordinates coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("search_by_coordinates", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(
+        &data,
+        &req,
+        "search_by_coordinates",
+        &info,
+        &options,
+        || async {
+            let response = if let (Some(lat), Some(lon)) =
+                (info.get("latitude"), info.get("longitude"))
+            {
+                info!(
+                    "Latitude and longitude parameters found: lat={}, lon={}",
+                    lat, lon
+                );
+                if let (Ok(latitude), Ok(longitude)) = (lat.parse::<f64>(), lon.parse::<f64>()) {
+                    info!(
+                        "Parsed latitude and longitude successfully: latitude={}, longitude={}",
+                        latitude, longitude
+                    );
+                    match query_by_coordinates_with(latitude, longitude, &options) {
+                        Ok(response) => response,
+                        Err(e) => return Err(query_error(e)),
+                    }
+                } else {
+                    warn!(
+                        "Invalid latitude or longitude format: lat={}, lon={}",
+                        lat, lon
+                    );
+                    return Err(ApiError::invalid_parameters(
+                        &["latitude", "longitude"],
+                        "Invalid latitude or longitude format",
+                    )
+                    .error_response());
+                }
+            } else {
+                warn!(
+                    "Missing latitude or longitude parameters in query: {:?}",
+                    info
+                );
+                return Err(ApiError::invalid_parameters(
+                    &["latitude", "longitude"],
+                    "Missing latitude or longitude parameters",
+                )
+                .error_response());
+            };
+
+            let mut response = response;
+            if info.get("mode").is_some_and(|mode| mode == "compact") {
+                compact_section(&mut response);
+            }
+
+            if let Some(timings) = response.get_mut("timings") {
+                timings["parse_ms"] = json!(parse_ms);
+            }
+            Ok(response)
+        },
+    )
+    .await
+}
+
+/// All addresses within `radius_m` of a position, capped by the route's
+/// `limit`.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("latitude" = Option<f64>, Query, description = "Latitude in degrees, required without `x`"),
+        ("longitude" = Option<f64>, Query, description = "Longitude in degrees, required without `y`"),
+        ("x" = Option<f64>, Query, description = "Easting in the one `crs` system, e.g. RD New"),
+        ("y" = Option<f64>, Query, description = "Northing in the one `crs` system"),
+        ("radius_m" = f64, Query, description = "Radius in meters, at most the configured `max_radius_m`"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Addresses within the radius, nearest first", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/search_within_radius")]
+async fn search_within_radius(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for search_within_radius from {} with query: {:?}",
+        client_ip, info
+    );
+
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected search_within_radius coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("search_within_radius", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(
+        &data,
+        &req,
+        "search_within_radius",
+        &info,
+        &options,
+        || async {
+            let param = |name: &str| info.get(name).and_then(|v| v.parse::<f64>().ok());
+            let (Some(latitude), Some(longitude), Some(radius_m)) =
+                (param("latitude"), param("longitude"), param("radius_m"))
+            else {
+                warn!("Missing or invalid radius search parameters: {:?}", info);
+                return Err(ApiError::invalid_parameters(
+                    &["latitude", "longitude", "radius_m"],
+                    "latitude, longitude and radius_m are required numbers",
+                )
+                .error_response());
+            };
+            let config = config::current();
+            if !(0.0..=config.limits.max_radius_m).contains(&radius_m) {
+                return Err(ApiError::invalid_parameters(
+                    &["radius_m"],
+                    format!(
+                        "radius_m must be between 0 and {}",
+                        config.limits.max_radius_m
+                    ),
+                )
+                .error_response());
+            }
+            let limit = config
+                .limits
+                .for_route("search_within_radius")
+                .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+            let mut response =
+                match query_within_radius_with(latitude, longitude, radius_m, limit, &options) {
+                    Ok(response) => response,
+                    Err(e) => return Err(query_error(e)),
+                };
+            if info.get("mode").is_some_and(|mode| mode == "compact") {
+                compact_section(&mut response);
+            }
+            if let Some(timings) = response.get_mut("timings") {
+                timings["parse_ms"] = json!(parse_ms);
+            }
+            Ok(response)
+        },
+    )
+    .await
+}
+
+/// The `n` nearest addresses to a position, every address rather than one
+/// per street, within the `municipality`, `province` and `city` filters.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("latitude" = Option<f64>, Query, description = "Latitude in degrees, required without `x`"),
+        ("longitude" = Option<f64>, Query, description = "Longitude in degrees, required without `y`"),
+        ("x" = Option<f64>, Query, description = "Easting in the one `crs` system, e.g. RD New"),
+        ("y" = Option<f64>, Query, description = "Northing in the one `crs` system"),
+        ("n" = Option<usize>, Query, description = "Addresses to return, defaults to the route's `default_limit` and capped at its `max_limit`"),
+        ("city" = Option<String>, Query, description = "Only addresses in this city"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Nearest addresses with `distance_m`, nearest first", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/nearest")]
+async fn nearest(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for nearest from {} with query: {:?}",
+        client_ip, info
+    );
+
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected nearest coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
+
+    let mut stopwatch = Stopwatch::start();
+    let mut options = match query_options("nearest", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    if let Some(city) = info.get("city").filter(|city| !city.trim().is_empty()) {
+        options.filter = Filter::and(options.filter, Some(Filter::equals(Field::City, city)));
+    }
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "nearest", &info, &options, || async {
+        let param = |name: &str| info.get(name).and_then(|v| v.parse::<f64>().ok());
+        let (Some(latitude), Some(longitude)) = (param("latitude"), param("longitude")) else {
+            warn!("Missing or invalid nearest parameters: {:?}", info);
+            return Err(ApiError::invalid_parameters(
+                &["latitude", "longitude"],
+                "latitude and longitude are required numbers",
+            )
+            .error_response());
+        };
+        let n = match info.get("n").map(|n| n.parse::<usize>()) {
+            None => None,
+            Some(Ok(n)) if n > 0 => Some(n),
+            Some(_) => {
+                return Err(
+                    ApiError::invalid_parameters(&["n"], "n must be a positive number")
+                        .error_response(),
+                )
+            }
+        };
+        let n = config::current().limits.for_route("nearest").resolve(n);
+
+        let mut response = match query_nearest_with(latitude, longitude, n, &options) {
+            Ok(response) => response,
+            Err(e) => return Err(query_error(e)),
+        };
+        if info.get("mode").is_some_and(|mode| mode == "compact") {
+            compact_section(&mut response);
+        }
+        if let Some(timings) = response.get_mut("timings") {
+            timings["parse_ms"] = json!(parse_ms);
+        }
+        Ok(response)
+    })
+    .await
+}
+
+/// Rewrites a position given in the `crs` system into the WGS84 parameters
+/// the coordinate endpoints read: `x` and `y` into `latitude` and
+/// `longitude`, and `min_x`, `min_y`, `max_x` and `max_y` into the box
+/// around their four corners. Leaves `info` alone without `x` or `min_x`.
+fn wgs84_params(info: &mut HashMap<String, String>) -> std::result::Result<(), String> {
+    if !info.contains_key("x") && !info.contains_key("min_x") {
+        return Ok(());
+    }
+    let Some(list) = info.get("crs") else {
+        return Err("x and y need a crs, e.g. crs=EPSG:28992".to_string());
+    };
+    let input = InputCrs::parse(list)?;
+    let number = |name: &str| {
+        info.get(name)
+            .and_then(|v| v.parse::<f64>().ok())
+            .ok_or_else(|| format!("{} must be a number", name))
+    };
+
+    let mut converted = Vec::new();
+    if info.contains_key("x") {
+        let (latitude, longitude) = input.to_wgs84(number("x")?, number("y")?)?;
+        converted.push(("latitude", latitude));
+        converted.push(("longitude", longitude));
+    }
+    if info.contains_key("min_x") {
+        let (min_x, min_y) = (number("min_x")?, number("min_y")?);
+        let (max_x, max_y) = (number("max_x")?, number("max_y")?);
+        let (latitudes, longitudes): (Vec<f64>, Vec<f64>) = [
+            (min_x, min_y),
+            (min_x, max_y),
+            (max_x, min_y),
+            (max_x, max_y),
+        ]
+        .into_iter()
+        .map(|(x, y)| input.to_wgs84(x, y))
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+        let min = |values: &[f64]| values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = |values: &[f64]| values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        converted.push(("min_lat", min(&latitudes)));
+        converted.push(("min_lon", min(&longitudes)));
+        converted.push(("max_lat", max(&latitudes)));
+        converted.push(("max_lon", max(&longitudes)));
+    }
+    for (name, value) in converted {
+        info.insert(name.to_string(), value.to_string());
+    }
+    Ok(())
+}
+
+/// The `max_distance_km` parameter, capped at the configured limit and
+/// defaulting to it. `None` when it is not a positive number.
+fn reverse_max_distance_km(info: &HashMap<String, String>) -> Option<f64> {
+    let configured_km = config::current().limits.reverse_max_distance_km;
+    match info.get("max_distance_km").map(|v| v.parse::<f64>()) {
+        None => Some(configured_km),
+        Some(Ok(km)) if km > 0.0 => Some(km.min(configured_km)),
+        Some(_) => None,
+    }
+}
+
+/// The single address at a position, for "what address is this pin".
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("latitude" = Option<f64>, Query, description = "Latitude in degrees, required without `x`"),
+        ("longitude" = Option<f64>, Query, description = "Longitude in degrees, required without `y`"),
+        ("x" = Option<f64>, Query, description = "Easting in the one `crs` system, e.g. RD New"),
+        ("y" = Option<f64>, Query, description = "Northing in the one `crs` system"),
+        ("max_distance_km" = Option<f64>, Query, description = "Farthest address to accept, at most the configured limit"),
+        ("elevation" = Option<bool>, Query, description = "Add `elevation_m` to the entries, needs the `elevation` feature and a provider"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Nearest address with distance and confidence", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 404, description = "No address within range", body = Object),
+        (status = 501, description = "`elevation=true` without an elevation provider", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/reverse_geocode")]
+async fn reverse_geocode(
     web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    reverse_geocode_response(info, data, req, client_ip).await
+}
+
+/// Photon's reverse path, answering `/reverse_geocode` for `lat` and `lon`
+/// in Photon's GeoJSON, see `api::photon`.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("lat" = f64, Query, description = "Latitude in degrees"),
+        ("lon" = f64, Query, description = "Longitude in degrees"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "`FeatureCollection` with the nearest address, empty when none is in range", body = Object),
+        (status = 400, description = "Missing or invalid parameters, as `message`", body = Object)
+    )
+)]
+#[get("/reverse")]
+async fn photon_reverse(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
 ) -> impl Responder {
+    for (photon, name) in [("lat", "latitude"), ("lon", "longitude")] {
+        if let Some(value) = info.remove(photon) {
+            info.insert(name.to_string(), value);
+        }
+    }
+    reverse_geocode_response(info, data, req, client_ip).await
+}
+
+async fn reverse_geocode_response(
+    mut info: HashMap<String, String>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> HttpResponse {
     info!(
-        "Received request for search_by_coordinates with query: {:?}",
-        info
+        "Received request for reverse_geocode from {} with query: {:?}",
+        client_ip, info
     );
 
-    let response = if let (Some(lat), Some(lon)) = (info.get("latitude"), info.get("longitude")) {
-        info!(
-            "Latitude and longitude parameters found: lat={}, lon={}",
-            lat, lon
-        );
-        if let (Ok(latitude), Ok(longitude)) = (lat.parse::<f64>(), lon.parse::<f64>()) {
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected reverse_geocode coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("reverse_geocode", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "reverse_geocode", &info, &options, || async {
+        let param = |name: &str| info.get(name).and_then(|v| v.parse::<f64>().ok());
+        let (Some(latitude), Some(longitude)) = (param("latitude"), param("longitude")) else {
+            warn!("Missing or invalid reverse geocode parameters: {:?}", info);
+            return Err(ApiError::invalid_parameters(
+                &["latitude", "longitude"],
+                "latitude and longitude are required numbers",
+            )
+            .error_response());
+        };
+
+        let Some(max_distance_km) = reverse_max_distance_km(&info) else {
+            return Err(ApiError::invalid_parameters(
+                &["max_distance_km"],
+                "max_distance_km must be a positive number",
+            )
+            .error_response());
+        };
+
+        let mut response =
+            match query_reverse_geocode_with(latitude, longitude, max_distance_km, &options) {
+                Ok(response) => response,
+                Err(e) => return Err(query_error(e)),
+            };
+        if response["out_of_range"].as_bool() == Some(true) {
             info!(
-                "Parsed latitude and longitude successfully: latitude={}, longitude={}",
-                latitude, longitude
-            );
-            query_by_coordinates(latitude, longitude)
-        } else {
-            warn!(
-                "Invalid latitude or longitude format: lat={}, lon={}",
-                lat, lon
+                "No address within {} km for reverse geocode: {:?}",
+                max_distance_km, info
             );
-            json!({ "error": "Invalid latitude or longitude format" })
+            return Err(ApiError::not_found(
+                "out_of_range",
+                format!("No address within {} km", max_distance_km),
+            )
+            .with_details(json!({ "max_distance_km": max_distance_km }))
+            .error_response());
+        }
+        if response["entry"].is_null() {
+            warn!("No address found for reverse geocode: {:?}", info);
+            if !options.deadline.expired() {
+                record_zero_result("reverse_geocode", &info);
+            }
+            return Err(ApiError::no_match().error_response());
+        }
+        if let Err(e) = add_elevation(&info, &mut response).await {
+            return Err(e.error_response());
+        }
+        if let Some(timings) = response.get_mut("timings") {
+            timings["parse_ms"] = json!(parse_ms);
         }
-    } else {
+        Ok(response)
+    })
+    .await
+}
+
+/// `latitude` and `longitude` of one bulk reverse geocode point, or its `x`
+/// and `y` in the `input` system converted to WGS84.
+fn point_coordinates(point: &Map<String, Value>, input: Option<&InputCrs>) -> Option<(f64, f64)> {
+    let coordinate = |name: &str| point.get(name).and_then(Value::as_f64);
+    match (coordinate("x"), coordinate("y"), input) {
+        (Some(x), Some(y), Some(input)) => input.to_wgs84(x, y).ok(),
+        _ => Some((coordinate("latitude")?, coordinate("longitude")?)),
+    }
+}
+
+/// The system bulk points give `x` and `y` in, when `crs` names one. An
+/// unknown code is the same invalid `crs` the single-point endpoints
+/// answer; a list of several systems leaves the points in WGS84.
+fn input_crs(
+    info: &HashMap<String, String>,
+) -> std::result::Result<Option<InputCrs>, OptionsError> {
+    let Some(list) = info.get("crs") else {
+        return Ok(None);
+    };
+    CrsSet::parse(list).map_err(|code| OptionsError::InvalidParameter {
+        name: "crs",
+        value: code,
+    })?;
+    Ok(InputCrs::parse(list).ok())
+}
+
+/// Reverse geocodes a JSON array of `{"latitude", "longitude"}` points in
+/// one pass over the spatial index, for enriching GPS traces. Results keep
+/// the order of the points; the query string takes the options and
+/// `max_distance_km` of `/reverse_geocode`. Results are not cached.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("max_distance_km" = Option<f64>, Query, description = "Farthest address to accept, at most the configured limit"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    request_body(
+        content = Vec<Object>,
+        description = "Points as `{\"latitude\": .., \"longitude\": ..}` objects, or `{\"x\": .., \"y\": ..}` in the one `crs` system"
+    ),
+    responses(
+        (status = 200, description = "One result per point, in order", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Invalid point or parameters", body = Object),
+        (status = 413, description = "More points than `max_reverse_bulk_points`", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[post("/reverse/bulk")]
+async fn reverse_bulk(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    body: web::Json<Vec<Map<String, Value>>>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    let points = body.into_inner();
+    info!(
+        "Received bulk reverse geocode from {} with {} points",
+        client_ip,
+        points.len()
+    );
+    let max_points = config::current().limits.max_reverse_bulk_points;
+    if points.len() > max_points {
         warn!(
-            "Missing latitude or longitude parameters in query: {:?}",
-            info
+            "Rejected bulk reverse geocode of {} points, limit is {}",
+            points.len(),
+            max_points
         );
-        json!({ "error": "Missing latitude or longitude parameters" })
+        return ApiError::too_large(
+            format!("At most {} points per request", max_points),
+            max_points,
+        )
+        .error_response();
+    }
+    let options = match query_options("reverse_geocode", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
     };
+    let Some(max_distance_km) = reverse_max_distance_km(&info) else {
+        return ApiError::invalid_parameters(
+            &["max_distance_km"],
+            "max_distance_km must be a positive number",
+        )
+        .error_response();
+    };
+
+    let input = match input_crs(&info) {
+        Ok(input) => input,
+        Err(e) => return invalid_options(e),
+    };
+    let mut coordinates = Vec::with_capacity(points.len());
+    for (i, point) in points.iter().enumerate() {
+        let Some(coordinate) = point_coordinates(point, input.as_ref()) else {
+            warn!("Invalid point {} in bulk reverse geocode", i);
+            return ApiError::bad_request(
+                "invalid_point",
+                format!(
+                    "Point {} needs numeric latitude and longitude, or x and y in one crs",
+                    i
+                ),
+            )
+            .with_details(json!({ "index": i }))
+            .error_response();
+        };
+        coordinates.push(coordinate);
+    }
 
-    info!("Response for search_by_coordinates: {:?}", response);
-    HttpResponse::Ok().json(response)
+    match query_reverse_geocode_bulk_with(&coordinates, max_distance_km, &options) {
+        Ok(response) => respond(&req, response),
+        Err(e) => query_error(e),
+    }
 }
 
-#[get("/search")]
-async fn search(
+/// Results for a run of NDJSON points in order, with an `error` in place of
+/// each line that is not a point. `false` when the deadline stopped the
+/// lookups before the last point.
+fn reverse_stream_lines(
+    lines: &[String],
+    max_distance_km: f64,
+    input: Option<&InputCrs>,
+    options: &QueryOptions,
+) -> std::result::Result<(Vec<Value>, bool), QueryError> {
+    let points: Vec<Option<(f64, f64)>> = lines
+        .iter()
+        .map(|line| {
+            serde_json::from_str::<Map<String, Value>>(line)
+                .ok()
+                .and_then(|point| point_coordinates(&point, input))
+        })
+        .collect();
+    let valid: Vec<(f64, f64)> = points.iter().flatten().copied().collect();
+    let mut found = reverse_geocode_points(&valid, max_distance_km, options)?.into_iter();
+
+    let mut results = Vec::with_capacity(points.len());
+    for point in points {
+        if point.is_none() {
+            let error = ApiError::bad_request(
+                "invalid_point",
+                "Expected an object with numeric latitude and longitude, or x and y",
+            );
+            results.push(error.to_json());
+            continue;
+        }
+        match found.next() {
+            Some(result) => results.push(result),
+            None => return Ok((results, false)),
+        }
+    }
+    Ok((results, true))
+}
+
+/// State of one `/reverse/bulk/stream` response between body chunks.
+struct ReverseStream {
+    payload: web::Payload,
+    lines: Lines,
+    options: QueryOptions,
+    max_distance_km: f64,
+    input: Option<InputCrs>,
+    count: usize,
+    found: usize,
+}
+
+impl ReverseStream {
+    /// Answers the points of the next chunks, `true` when this is the last
+    /// output: the body ended, the time budget ran out, the body was
+    /// malformed or the data could not be read. The last output ends with the `summary` line.
+    async fn next_output(&mut self) -> (Vec<Value>, bool) {
+        loop {
+            if self.options.deadline.expired() {
+                return (vec![self.summary(true)], true);
+            }
+            let (lines, last) = match self.payload.next().await {
+                Some(Ok(chunk)) => match self.lines.push(&chunk) {
+                    Some(lines) => (lines, false),
+                    None => {
+                        warn!("Rejected NDJSON line over {} bytes", MAX_LINE_BYTES);
+                        let error = ApiError::too_large(
+                            format!("Lines are limited to {} bytes", MAX_LINE_BYTES),
+                            MAX_LINE_BYTES,
+                        );
+                        return (vec![error.to_json(), self.summary(true)], true);
+                    }
+                },
+                Some(Err(e)) => {
+                    warn!("Failed to read reverse geocode stream: {}", e);
+                    let error = ApiError::bad_request(
+                        "invalid_body",
+                        format!("Failed to read body: {}", e),
+                    );
+                    return (vec![error.to_json(), self.summary(true)], true);
+                }
+                None => (self.lines.finish(), true),
+            };
+
+            let lookup = reverse_stream_lines(
+                &lines,
+                self.max_distance_km,
+                self.input.as_ref(),
+                &self.options,
+            );
+            let (mut results, complete) = match lookup {
+                Ok(found) => found,
+                Err(e) => {
+                    error!("Query failed: {}", e);
+                    let error = ApiError::from(e);
+                    return (vec![error.to_json(), self.summary(true)], true);
+                }
+            };
+            self.count += results.len();
+            self.found += results
+                .iter()
+                .filter(|result| result.get("entry").is_some_and(|entry| !entry.is_null()))
+                .count();
+            if last || !complete {
+                results.push(self.summary(!complete));
+                return (results, true);
+            }
+            if !results.is_empty() {
+                return (results, false);
+            }
+        }
+    }
+
+    fn summary(&self, truncated: bool) -> Value {
+        info!(
+            "Streamed reverse geocode found {} of {} addresses{}",
+            self.found,
+            self.count,
+            if truncated { ", truncated" } else { "" }
+        );
+        json!({
+            "summary": {
+                "count": self.count,
+                "found": self.found,
+                "truncated": truncated,
+            }
+        })
+    }
+}
+
+/// Streaming `/reverse/bulk`: reads one `{"latitude", "longitude"}` object
+/// per line and writes one result per line as the body arrives, so traces
+/// of any length need no client side chunking. The request runs for the
+/// configured `stream_time_budget_ms` or the client's deadline if shorter;
+/// the final line is a `summary` with `truncated: true` when it ran out
+/// before the end of the body.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("max_distance_km" = Option<f64>, Query, description = "Farthest address to accept, at most the configured limit"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    request_body(
+        content = String,
+        content_type = "application/x-ndjson",
+        description = "One `{\"latitude\": .., \"longitude\": ..}` object, or `{\"x\": .., \"y\": ..}` in the one `crs` system, per line"
+    ),
+    responses(
+        (status = 200, description = "One result per line, then a `summary` line", content((String = "application/x-ndjson"))),
+        (status = 400, description = "Invalid parameters", body = Object)
+    )
+)]
+#[post("/reverse/bulk/stream")]
+async fn reverse_bulk_stream(
     web::Query(info): web::Query<HashMap<String, String>>,
+    payload: web::Payload,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received streaming reverse geocode from {} with query: {:?}",
+        client_ip, info
+    );
+    let mut options = match query_options("reverse_geocode", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let Some(max_distance_km) = reverse_max_distance_km(&info) else {
+        return ApiError::invalid_parameters(
+            &["max_distance_km"],
+            "max_distance_km must be a positive number",
+        )
+        .error_response();
+    };
+    let input = match input_crs(&info) {
+        Ok(input) => input,
+        Err(e) => return invalid_options(e),
+    };
+    // the whole stream shares one budget, the per-query timeout would end it at once
+    let budget = Duration::from_millis(config::current().limits.stream_time_budget_ms);
+    options.deadline = Deadline::after(budget).min(client_deadline(&req));
+
+    let state = ReverseStream {
+        payload,
+        lines: Lines::default(),
+        input,
+        options,
+        max_distance_km,
+        count: 0,
+        found: 0,
+    };
+    let body = stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        let (results, last) = state.next_output().await;
+        let chunk = ndjson::to_bytes(&results);
+        Some((Ok::<_, actix_web::Error>(chunk), (!last).then_some(state)))
+    });
+    HttpResponse::Ok().content_type(NDJSON).streaming(body)
+}
+
+/// Addresses inside a viewport rectangle, capped by the route's `limit`.
+#[utoipa::path(
+    tag = "geo",
+    params(
+        ("min_lat" = Option<f64>, Query, description = "Southern edge, required without `min_x`"),
+        ("min_lon" = Option<f64>, Query, description = "Western edge"),
+        ("max_lat" = Option<f64>, Query, description = "Northern edge"),
+        ("max_lon" = Option<f64>, Query, description = "Eastern edge"),
+        ("min_x" = Option<f64>, Query, description = "Western edge in the one `crs` system, with `min_y`, `max_x` and `max_y`"),
+        ("min_y" = Option<f64>, Query, description = "Southern edge in the `crs` system"),
+        ("max_x" = Option<f64>, Query, description = "Eastern edge in the `crs` system"),
+        ("max_y" = Option<f64>, Query, description = "Northern edge in the `crs` system"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Addresses inside the rectangle", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid bounds", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/search_in_bbox")]
+async fn search_in_bbox(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
     data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
 ) -> impl Responder {
-    info!("Received request for search with query: {:?}", info);
+    info!(
+        "Received request for search_in_bbox from {} with query: {:?}",
+        client_ip, info
+    );
 
-    let mut response = json!({});
-    let mut found = false;
-    let limit: usize = info.get("limit").and_then(|l| l.parse().ok()).unwrap_or(10);
-    let unique_street_only: bool = info
-        .get("unique_street_only")
-        .map_or(false, |v| v.parse().unwrap_or(false));
-    info!("Limit for search results set to: {}", limit);
-    info!("Unique street only flag set to: {}", unique_street_only);
+    if let Err(e) = wgs84_params(&mut info) {
+        warn!("Rejected search_in_bbox coordinates: {}", e);
+        return ApiError::bad_request("invalid_coordinates", e).error_response();
+    }
 
-    if let Some(postal_code) = info.get("postal_code") {
-        info!("Postal code parameter found: {}", postal_code);
-        let mut location_data = query_postal_code(postal_code);
-        if let Some(house_number) = info.get("house_number") {
-            info!("House number parameter found: {}", house_number);
-            if let Some(entry) = location_data.get_mut("entry") {
-                if entry
-                    .get("house_number")
-                    .map_or(false, |hn| hn != house_number)
-                {
-                    info!("House number does not match entry house number, clearing location data");
-                    location_data = json!({});
-                }
-            }
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("search_in_bbox", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "search_in_bbox", &info, &options, || async {
+        let param = |name: &str| info.get(name).and_then(|v| v.parse::<f64>().ok());
+        let (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) = (
+            param("min_lat"),
+            param("min_lon"),
+            param("max_lat"),
+            param("max_lon"),
+        ) else {
+            warn!("Missing or invalid bbox parameters: {:?}", info);
+            return Err(ApiError::invalid_parameters(
+                &["min_lat", "min_lon", "max_lat", "max_lon"],
+                "min_lat, min_lon, max_lat and max_lon are required numbers",
+            )
+            .error_response());
+        };
+        let valid = (-90.0..=90.0).contains(&min_lat)
+            && (-90.0..=90.0).contains(&max_lat)
+            && (-180.0..=180.0).contains(&min_lon)
+            && (-180.0..=180.0).contains(&max_lon)
+            && min_lat <= max_lat
+            && min_lon <= max_lon;
+        if !valid {
+            return Err(ApiError::invalid_parameters(
+                &["min_lat", "min_lon", "max_lat", "max_lon"],
+                "bbox must have min_lat <= max_lat and min_lon <= max_lon within valid ranges",
+            )
+            .error_response());
+        }
+        let limit = config::current()
+            .limits
+            .for_route("search_in_bbox")
+            .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+        let mut response =
+            match query_in_bbox_with((min_lat, min_lon), (max_lat, max_lon), limit, &options) {
+                Ok(response) => response,
+                Err(e) => return Err(query_error(e)),
+            };
+        if info.get("mode").is_some_and(|mode| mode == "compact") {
+            compact_section(&mut response);
+        }
+        if let Some(timings) = response.get_mut("timings") {
+            timings["parse_ms"] = json!(parse_ms);
         }
-        if let Some(entries) = location_data.get_mut("entries") {
-            if let Some(entries_array) = entries.as_array_mut() {
-                if unique_street_only {
-                    let mut seen_streets = std::collections::HashSet::new();
-                    entries_array.retain(|entry| {
-                        entry
-                            .get("street")
-                            .map_or(false, |street| seen_streets.insert(street.clone()))
-                    });
-                    info!("Filtered entries to unique streets");
+        Ok(response)
+    })
+    .await
+}
+
+/// Addresses in a neighborhood, optionally of one city.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("neighborhood" = String, Query, description = "Neighborhood name"),
+        ("city" = Option<String>, Query, description = "City the neighborhood lies in"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Addresses in the neighborhood", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing neighborhood", body = Object),
+        (status = 404, description = "No matching data found", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/search_by_neighborhood")]
+async fn search_by_neighborhood(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for search_by_neighborhood from {} with query: {:?}",
+        client_ip, info
+    );
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("search_by_neighborhood", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(
+        &data,
+        &req,
+        "search_by_neighborhood",
+        &info,
+        &options,
+        || async {
+            let Some(neighborhood) = info.get("neighborhood") else {
+                warn!("Missing neighborhood parameter: {:?}", info);
+                return Err(ApiError::invalid_parameters(
+                    &["neighborhood"],
+                    "neighborhood is required",
+                )
+                .error_response());
+            };
+            let limit = config::current()
+                .limits
+                .for_route("search_by_neighborhood")
+                .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+            let city = info.get("city").map(String::as_str);
+            let mut response = match query_neighborhood_with(neighborhood, city, limit, &options) {
+                Ok(response) => response,
+                Err(e) => return Err(query_error(e)),
+            };
+            let found = response["estimated_total"].as_u64().unwrap_or(0) > 0;
+            if !found {
+                warn!("No addresses found for neighborhood: {:?}", info);
+                if !options.deadline.expired() {
+                    record_zero_result("search_by_neighborhood", &info);
                 }
-                entries_array.truncate(limit);
-                info!("Truncated entries to limit: {}", limit);
+                return Err(ApiError::no_match().error_response());
             }
-            if !entries.as_array().unwrap_or(&vec![]).is_empty() {
-                response["postal_code"] = location_data;
-                found = true;
-                info!("Postal code entries found and added to response");
+            if info.get("mode").is_some_and(|mode| mode == "compact") {
+                compact_section(&mut response);
+            }
+            if let Some(timings) = response.get_mut("timings") {
+                timings["parse_ms"] = json!(parse_ms);
+            }
+            Ok(response)
+        },
+    )
+    .await
+}
+
+/// Every unit at a house number, for unit pickers:
+/// `/units?postal_code=1017GE&house_number=12` lists 12, 12A, 12B, 12-1, ...
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("postal_code" = String, Query, description = "Postal code, e.g. `1017GE`"),
+        ("house_number" = String, Query, description = "House number, suffix is ignored"),
+        ("elevation" = Option<bool>, Query, description = "Add `elevation_m` to the entries, needs the `elevation` feature and a provider"),
+        ResultParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Units at the house number", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing or invalid parameters", body = Object),
+        (status = 404, description = "No matching data found", body = Object),
+        (status = 501, description = "`elevation=true` without an elevation provider", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/units")]
+async fn units(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for units from {} with query: {:?}",
+        client_ip, info
+    );
+
+    let mut stopwatch = Stopwatch::start();
+    // house_number selects the units here, it is not a row filter
+    let mut params = info.clone();
+    params.remove("house_number");
+    let options = match query_options("units", &req, &params) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let (Some(postal_code), Some(house_number)) =
+        (info.get("postal_code"), info.get("house_number"))
+    else {
+        warn!("Missing units parameters: {:?}", info);
+        return ApiError::invalid_parameters(
+            &["postal_code", "house_number"],
+            "postal_code and house_number are required",
+        )
+        .error_response();
+    };
+    let postal_code = match postal_code::parse(postal_code) {
+        Ok(postal_code) => postal_code,
+        Err(e) => return invalid_postal_code(e),
+    };
+    let Some(house_number) = HouseNumber::parse(house_number) else {
+        warn!("Rejected house number: {}", house_number);
+        return ApiError::invalid_parameters(
+            &["house_number"],
+            "house_number must start with a number",
+        )
+        .error_response();
+    };
+    let parse_ms = stopwatch.lap();
+    cached(&data, &req, "units", &info, &options, || async {
+        let limit = config::current()
+            .limits
+            .for_route("units")
+            .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+        let mut response = match query_units_with(&postal_code, &house_number, limit, &options) {
+            Ok(response) => response,
+            Err(e) => return Err(query_error(e)),
+        };
+        let found = response["estimated_total"].as_u64().unwrap_or(0) > 0;
+        if !found {
+            warn!("No units found: {:?}", info);
+            if !options.deadline.expired() {
+                record_zero_result("units", &info);
+            }
+            return Err(ApiError::no_match().error_response());
+        }
+        if info.get("mode").is_some_and(|mode| mode == "compact") {
+            compact_section(&mut response);
+        }
+        if let Err(e) = add_elevation(&info, &mut response).await {
+            return Err(e.error_response());
+        }
+        if let Some(timings) = response.get_mut("timings") {
+            timings["parse_ms"] = json!(parse_ms);
+        }
+        Ok(response)
+    })
+    .await
+}
+
+/// Postal codes covering a street, with house number ranges per code:
+/// `/postal_codes?street=Kerkstraat&city=Amsterdam`.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("street" = String, Query, description = "Street name"),
+        ("city" = Option<String>, Query, description = "Only the street in this city"),
+        HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Postal codes with house number ranges", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing street", body = Object),
+        (status = 404, description = "No matching data found", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/postal_codes")]
+async fn postal_codes(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for postal_codes from {} with query: {:?}",
+        client_ip, info
+    );
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("postal_codes", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "postal_codes", &info, &options, || async {
+        let Some(street) = info
+            .get("street")
+            .filter(|street| !street.trim().is_empty())
+        else {
+            warn!("Missing street parameter: {:?}", info);
+            return Err(
+                ApiError::invalid_parameters(&["street"], "street is required").error_response(),
+            );
+        };
+        let city = info.get("city").map(String::as_str);
+        let mut response = match query_postal_codes_for_street(street, city, &options) {
+            Ok(response) => response,
+            Err(e) => return Err(query_error(e)),
+        };
+        if response["total_entries"].as_u64().unwrap_or(0) == 0 {
+            warn!("No postal codes found: {:?}", info);
+            if !options.deadline.expired() {
+                record_zero_result("postal_codes", &info);
             }
-        } else if location_data.get("entry").is_some() {
-            response["postal_code"] = location_data;
+            return Err(ApiError::no_match().error_response());
+        }
+        if let Some(timings) = response.get_mut("timings") {
+            timings["parse_ms"] = json!(parse_ms);
+        }
+        Ok(response)
+    })
+    .await
+}
+
+/// Sections `/autocomplete` can answer with.
+const AUTOCOMPLETE_SECTIONS: [&str; 3] = ["postal_code", "street", "city"];
+
+/// Runs a free text query and applies `limit` (and `mode=compact`) to its
+/// sections. `None` when no section found anything.
+fn autocomplete_sections(
+    q: &str,
+    options: &QueryOptions,
+    limit: usize,
+    compact: bool,
+) -> std::result::Result<Option<Value>, QueryError> {
+    let mut response = query_autocomplete_with(q, options)?;
+    let mut found = false;
+    for name in AUTOCOMPLETE_SECTIONS {
+        let Some(section) = response.get_mut(name) else {
+            continue;
+        };
+        limit_section(section, limit);
+        let has_entries = section
+            .get("entries")
+            .and_then(Value::as_array)
+            .is_some_and(|entries| !entries.is_empty());
+        if has_entries || section.get("entry").is_some() {
             found = true;
-            info!("Single postal code entry found and added to response");
+            if compact {
+                compact_section(section);
+            }
         }
     }
+    Ok(found.then_some(response))
+}
 
-    if let Some(street) = info.get("street") {
-        info!("Street parameter found: {}", street);
-        let mut location_data = query_street(street);
-        if let Some(house_number) = info.get("house_number") {
-            info!("House number parameter found: {}", house_number);
-            if let Some(entries) = location_data.get_mut("entries") {
-                if let Some(entries_array) = entries.as_array_mut() {
-                    entries_array.retain(|entry| {
-                        entry
-                            .get("house_number")
-                            .map_or(false, |hn| hn == house_number)
-                    });
-                    info!("Filtered entries by house number: {}", house_number);
+/// Free-text address search, parsed into postal code, street and city.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("q" = String, Query, description = "Free text, e.g. `Kerkstraat 12 Amsterdam`"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "Sections per recognized part", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Missing q", body = Object),
+        (status = 404, description = "No matching data found", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/autocomplete")]
+async fn autocomplete(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    autocomplete_response(info, data, req, client_ip).await
+}
+
+/// Google Places Autocomplete's path, answering `/autocomplete` for
+/// `input` in Google's schema, see `api::google`.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("input" = String, Query, description = "Free text, e.g. `Kerkstraat 12 Amsterdam`"),
+        ("key" = Option<String>, Query, description = "API key, in place of the `X-Api-Key` header"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "`predictions` with a Google `status`, also for errors", body = Object)
+    )
+)]
+#[get("/place/autocomplete/json")]
+async fn google_autocomplete(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    let Some(input) = info.remove("input") else {
+        return ApiError::invalid_parameters(&["input"], "input is required").error_response();
+    };
+    info.insert("q".to_string(), input);
+    info.remove("key");
+    autocomplete_response(info, data, req, client_ip).await
+}
+
+/// Photon's search path, answering `/autocomplete` for `q` in Photon's
+/// GeoJSON, see `api::photon`.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("q" = String, Query, description = "Free text, e.g. `Kerkstraat 12 Amsterdam`"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "`FeatureCollection`, empty when nothing matched", body = Object),
+        (status = 400, description = "Missing q, as `message`", body = Object)
+    )
+)]
+#[get("/api")]
+async fn photon_search(
+    web::Query(mut info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    // location bias, not a position filter
+    info.remove("lat");
+    info.remove("lon");
+    autocomplete_response(info, data, req, client_ip).await
+}
+
+async fn autocomplete_response(
+    info: HashMap<String, String>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> HttpResponse {
+    info!(
+        "Received request for autocomplete from {} with query: {:?}",
+        client_ip, info
+    );
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("autocomplete", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "autocomplete", &info, &options, || async {
+        let Some(q) = info.get("q").filter(|q| !q.trim().is_empty()) else {
+            warn!("Missing q parameter: {:?}", info);
+            return Err(ApiError::invalid_parameters(&["q"], "q is required").error_response());
+        };
+        let limit = config::current()
+            .limits
+            .for_route("autocomplete")
+            .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+        let compact = info.get("mode").is_some_and(|mode| mode == "compact");
+        let mut response = match autocomplete_sections(q, &options, limit, compact) {
+            Ok(Some(response)) => response,
+            Ok(None) => {
+                warn!("No addresses found for autocomplete query: {:?}", info);
+                if !options.deadline.expired() {
+                    record_zero_result("autocomplete", &info);
                 }
+                return Err(ApiError::no_match().error_response());
             }
+            Err(e) => return Err(query_error(e)),
+        };
+        if options.timings {
+            response["timings"] = json!({
+                "parse_ms": parse_ms,
+                "total_ms": parse_ms + stopwatch.lap(),
+            });
         }
-        if let Some(entries) = location_data.get_mut("entries") {
-            if let Some(entries_array) = entries.as_array_mut() {
-                if unique_street_only {
-                    let mut seen_streets = std::collections::HashSet::new();
-                    entries_array.retain(|entry| {
-                        entry
-                            .get("street")
-                            .map_or(false, |street| seen_streets.insert(street.clone()))
-                    });
-                    info!("Filtered entries to unique streets");
-                }
-                entries_array.truncate(limit);
-                info!("Truncated entries to limit: {}", limit);
+        Ok(response)
+    })
+    .await
+}
+
+/// Incremental `/autocomplete` over Server-Sent Events. The first event,
+/// `session`, carries the id keystrokes are posted to; each update is
+/// answered with a `suggestions` event `{"q", "result"}`, where `result` is
+/// the `/autocomplete` body or `null` when nothing matched. The query string
+/// sets the options, `limit` and `mode` for the whole session.
+#[utoipa::path(
+    tag = "search",
+    params(ResultParams, HouseNumberParams, QueryOptionsParams),
+    responses(
+        (status = 200, description = "`session`, then `suggestions` and `error` events", content((String = "text/event-stream"))),
+        (status = 400, description = "Invalid filter", body = Object),
+        (status = 503, description = "Too many open streams", body = Object)
+    )
+)]
+#[get("/autocomplete/stream")]
+async fn autocomplete_stream(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received autocomplete stream from {} with query: {:?}",
+        client_ip, info
+    );
+    let options = match query_options("autocomplete", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let limit = config::current()
+        .limits
+        .for_route("autocomplete")
+        .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+    let compact = info.get("mode").is_some_and(|mode| mode == "compact");
+
+    let Some(session) = sse::open() else {
+        warn!(
+            "Rejected autocomplete stream from {}, too many open",
+            client_ip
+        );
+        return too_many_sessions("Too many open autocomplete streams");
+    };
+    let answer = move |q: &str| {
+        if q.trim().is_empty() {
+            return ("suggestions", json!({ "q": q, "result": null }));
+        }
+        // every update gets a fresh budget, the opening request's is long gone
+        let mut options = options.clone();
+        options.deadline = configured_deadline();
+        match autocomplete_sections(q, &options, limit, compact) {
+            Ok(result) => ("suggestions", json!({ "q": q, "result": result })),
+            Err(e) => {
+                let mut error = ApiError::from(e).to_json();
+                error["q"] = json!(q);
+                ("error", error)
+            }
+        }
+    };
+    HttpResponse::Ok()
+        .content_type(sse::EVENT_STREAM)
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(sse::stream(session, answer))
+}
+
+/// Sends the text typed so far, `{"q": ".."}`, to an open autocomplete
+/// stream. Updates that arrive before the previous one is answered replace
+/// it.
+#[utoipa::path(
+    tag = "search",
+    params(("session" = String, Path, description = "Id from the stream's `session` event")),
+    request_body(content = Object, description = "`{\"q\": \"Kerkstr\"}`"),
+    responses(
+        (status = 202, description = "Queued for the stream"),
+        (status = 400, description = "Missing q", body = Object),
+        (status = 404, description = "No such stream", body = Object)
+    )
+)]
+#[post("/autocomplete/stream/{session}")]
+async fn autocomplete_stream_update(
+    session: web::Path<String>,
+    body: web::Json<Map<String, Value>>,
+) -> impl Responder {
+    let Some(q) = body.get("q").and_then(Value::as_str) else {
+        return ApiError::invalid_parameters(&["q"], "q is required").error_response();
+    };
+    if !sse::update(&session, q) {
+        return ApiError::not_found("unknown_session", "No such autocomplete stream")
+            .error_response();
+    }
+    HttpResponse::Accepted().finish()
+}
+
+/// Autocomplete over a WebSocket, see `api::ws` for the messages. The
+/// query string sets the options, `limit` and `mode` for the whole session.
+#[utoipa::path(
+    tag = "search",
+    params(ResultParams, HouseNumberParams, QueryOptionsParams),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Invalid filter or not a WebSocket handshake", body = Object),
+        (status = 503, description = "Too many open sessions", body = Object)
+    )
+)]
+#[get("/ws/autocomplete")]
+async fn ws_autocomplete(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    body: web::Payload,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received autocomplete socket from {} with query: {:?}",
+        client_ip, info
+    );
+    let options = match query_options("autocomplete", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    let limit = config::current()
+        .limits
+        .for_route("autocomplete")
+        .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+    let compact = info.get("mode").is_some_and(|mode| mode == "compact");
+
+    let Some(slot) = ws::open() else {
+        warn!(
+            "Rejected autocomplete socket from {}, too many open",
+            client_ip
+        );
+        return too_many_sessions("Too many open autocomplete sessions");
+    };
+    let (response, session, messages) = match actix_ws::handle(&req, body) {
+        Ok(handshake) => handshake,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let answer = move |q: &str, state: &SessionState| {
+        if q.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut options = options.clone();
+        options.deadline = configured_deadline();
+        options.ranker = state.ranker(options.ranker);
+        if let Some(city) = &state.city_bias {
+            options.city_bias = Some(city.clone());
+        }
+        autocomplete_sections(q, &options, limit, compact).map_err(ApiError::from)
+    };
+    actix_web::rt::spawn(ws::run(slot, session, messages, answer));
+    response
+}
+
+/// Searches by postal code, street, neighborhood, area and city, one
+/// section per given parameter.
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("postal_code" = Option<String>, Query, description = "Postal code or prefix"),
+        ("street" = Option<String>, Query, description = "Street name"),
+        ("neighborhood" = Option<String>, Query, description = "Neighborhood name"),
+        ("area" = Option<String>, Query, description = "Area name"),
+        ("city" = Option<String>, Query, description = "City, or the city scoping `neighborhood` and `area`"),
+        ("count_only" = Option<bool>, Query, description = "Return only the number of matches per section"),
+        ResultParams, HouseNumberParams, QueryOptionsParams, DeadlineHeaders
+    ),
+    responses(
+        (status = 200, description = "One section per searched parameter", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 400, description = "Invalid filter or postal code", body = Object),
+        (status = 404, description = "No matching data found", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[get("/search")]
+async fn search(
+    web::Query(info): web::Query<HashMap<String, String>>,
+    data: Data<SharedCache>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    info!(
+        "Received request for search from {} with query: {:?}",
+        client_ip, info
+    );
+
+    let mut stopwatch = Stopwatch::start();
+    let options = match query_options("search", &req, &info) {
+        Ok(options) => options,
+        Err(e) => return invalid_options(e),
+    };
+    if let Some(Err(e)) = info
+        .get("postal_code")
+        .map(|pc| postal_code::parse_query(pc))
+    {
+        return invalid_postal_code(e);
+    }
+    let parse_ms = stopwatch.lap();
+
+    cached(&data, &req, "search", &info, &options, || async {
+        let mut response = json!({});
+        let mut found = false;
+        let limit: usize = config::current()
+            .limits
+            .for_route("search")
+            .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+        info!("Limit for search results set to: {}", limit);
+        info!("Filter set to: {:?}", options.filter);
+
+        if info
+            .get("count_only")
+            .is_some_and(|v| v.parse().unwrap_or(false))
+        {
+            if let Err(e) = count_sections(&info, &options, &mut response) {
+                return Err(query_error(e));
+            }
+            info!("Count only search, returning counts");
+            return Ok(response);
+        }
+
+        let mut sections: Vec<(&str, Value)> = Vec::new();
+        if let Some(postal_code) = info.get("postal_code") {
+            info!("Postal code parameter found: {}", postal_code);
+            match query_postal_code_with(postal_code, &options) {
+                Ok(section) => sections.push(("postal_code", section)),
+                Err(e) => return Err(query_error(e)),
+            }
+        }
+
+        if let Some(street) = info.get("street") {
+            info!("Street parameter found: {}", street);
+            match query_street_with(street, &options) {
+                Ok(section) => sections.push(("street", section)),
+                Err(e) => return Err(query_error(e)),
+            }
+        }
+
+        for kind in PlaceKind::ALL {
+            let Some(place) = info.get(kind.name()) else {
+                continue;
+            };
+            info!("{} parameter found: {}", kind.name(), place);
+            let city = info.get("city").map(String::as_str);
+            match query_place_with(kind, place, city, &options) {
+                Ok(section) => sections.push((kind.name(), section)),
+                Err(e) => return Err(query_error(e)),
             }
-            if !entries.as_array().unwrap_or(&vec![]).is_empty() {
-                response["street"] = location_data;
+        }
+
+        if let Some(city) = city_query(&info) {
+            info!("City parameter found: {}", city);
+            match query_city_with(city, &options) {
+                Ok(section) => sections.push(("city", section)),
+                Err(e) => return Err(query_error(e)),
+            }
+        }
+
+        // the same address can match several queries, keep it once in the first section
+        if sections.len() > 1 {
+            let mut named: Vec<(&str, &mut Value)> = sections
+                .iter_mut()
+                .map(|(name, section)| (*name, section))
+                .collect();
+            dedup_sections(&mut named);
+            info!("Deduplicated entries across sections");
+        }
+
+        for (name, mut location_data) in sections {
+            limit_section(&mut location_data, limit);
+            info!("Truncated {} entries to limit: {}", name, limit);
+            let has_entries = location_data
+                .get("entries")
+                .and_then(Value::as_array)
+                .is_some_and(|entries| !entries.is_empty());
+            if has_entries || location_data.get("entry").is_some() {
+                response[name] = location_data;
                 found = true;
-                info!("Street entries found and added to response");
+                info!("{} entries found and added to response", name);
+            }
+        }
+
+        if !found {
+            warn!("No matching data found for search query: {:?}", info);
+            if !options.deadline.expired() {
+                record_zero_result("search", &info);
+            }
+            return Err(ApiError::no_match().error_response());
+        }
+
+        if info.get("mode").is_some_and(|mode| mode == "compact") {
+            for section in ["postal_code", "street", "neighborhood", "area", "city"] {
+                if let Some(section) = response.get_mut(section) {
+                    compact_section(section);
+                }
             }
         }
-    }
 
-    if found {
+        if options.timings {
+            response["timings"] = json!({
+                "parse_ms": parse_ms,
+                "total_ms": parse_ms + stopwatch.lap(),
+            });
+        }
         info!("Search successful, returning response");
-        HttpResponse::Ok().json(response)
-    } else {
-        warn!("No matching data found for search query: {:?}", info);
-        HttpResponse::NotFound().body("No matching data found")
+        Ok(response)
+    })
+    .await
+}
+
+/// Parameters of one batch query. Numbers and booleans are accepted as
+/// JSON values and read like their query string form.
+fn batch_params(query: &Map<String, Value>) -> HashMap<String, String> {
+    query
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => return None,
+            };
+            Some((name.clone(), value))
+        })
+        .collect()
+}
+
+/// Runs one batch query: the `postal_code`, `street` and
+/// `latitude`/`longitude` sections `/search` and `/search_by_coordinates`
+/// would return, or an `error` for this query alone.
+fn batch_query(
+    client_deadline: Deadline,
+    info: &HashMap<String, String>,
+) -> std::result::Result<Value, QueryError> {
+    let mut options = match QueryOptions::from_params("search", info) {
+        Ok(options) => options,
+        Err(e) => return Ok(ApiError::from(e).to_json()),
+    };
+    options.deadline = client_deadline.min(configured_deadline());
+    if let Some(Err(e)) = info
+        .get("postal_code")
+        .map(|pc| postal_code::parse_query(pc))
+    {
+        return Ok(ApiError::from(e).to_json());
+    }
+    let limit = config::current()
+        .limits
+        .for_route("search")
+        .resolve(info.get("limit").and_then(|l| l.parse().ok()));
+
+    let mut sections: Vec<(&str, Value)> = Vec::new();
+    if let Some(postal_code) = info.get("postal_code") {
+        sections.push((
+            "postal_code",
+            query_postal_code_with(postal_code, &options)?,
+        ));
+    }
+    if let Some(street) = info.get("street") {
+        sections.push(("street", query_street_with(street, &options)?));
+    }
+    if let (Some(lat), Some(lon)) = (info.get("latitude"), info.get("longitude")) {
+        let (Ok(latitude), Ok(longitude)) = (lat.parse::<f64>(), lon.parse::<f64>()) else {
+            let error = ApiError::invalid_parameters(
+                &["latitude", "longitude"],
+                "Invalid latitude or longitude format",
+            );
+            return Ok(error.to_json());
+        };
+        sections.push((
+            "coordinates",
+            query_by_coordinates_with(latitude, longitude, &options)?,
+        ));
+    }
+    if sections.is_empty() {
+        let error = ApiError::invalid_parameters(
+            &["postal_code", "street", "latitude", "longitude"],
+            "Expected postal_code, street or latitude and longitude",
+        );
+        return Ok(error.to_json());
+    }
+    if sections.len() > 1 {
+        let mut named: Vec<(&str, &mut Value)> = sections
+            .iter_mut()
+            .map(|(name, section)| (*name, section))
+            .collect();
+        dedup_sections(&mut named);
+    }
+
+    let mut result = json!({});
+    for (name, mut section) in sections {
+        limit_section(&mut section, limit);
+        let has_entries = section
+            .get("entries")
+            .and_then(Value::as_array)
+            .is_some_and(|entries| !entries.is_empty());
+        if has_entries || section.get("entry").is_some() {
+            result[name] = section;
+        }
+    }
+    if result.as_object().is_some_and(Map::is_empty) {
+        return Ok(ApiError::no_match().to_json());
     }
+    Ok(result)
 }
 
+/// Runs a JSON array of queries, each an object of `/search` parameters or
+/// `latitude` and `longitude`, and returns their results in the same order.
+/// A query that fails or finds nothing gets an `error` in its place, the
+/// others are still answered. Results are not cached.
+#[utoipa::path(
+    tag = "search",
+    request_body(
+        content = Vec<Object>,
+        description = "Queries as objects of `/search` parameters or `latitude` and `longitude`"
+    ),
+    responses(
+        (status = 200, description = "One result per query, in order", content((Object = "application/json"), (Object = "application/geo+json"), (String = "text/csv"), (String = "application/x-ndjson"))),
+        (status = 413, description = "More queries than `max_batch_size`", body = Object),
+        (status = 503, description = "Query failed", body = Object)
+    )
+)]
+#[post("/search/batch")]
+async fn search_batch(
+    body: web::Json<Vec<Map<String, Value>>>,
+    req: HttpRequest,
+    client_ip: ClientIp,
+) -> impl Responder {
+    let queries = body.into_inner();
+    info!(
+        "Received batch search from {} with {} queries",
+        client_ip,
+        queries.len()
+    );
+    let max_batch_size = config::current().limits.max_batch_size;
+    if queries.len() > max_batch_size {
+        warn!(
+            "Rejected batch of {} queries, limit is {}",
+            queries.len(),
+            max_batch_size
+        );
+        return ApiError::too_large(
+            format!("At most {} queries per batch", max_batch_size),
+            max_batch_size,
+        )
+        .error_response();
+    }
+
+    // up to max_batch_size queries, kept off the worker serving requests
+    let deadline = client_deadline(&req);
+    let results = web::block(move || {
+        queries
+            .iter()
+            .map(|query| batch_query(deadline, &batch_params(query)))
+            .collect::<std::result::Result<Vec<_>, _>>()
+    })
+    .await;
+    let results = match results {
+        Ok(Ok(results)) => results,
+        Ok(Err(e)) => return query_error(e),
+        Err(e) => {
+            error!("Batch search task failed: {}", e);
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Batch search failed",
+            )
+            .error_response();
+        }
+    };
+    let found = results
+        .iter()
+        .filter(|result| result.get("error").is_none())
+        .count();
+    info!(
+        "Batch search answered {} of {} queries",
+        found,
+        results.len()
+    );
+    respond(
+        &req,
+        json!({
+            "results": results,
+            "count": results.len(),
+            "found": found,
+        }),
+    )
+}
+
+#[cfg(feature = "grpc")]
+fn start_grpc(listen: &str) {
+    if let Err(e) = places_autocomplete_rs::grpc::spawn(listen) {
+        error!("Failed to start gRPC server: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn start_grpc(listen: &str) {
+    warn!(
+        "grpc.listen is set to {} but this binary was built without the grpc feature",
+        listen
+    );
+}
+
+#[cfg(feature = "elevation")]
+fn start_elevation(settings: &config::ElevationConfig) {
+    if let Err(e) = places_autocomplete_rs::elevation::init(settings) {
+        warn!("Failed to set up elevation: {}", e);
+    }
+}
+
+#[cfg(not(feature = "elevation"))]
+fn start_elevation(_settings: &config::ElevationConfig) {
+    warn!("elevation is configured but this binary was built without the elevation feature");
+}
+
+/// The spec at `/openapi.json` and the Swagger UI under `/docs/`.
+#[cfg(feature = "swagger-ui")]
+fn docs(cfg: &mut web::ServiceConfig, openapi: utoipa::openapi::OpenApi) {
+    cfg.service(utoipa_swagger_ui::SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", openapi));
+}
+
+/// The spec at `/openapi.json`, built without the Swagger UI.
+#[cfg(not(feature = "swagger-ui"))]
+fn docs(cfg: &mut web::ServiceConfig, openapi: utoipa::openapi::OpenApi) {
+    cfg.route(
+        "/openapi.json",
+        web::get().to(move || {
+            let openapi = openapi.clone();
+            async move { HttpResponse::Ok().json(openapi) }
+        }),
+    );
+}
+
+/// The admin group under `prefix`, answering as `version`.
+fn admin_scope(prefix: &str, version: ApiVersion) -> impl HttpServiceFactory {
+    web::scope(&format!("{}/admin", prefix))
+        .wrap(from_fn(move |req, next| {
+            api_version::handle(version, req, next)
+        }))
+        .wrap(from_fn(|req, next| {
+            middleware::handle(RouteGroup::Admin, req, next)
+        }))
+        .wrap(cors_for(RouteGroup::Admin))
+        .app_data(web::JsonConfig::default().error_handler(api_error::invalid_body))
+        .app_data(web::QueryConfig::default().error_handler(api_error::invalid_body))
+        .service(get_log_level)
+        .service(put_log_level)
+        .service(admin_info)
+        .service(cache_flush)
+        .service(cache_stats)
+        .service(usage_timeseries)
+        .service(get_zero_results)
+        .service(delete_zero_results)
+        .service(list_zones)
+        .service(put_zone)
+        .service(delete_zone)
+}
+
+/// The public group under `prefix`, answering as `version`.
+fn public_scope(prefix: &str, version: ApiVersion) -> impl HttpServiceFactory {
+    web::scope(prefix)
+        .wrap(from_fn(move |req, next| {
+            api_version::handle(version, req, next)
+        }))
+        .wrap(from_fn(|req, next| {
+            middleware::handle(RouteGroup::Public, req, next)
+        }))
+        .wrap(from_fn(google::handle))
+        .wrap(from_fn(photon::handle))
+        .wrap(cors_for(RouteGroup::Public))
+        // batch and bulk bodies outgrow actix's 32 KiB default
+        .app_data(
+            web::JsonConfig::default()
+                .limit(4 * 1024 * 1024)
+                .error_handler(api_error::invalid_body),
+        )
+        .app_data(web::QueryConfig::default().error_handler(api_error::invalid_body))
+        // endpoints // docs
+        .service(ping)
+        .service(stats)
+        .service(search)
+        .service(search_batch)
+        .service(search_by_coordinates)
+        .service(search_within_radius)
+        .service(nearest)
+        .service(reverse_geocode)
+        .service(photon_reverse)
+        .service(reverse_bulk)
+        .service(reverse_bulk_stream)
+        .service(search_in_bbox)
+        .service(search_by_neighborhood)
+        .service(autocomplete)
+        .service(google_autocomplete)
+        .service(photon_search)
+        .service(autocomplete_stream)
+        .service(autocomplete_stream_update)
+        .service(ws_autocomplete)
+        .service(units)
+        .service(postal_codes)
+        .service(municipality_aliases)
+        .service(city_neighborhoods)
+        .service(city_areas)
+        .service(postal_code_neighbors_route)
+        .service(postal_code_area_route)
+        .service(zone_lookup)
+        .service(street_line)
+}
+
+/// The served OpenAPI spec: every public route plus the nested `/admin`
+/// scope, relative to `/v1` or, for older clients, the unversioned root.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "places_autocomplete_rs", description = "Dutch address search and geocoding"),
+    servers(
+        (url = "/v1"),
+        (url = "/", description = "Unversioned, answers like /v1")
+    ),
+    paths(
+        places_autocomplete_rs::api::actix_client::ping,
+        places_autocomplete_rs::api::health::readyz,
+        places_autocomplete_rs::api::health::stats,
+        search,
+        search_batch,
+        search_by_coordinates,
+        search_within_radius,
+        nearest,
+        reverse_geocode,
+        photon_reverse,
+        reverse_bulk,
+        reverse_bulk_stream,
+        search_in_bbox,
+        search_by_neighborhood,
+        autocomplete,
+        google_autocomplete,
+        photon_search,
+        autocomplete_stream,
+        autocomplete_stream_update,
+        ws_autocomplete,
+        units,
+        postal_codes,
+        municipality_aliases,
+        city_neighborhoods,
+        city_areas,
+        postal_code_neighbors_route,
+        postal_code_area_route,
+        zone_lookup,
+        street_line,
+    ),
+    nest((path = "/admin", api = AdminApi)),
+    modifiers(&SecuritySchemes),
+    security(("bearer" = []), ("api_key" = []))
+)]
+struct ApiDoc;
+
 #[actix_web::main]
 async fn main() -> Result<()> {
-    println!("Hello, world!");
-    initialize_location_data("./data_split");
+    mark_started();
+    dotenv::dotenv().ok();
 
-    // Initialize tracing
-    // floris; fixme
-    init_tracing();
+    // tracing before the config so its loader's messages show, at the
+    // configured level once read; offline commands print JSON on stdout and
+    // stay without log lines
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let is_command = cli::is_command(&args);
+    if !is_command {
+        init_tracing(&config::Config::default().log_level);
+    }
+    let config = config::set(
+        config::load_from_file(config::config_path()).unwrap_or_else(|e| {
+            // offline commands have no subscriber to log to
+            if is_command {
+                eprintln!("Failed to read config, using defaults: {}", e);
+            } else {
+                error!("Failed to read config, using defaults: {}", e);
+            }
+            config::Config::default()
+        }),
+    );
 
-    dotenv::dotenv().ok();
+    if let Some(code) = cli::dispatch(&args).await {
+        std::process::exit(code);
+    }
+    if args.iter().any(|arg| arg == "--strict") {
+        config::force_strict_loading();
+    }
+
+    if let Err(e) = set_configured_log_level(&config.log_level) {
+        warn!("Failed to apply log level '{}': {}", config.log_level, e);
+    }
+    // street spellings are indexed while loading
+    if let Err(e) = variants::load(config.street_variants_path.as_deref()) {
+        warn!("Failed to load street spelling variants: {}", e);
+    }
+    if let Err(e) = initialize_location_data(&config.data_dir) {
+        error!("Failed to load location data: {}", e);
+        std::process::exit(1);
+    }
+    canary::run();
+    if let Err(e) = aliases::load(config.municipality_aliases_path.as_deref()) {
+        warn!("Failed to load municipality aliases: {}", e);
+    }
+    if let Err(e) = zones::load(config.zones_path.as_deref()) {
+        warn!("Failed to load delivery zones: {}", e);
+    }
+    log_startup_summary();
+    spawn_sighup_listener();
+    if let Some(listen) = &config.grpc.listen {
+        start_grpc(listen);
+    }
+    if config.elevation.tiles_dir.is_some() || config.elevation.url.is_some() {
+        start_elevation(&config.elevation);
+    }
+    if config.routes.admin.auth.is_open() {
+        warn!("routes.admin.auth has no tokens or keys, admin routes answer 403");
+    }
+    if config.cache.redis.is_some() && !cfg!(feature = "redis") {
+        warn!("cache.redis is set but this binary was built without the redis feature");
+    }
 
     let port: u16 = var("XLX_PLACES_AUTOCOMPLETE_API_PORT")
         .unwrap_or("4444".to_string())
         .parse()
         .unwrap_or(4444);
 
-    let cache: SharedCache = Arc::new(Mutex::new(
-        Cache::builder()
-            .time_to_live(Duration::from_secs(60 * 60 * 5000))
-            .build(),
-    ));
+    let cache: SharedCache = RESPONSE_CACHE.clone();
+
+    let openapi = ApiDoc::openapi();
 
     // http builder
     HttpServer::new(move || {
-        let cors: Cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header();
-
         App::new()
-            .wrap(cors)
-            .wrap_fn(|req, srv| {
-                let fut = srv.call(req);
-                async move {
-                    let mut res: ServiceResponse<EitherBody<BoxBody>> = fut.await?;
-                    res.headers_mut()
-                        .insert(header::SERVER, "XYLEX/0".parse().unwrap());
-                    Ok(res)
-                }
-            })
+            .wrap(from_fn(server_header))
             // cache injecting middleware
             .app_data(Data::new(cache.clone()))
-            // endpoints // docs
-            .service(ping)
-            .service(search)
-            .service(search_by_coordinates)
+            // versioned routes, each admin group ahead of its public scope
+            .configure(|cfg| {
+                for version in ApiVersion::ALL {
+                    cfg.service(admin_scope(version.prefix(), version))
+                        .service(public_scope(version.prefix(), version));
+                }
+            })
+            // unversioned compatibility routes, answering as the legacy
+            // version; admin first so the public "" scope does not swallow it
+            .service(admin_scope("", ApiVersion::LEGACY))
+            // spec and Swagger UI, also ahead of the public "" scope
+            .configure(|cfg| docs(cfg, openapi.clone()))
+            // readiness probe, outside the groups so it needs no token
+            .service(readyz)
+            .service(public_scope("", ApiVersion::LEGACY))
+            .default_service(web::to(|| async {
+                ApiError::not_found("unknown_route", "No such endpoint").error_response()
+            }))
     })
     .workers(4)
     .bind(("0.0.0.0", port))?
     .run()
     .await
 }
-
-/// ## Initialize Tracing
-///
-/// This function sets up the tracing subscriber for logging and monitoring.
-///
-/// ### Example
-///
-/// ```
-/// init_tracing();
-/// ```
-fn init_tracing() {
-    let filter: EnvFilter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    tracing_subscriber::fmt().with_env_filter(filter).init()
-}
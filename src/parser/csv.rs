@@ -1,9 +1,8 @@
-use csv::{ReaderBuilder, StringRecord};
-use futures::stream::StreamExt;
+use csv::ReaderBuilder;
 use std::error::Error;
 use std::fs::File;
 use std::path::Path;
-use tracing::{error, info, warn};
+use tracing::{error, info};
 
 pub async fn open_csv_and_extract_headers<P: AsRef<Path>>(
     file_path: P,
@@ -20,8 +19,7 @@ pub async fn open_csv_and_extract_headers<P: AsRef<Path>>(
 pub async fn read_all_lines(file_path: &str) -> Result<(), Box<dyn Error>> {
     let mut rdr: csv::Reader<File> = ReaderBuilder::new().from_path(file_path)?;
 
-    let mut records = rdr.records();
-    while let Some(record) = records.next() {
+    for record in rdr.records() {
         match record {
             Ok(record) => {
                 info!("Record: {:#?}", record);
@@ -39,8 +37,7 @@ pub async fn count_lines_in_csv(file_path: &str) -> Result<usize, Box<dyn Error>
     let mut rdr: csv::Reader<File> = ReaderBuilder::new().from_path(file_path)?;
     let mut count = 0;
 
-    let mut records = rdr.records();
-    while let Some(record) = records.next() {
+    for record in rdr.records() {
         match record {
             Ok(_) => {
                 count += 1;
@@ -1,27 +1,223 @@
-pub fn enumerate_house_numbers(line: &str) -> Vec<String> {
-    let mut result = Vec::new();
-    let parts: Vec<&str> = line.split(',').collect();
+use csv::StringRecord;
 
-    if parts.len() < 3 {
-        return result; 
-    }
+/// Position of `house_number` in the source and output records.
+pub const HOUSE_NUMBER_COLUMN: usize = 2;
+
+/// Expands a record whose house number is a range like `1 t/m 5` into one
+/// record per number. Other records come back unchanged, records without a
+/// house number column are dropped. Fields are handled as parsed values, so
+/// commas, quotes and line breaks inside other fields survive as they are;
+/// quoting is left to the `csv::Writer`.
+pub fn enumerate_house_numbers(record: &StringRecord) -> Vec<StringRecord> {
+    let mut result = Vec::new();
+    let Some(house_numbers) = record.get(HOUSE_NUMBER_COLUMN) else {
+        return result;
+    };
 
-    let house_numbers = parts[2];
     if let Some(range_pos) = house_numbers.find(" t/m ") {
-        let start = &house_numbers[..range_pos].trim();
-        let end = &house_numbers[range_pos + 5..].trim();
+        let start = house_numbers[..range_pos].trim();
+        let end = house_numbers[range_pos + 5..].trim();
 
         if let (Ok(start_num), Ok(end_num)) = (start.parse::<u32>(), end.parse::<u32>()) {
             for num in start_num..=end_num {
-                let mut new_line = parts.clone();
-                let num_string = num.to_string();
-                new_line[2] = &num_string;
-                result.push(new_line.join(","));
+                result.push(with_house_number(record, &num.to_string()));
             }
         }
     } else {
-        result.push(line.to_string());
+        result.push(record.clone());
     }
 
     result
 }
+
+/// [`enumerate_house_numbers`] that, with `expand_suffixes`, also expands
+/// suffix ranges into one record per unit, see [`suffix_range`].
+pub fn enumerate_house_numbers_with(
+    record: &StringRecord,
+    expand_suffixes: bool,
+) -> Vec<StringRecord> {
+    if expand_suffixes {
+        if let Some(house_numbers) = record.get(HOUSE_NUMBER_COLUMN).and_then(suffix_range) {
+            return house_numbers
+                .iter()
+                .map(|house_number| with_house_number(record, house_number))
+                .collect();
+        }
+    }
+    enumerate_house_numbers(record)
+}
+
+/// House numbers in a suffix range: `12 A t/m F`, `12A t/m 12F`, `12a-12d`
+/// and `12a-d` all list the number with each letter in between, uppercased
+/// (`12A`, `12B`, ...). `None` when `value` is not a suffix range.
+pub fn suffix_range(value: &str) -> Option<Vec<String>> {
+    let (start, end) = value
+        .split_once(" t/m ")
+        .or_else(|| value.split_once('-'))?;
+    let (number, first) = numbered_suffix(start)?;
+    let last = match numbered_suffix(end) {
+        Some((end_number, last)) if end_number == number => last,
+        Some(_) => return None,
+        None => single_letter(end)?,
+    };
+    if first > last {
+        return None;
+    }
+    Some(
+        (first..=last)
+            .map(|suffix| format!("{}{}", number, suffix))
+            .collect(),
+    )
+}
+
+/// `12 A` or `12a` as the number and the uppercase suffix letter.
+fn numbered_suffix(value: &str) -> Option<(u32, char)> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit())?;
+    let number = value[..digits].parse().ok()?;
+    Some((number, single_letter(&value[digits..])?))
+}
+
+fn single_letter(value: &str) -> Option<char> {
+    let mut chars = value.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_alphabetic() => Some(letter.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+/// `record` with its house number replaced.
+fn with_house_number(record: &StringRecord, house_number: &str) -> StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            if i == HOUSE_NUMBER_COLUMN {
+                house_number
+            } else {
+                field
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::{ReaderBuilder, WriterBuilder};
+
+    fn read(data: &str) -> Vec<StringRecord> {
+        ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(data.as_bytes())
+            .records()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn write(records: &[StringRecord]) -> String {
+        let mut writer = WriterBuilder::new().from_writer(Vec::new());
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn expands_range() {
+        let record = StringRecord::from(vec!["1017GE", "Kalverstraat", "10 t/m 12", "Amsterdam"]);
+        let house_numbers: Vec<String> = enumerate_house_numbers(&record)
+            .iter()
+            .map(|record| record[HOUSE_NUMBER_COLUMN].to_string())
+            .collect();
+        assert_eq!(house_numbers, ["10", "11", "12"]);
+    }
+
+    #[test]
+    fn keeps_single_house_number() {
+        let record = StringRecord::from(vec!["1017GE", "Kalverstraat", "12A", "Amsterdam"]);
+        assert_eq!(enumerate_house_numbers(&record), vec![record]);
+    }
+
+    #[test]
+    fn drops_record_without_house_number() {
+        let record = StringRecord::from(vec!["1017GE", "Kalverstraat"]);
+        assert!(enumerate_house_numbers(&record).is_empty());
+    }
+
+    #[test]
+    fn drops_unparsable_range() {
+        let record = StringRecord::from(vec!["1017GE", "Kalverstraat", "a t/m c", "Amsterdam"]);
+        assert!(enumerate_house_numbers(&record).is_empty());
+    }
+
+    #[test]
+    fn expands_suffix_ranges() {
+        for value in ["12 A t/m D", "12a t/m 12d", "12a-12d", "12A-d"] {
+            assert_eq!(
+                suffix_range(value).unwrap(),
+                ["12A", "12B", "12C", "12D"],
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_non_suffix_ranges() {
+        for value in ["12 t/m 14", "12-14", "12a-13c", "12D-A", "12", "12AB-12AD"] {
+            assert_eq!(suffix_range(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn suffix_expansion_is_opt_in() {
+        let record = StringRecord::from(vec!["1017GE", "Kalverstraat", "12a-12c", "Amsterdam"]);
+        assert_eq!(
+            enumerate_house_numbers_with(&record, false),
+            vec![record.clone()]
+        );
+
+        let house_numbers: Vec<String> = enumerate_house_numbers_with(&record, true)
+            .iter()
+            .map(|record| record[HOUSE_NUMBER_COLUMN].to_string())
+            .collect();
+        assert_eq!(house_numbers, ["12A", "12B", "12C"]);
+    }
+
+    #[test]
+    fn comma_inside_quoted_street_survives() {
+        let records = read("1017GE,\"Straat, de\",1 t/m 2,Amsterdam\n");
+        let enumerated = enumerate_house_numbers(&records[0]);
+        assert_eq!(enumerated.len(), 2);
+        for (record, house_number) in enumerated.iter().zip(["1", "2"]) {
+            assert_eq!(record.len(), 4);
+            assert_eq!(&record[1], "Straat, de");
+            assert_eq!(&record[HOUSE_NUMBER_COLUMN], house_number);
+            assert_eq!(&record[3], "Amsterdam");
+        }
+    }
+
+    #[test]
+    fn quotes_inside_city_survive_round_trip() {
+        let input = "2511CV,Königinnelaan,2 t/m 3,\"'s-Gravenhage \"\"Den Haag\"\", centrum\"\n";
+        let enumerated = enumerate_house_numbers(&read(input)[0]);
+        assert_eq!(&enumerated[0][3], "'s-Gravenhage \"Den Haag\", centrum");
+
+        let written = write(&enumerated);
+        assert_eq!(
+            written,
+            "2511CV,Königinnelaan,2,\"'s-Gravenhage \"\"Den Haag\"\", centrum\"\n\
+             2511CV,Königinnelaan,3,\"'s-Gravenhage \"\"Den Haag\"\", centrum\"\n"
+        );
+        assert_eq!(read(&written), enumerated);
+    }
+
+    #[test]
+    fn line_break_inside_field_survives() {
+        let records = read("1012AB,\"Damrak\nnoord\",1,Amsterdam\n");
+        let enumerated = enumerate_house_numbers(&records[0]);
+        assert_eq!(&enumerated[0][1], "Damrak\nnoord");
+        assert_eq!(read(&write(&enumerated)), enumerated);
+    }
+}
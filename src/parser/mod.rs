@@ -1,2 +1,2 @@
+pub mod csv;
 pub mod enumurate_house_numbers;
-pub mod csv;
\ No newline at end of file
@@ -0,0 +1,117 @@
+//! Free-text address parsing
+//!
+//! Splits one string as typed into an address bar into its parts, so
+//! `/autocomplete?q=` can dispatch to the right index: `"1234AB 12"` is a
+//! postal code and house number, `"Kerkstraat 5 Amsterdam"` a street, house
+//! number and city. The rules follow how Dutch addresses are written:
+//!
+//! - a postal code is four digits and two letters, with or without a space;
+//!   the start of one on its own (`12`, `1234`, `1234A`) is a prefix search
+//! - a house number starts with a digit and follows the street or postal
+//!   code, a single letter after it is its suffix (`12 a` is `12A`)
+//! - words after the house number or postal code are the city
+//! - without either, trailing words naming a known city are the city
+//!   (`Kerkstraat Amsterdam`) and a query that is only a city name is a city
+//!   search
+//! - the remaining words are the street
+
+use serde::Serialize;
+
+use crate::query::normalize;
+use crate::query::postal_code::{self, PostalQuery};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ParsedAddress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub street: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub house_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+}
+
+impl ParsedAddress {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Parses `query`, `is_city` tells whether a name is a known city.
+pub fn parse(query: &str, is_city: impl Fn(&str) -> bool) -> ParsedAddress {
+    let tokens: Vec<&str> = query
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+
+    let mut parsed = ParsedAddress::default();
+    if let [token] = tokens[..] {
+        if let Ok(PostalQuery::Prefix(prefix)) = postal_code::parse_query(token) {
+            parsed.postal_code = Some(prefix);
+            return parsed;
+        }
+    }
+
+    let mut before: Vec<&str> = Vec::new();
+    let mut after: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        if parsed.postal_code.is_none() {
+            if let Ok(code) = postal_code::parse(token) {
+                parsed.postal_code = Some(code);
+                i += 1;
+                continue;
+            }
+            if let Some(letters) = tokens.get(i + 1).filter(|next| next.len() == 2) {
+                if let Ok(code) = postal_code::parse(&format!("{}{}", token, letters)) {
+                    parsed.postal_code = Some(code);
+                    i += 2;
+                    continue;
+                }
+            }
+        }
+        let follows_street = !before.is_empty() || parsed.postal_code.is_some();
+        if parsed.house_number.is_none()
+            && follows_street
+            && token.starts_with(|c: char| c.is_ascii_digit())
+        {
+            let mut house_number = token.to_string();
+            if let Some(suffix) = tokens.get(i + 1).filter(|next| is_single_letter(next)) {
+                house_number.push_str(suffix);
+                i += 1;
+            }
+            parsed.house_number = Some(normalize::house_number(&house_number));
+            i += 1;
+            continue;
+        }
+        if parsed.house_number.is_some() || parsed.postal_code.is_some() {
+            after.push(token);
+        } else {
+            before.push(token);
+        }
+        i += 1;
+    }
+
+    if !after.is_empty() {
+        parsed.city = Some(after.join(" "));
+    } else if parsed.house_number.is_none() && parsed.postal_code.is_none() {
+        // the longest run of trailing words naming a city, all of them makes
+        // this a city search
+        let split = (0..before.len()).find(|&start| is_city(&before[start..].join(" ")));
+        if let Some(start) = split {
+            parsed.city = Some(before[start..].join(" "));
+            before.truncate(start);
+        }
+    }
+    if !before.is_empty() {
+        parsed.street = Some(before.join(" "));
+    }
+    parsed
+}
+
+fn is_single_letter(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic()) && chars.next().is_none()
+}
@@ -0,0 +1,129 @@
+//! Postal code adjacency
+//!
+//! Two postal codes are neighbors when addresses of both lie in the same or
+//! touching cells of a grid of roughly 100 m, a geohash-style test that
+//! needs one pass over the rows instead of a distance query per address.
+//! Built at load time, so `/postal_code/{code}/neighbors` is a lookup.
+//! Neighbors are listed nearest first by the distance between the codes'
+//! mean positions.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::query::Row;
+
+/// Cell height in degrees latitude, about 111 m.
+const CELL_LAT_DEG: f64 = 0.001;
+/// Cell width in degrees longitude, about 100 m at Dutch latitudes.
+const CELL_LON_DEG: f64 = 0.0015;
+
+#[derive(Debug, Default)]
+pub struct PostalAdjacency {
+    /// Postal code to its mean position.
+    centroids: HashMap<String, (f64, f64)>,
+    /// Postal code to its neighbors, nearest first.
+    neighbors: HashMap<String, Vec<String>>,
+}
+
+impl PostalAdjacency {
+    pub fn build<'a, I: IntoIterator<Item = &'a Row>>(rows: I) -> Self {
+        let mut cells: HashMap<(i64, i64), BTreeSet<&str>> = HashMap::new();
+        let mut sums: HashMap<&str, (f64, f64, usize)> = HashMap::new();
+        for row in rows {
+            if !row.latitude.is_finite() || !row.longitude.is_finite() {
+                continue;
+            }
+            cells
+                .entry(cell(row.latitude, row.longitude))
+                .or_default()
+                .insert(&row.postal_code);
+            let sum = sums.entry(&row.postal_code).or_default();
+            sum.0 += row.latitude;
+            sum.1 += row.longitude;
+            sum.2 += 1;
+        }
+
+        let mut pairs: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+        for (&(lat, lon), codes) in &cells {
+            for dlat in -1..=1 {
+                for dlon in -1..=1 {
+                    let Some(touching) = cells.get(&(lat + dlat, lon + dlon)) else {
+                        continue;
+                    };
+                    for code in codes {
+                        let neighbors = pairs.entry(code).or_default();
+                        neighbors.extend(touching.iter().filter(|other| *other != code));
+                    }
+                }
+            }
+        }
+
+        let centroids: HashMap<String, (f64, f64)> = sums
+            .into_iter()
+            .map(|(code, (lat, lon, n))| (code.to_string(), (lat / n as f64, lon / n as f64)))
+            .collect();
+        let neighbors = pairs
+            .into_iter()
+            .map(|(code, neighbors)| {
+                let origin = centroids[code];
+                let mut neighbors: Vec<(f64, &str)> = neighbors
+                    .into_iter()
+                    .map(|other| (squared_distance(origin, centroids[other]), other))
+                    .collect();
+                neighbors.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+                let neighbors = neighbors
+                    .into_iter()
+                    .map(|(_, other)| other.to_string())
+                    .collect();
+                (code.to_string(), neighbors)
+            })
+            .collect();
+        Self {
+            centroids,
+            neighbors,
+        }
+    }
+
+    /// Neighbors of `postal_code`, nearest first. `None` for a postal code
+    /// without positioned addresses.
+    pub fn neighbors(&self, postal_code: &str) -> Option<&[String]> {
+        self.centroids.get(postal_code)?;
+        Some(
+            self.neighbors
+                .get(postal_code)
+                .map_or(&[][..], Vec::as_slice),
+        )
+    }
+
+    /// Mean position of the addresses with `postal_code`.
+    pub fn centroid(&self, postal_code: &str) -> Option<(f64, f64)> {
+        self.centroids.get(postal_code).copied()
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        let centroids: usize = self
+            .centroids
+            .keys()
+            .map(|code| code.capacity() + std::mem::size_of::<(f64, f64)>())
+            .sum();
+        let neighbors: usize = self
+            .neighbors
+            .iter()
+            .map(|(code, neighbors)| {
+                code.capacity() + neighbors.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        centroids + neighbors
+    }
+}
+
+fn cell(latitude: f64, longitude: f64) -> (i64, i64) {
+    (
+        (latitude / CELL_LAT_DEG).floor() as i64,
+        (longitude / CELL_LON_DEG).floor() as i64,
+    )
+}
+
+/// For ordering only: squared degrees, longitude scaled to latitude.
+fn squared_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).powi(2) + ((a.1 - b.1) * a.0.to_radians().cos()).powi(2)
+}
@@ -0,0 +1,114 @@
+//! Municipality merge and rename table
+//!
+//! Dutch municipalities merge regularly, so user data still carries names
+//! that no longer exist in the dataset (`Haarlemmerliede en Spaarnwoude` is
+//! now `Haarlemmermeer`). The table is a CSV file with an `old,new` header,
+//! configured as `municipality_aliases_path`, and is re-read on SIGHUP.
+//! [`normalize::municipality`] resolves through it.
+//!
+//! [`normalize::municipality`]: crate::query::normalize::municipality
+
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+use crate::query::normalize;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MunicipalityAlias {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MunicipalityAliases {
+    /// Folded old name to the alias it came from.
+    by_old: BTreeMap<String, MunicipalityAlias>,
+}
+
+impl MunicipalityAliases {
+    pub fn from_aliases<I: IntoIterator<Item = MunicipalityAlias>>(aliases: I) -> Self {
+        let by_old = aliases
+            .into_iter()
+            .map(|alias| (normalize::text(&alias.old), alias))
+            .collect();
+        Self { by_old }
+    }
+
+    pub fn load_from_csv<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_path(path.as_ref())?;
+        let aliases = rdr
+            .deserialize::<MunicipalityAlias>()
+            .collect::<Result<Vec<_>, _>>()?;
+        info!(
+            "Loaded {} municipality aliases from {}",
+            aliases.len(),
+            path.as_ref().display()
+        );
+        Ok(Self::from_aliases(aliases))
+    }
+
+    /// Current name for `name`, following chains of merges (`a -> b -> c`).
+    /// Names without an alias are returned as given.
+    pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        let mut current = name;
+        // a merge chain can't be longer than the table, which also stops cycles
+        for _ in 0..=self.by_old.len() {
+            match self.by_old.get(&normalize::text(current)) {
+                Some(alias) => current = &alias.new,
+                None => break,
+            }
+        }
+        current
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_old.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_old.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MunicipalityAlias> {
+        self.by_old.values()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ALIASES: RwLock<Arc<MunicipalityAliases>> = RwLock::new(Arc::default());
+}
+
+/// Snapshot of the active alias table.
+pub fn current() -> Arc<MunicipalityAliases> {
+    ALIASES
+        .read()
+        .map(|aliases| aliases.clone())
+        .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+}
+
+pub fn set(aliases: MunicipalityAliases) {
+    let aliases = Arc::new(aliases);
+    match ALIASES.write() {
+        Ok(mut guard) => *guard = aliases,
+        Err(poisoned) => *poisoned.into_inner() = aliases,
+    }
+}
+
+/// Loads the table from `path`, or clears it when no path is configured.
+/// On error the previous table stays active.
+pub fn load(path: Option<&str>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let aliases = match path {
+        Some(path) => MunicipalityAliases::load_from_csv(path)?,
+        None => MunicipalityAliases::default(),
+    };
+    set(aliases);
+    Ok(())
+}
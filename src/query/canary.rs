@@ -0,0 +1,128 @@
+//! Canary queries
+//!
+//! A data drop can load without errors and still be broken: a shard left
+//! out, columns shifted, a city emptied. `health.canaries` lists queries
+//! with known answers. They run after every load and reload, failures are
+//! logged as errors and keep `/readyz` at 503 until a later run passes.
+
+use serde::Serialize;
+use std::sync::RwLock;
+use tracing::{error, info};
+
+use crate::config::{self, CanaryConfig};
+use crate::deadline::Deadline;
+use crate::query::edit_distance::Fuzziness;
+use crate::query::regions::RegionFilter;
+use crate::query::{normalize, postal_code, read_data, LocationData, Row};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CanaryResult {
+    pub name: String,
+    pub passed: bool,
+    /// Why it failed.
+    pub reason: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    static ref RESULTS: RwLock<Vec<CanaryResult>> = RwLock::new(Vec::new());
+}
+
+fn name(canary: &CanaryConfig) -> String {
+    canary
+        .name
+        .clone()
+        .or_else(|| canary.postal_code.clone())
+        .or_else(|| canary.street.clone())
+        .unwrap_or_default()
+}
+
+fn rows<'a>(data: &'a LocationData, canary: &CanaryConfig) -> Result<Vec<&'a Row>, String> {
+    if let Some(code) = &canary.postal_code {
+        return Ok(data
+            .lookup_by_postal_code(&postal_code::canonical(code))
+            .map(|rows| rows.iter().collect())
+            .unwrap_or_default());
+    }
+    if let Some(street) = &canary.street {
+        let (ranked, _) = data.ranked_street_rows_until(
+            street,
+            Fuzziness::from_config(),
+            &RegionFilter::default(),
+            None,
+            Deadline::none(),
+        );
+        return Ok(ranked.into_iter().map(|(row, _)| row).collect());
+    }
+    Err("neither postal_code nor street is set".to_string())
+}
+
+/// Runs one canary against `data`.
+pub fn check(data: &LocationData, canary: &CanaryConfig) -> CanaryResult {
+    let reason = match rows(data, canary) {
+        Err(reason) => Some(reason),
+        Ok(rows) if rows.len() < canary.min_results => Some(format!(
+            "{} results, expected at least {}",
+            rows.len(),
+            canary.min_results
+        )),
+        Ok(rows) => {
+            let has = |expected: &Option<String>, field: fn(&Row) -> &str| {
+                expected.as_ref().is_none_or(|expected| {
+                    let expected = normalize::text(expected);
+                    rows.iter()
+                        .any(|row| normalize::text(field(row)) == expected)
+                })
+            };
+            if !has(&canary.expect_street, |row| &row.street) {
+                Some(format!(
+                    "no result on street '{}'",
+                    canary.expect_street.as_deref().unwrap_or_default()
+                ))
+            } else if !has(&canary.expect_city, |row| &row.city) {
+                Some(format!(
+                    "no result in city '{}'",
+                    canary.expect_city.as_deref().unwrap_or_default()
+                ))
+            } else {
+                None
+            }
+        }
+    };
+    CanaryResult {
+        name: name(canary),
+        passed: reason.is_none(),
+        reason,
+    }
+}
+
+/// Runs the configured canaries against the loaded data and keeps the
+/// results for [`results`].
+pub fn run() {
+    let canaries = config::current().health.canaries.clone();
+    let results: Vec<CanaryResult> = match read_data() {
+        Ok(data) => canaries.iter().map(|canary| check(&data, canary)).collect(),
+        Err(e) => {
+            error!("Failed to run canaries: {}", e);
+            return;
+        }
+    };
+
+    let failed: Vec<&CanaryResult> = results.iter().filter(|result| !result.passed).collect();
+    for result in &failed {
+        error!(
+            "Canary '{}' failed: {}",
+            result.name,
+            result.reason.as_deref().unwrap_or_default()
+        );
+    }
+    if !results.is_empty() && failed.is_empty() {
+        info!("All {} canaries passed", results.len());
+    }
+
+    *RESULTS.write().expect("Failed to acquire write lock") = results;
+}
+
+/// Results of the last [`run`].
+pub fn results() -> Vec<CanaryResult> {
+    RESULTS.read().expect("Failed to acquire read lock").clone()
+}
@@ -0,0 +1,133 @@
+//! City index
+//!
+//! One entry per city (and municipality, so namesakes in different
+//! municipalities stay apart) with its address count and mean position.
+//! There are only a few thousand cities, so a query scores every one with
+//! the street ranking ([`street_index::score`]) instead of keeping postings.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::query::edit_distance::Fuzziness;
+use crate::query::street_index::{self, MatchScore};
+use crate::query::tokenize::{self, Stopwords};
+use crate::query::Row;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct City {
+    /// `<city>-<municipality>`, folded, so result sections can dedup on it.
+    pub id: String,
+    pub city: String,
+    pub municipality: String,
+    pub province: String,
+    /// Addresses in the city.
+    pub addresses: usize,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(skip)]
+    tokens: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct CityIndex {
+    cities: Vec<City>,
+}
+
+impl CityIndex {
+    pub fn build<'a, I: IntoIterator<Item = &'a Row>>(rows: I) -> Self {
+        let mut cities: HashMap<(String, String), (City, f64, f64)> = HashMap::new();
+        for row in rows {
+            let tokens = tokenize::tokens(&row.city);
+            let id = [
+                tokens.join("-"),
+                tokenize::tokens(&row.municipality).join("-"),
+            ]
+            .join("-");
+            let (city, latitude, longitude) = cities
+                .entry((tokens.join(" "), row.municipality.clone()))
+                .or_insert_with(|| {
+                    let city = City {
+                        id,
+                        city: row.city.clone(),
+                        municipality: row.municipality.clone(),
+                        province: row.province.clone(),
+                        addresses: 0,
+                        latitude: 0.0,
+                        longitude: 0.0,
+                        tokens,
+                    };
+                    (city, 0.0, 0.0)
+                });
+            city.addresses += 1;
+            *latitude += row.latitude;
+            *longitude += row.longitude;
+        }
+
+        let mut cities: Vec<City> = cities
+            .into_values()
+            .map(|(mut city, latitude, longitude)| {
+                city.latitude = latitude / city.addresses as f64;
+                city.longitude = longitude / city.addresses as f64;
+                city
+            })
+            .collect();
+        cities.sort_by(|a, b| a.id.cmp(&b.id));
+        Self { cities }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    /// Cities whose name contains every token of `query` as a prefix, best
+    /// match first, larger cities first among equal matches.
+    pub fn search(&self, query: &str, fuzziness: Fuzziness) -> Vec<(&City, MatchScore)> {
+        let stopwords = Stopwords::from_config();
+        let query = tokenize::tokens(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut result: Vec<(&City, MatchScore)> = self
+            .cities
+            .iter()
+            .filter_map(|city| {
+                street_index::score(&query, &city.tokens, &stopwords, fuzziness)
+                    .map(|score| (city, score))
+            })
+            .collect();
+        result.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then_with(|| b.0.addresses.cmp(&a.0.addresses))
+                .then_with(|| a.0.id.cmp(&b.0.id))
+        });
+        result
+    }
+
+    /// The city whose name tokenizes exactly like `name`, the one with the
+    /// most addresses among namesakes.
+    pub fn lookup(&self, name: &str) -> Option<&City> {
+        let tokens = tokenize::tokens(name);
+        self.cities
+            .iter()
+            .filter(|city| city.tokens == tokens)
+            .max_by_key(|city| city.addresses)
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        self.cities
+            .iter()
+            .map(|city| {
+                std::mem::size_of::<City>()
+                    + city.id.capacity()
+                    + city.city.capacity()
+                    + city.municipality.capacity()
+                    + city.province.capacity()
+                    + city.tokens.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,142 @@
+//! Bounded edit distance for typo tolerant matching
+//!
+//! Distances are optimal string alignment distances (Levenshtein plus
+//! adjacent transpositions), so `kalverstaat` is one edit from
+//! `kalverstraat` and `kavlerstraat` is one edit as well. Matching is
+//! against prefixes of the target, as in the exact prefix search.
+
+use crate::config;
+
+/// How many typos a query token may contain, see `search` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fuzziness {
+    /// Upper bound on edits per token.
+    pub max_edits: usize,
+    /// Token characters needed per allowed edit, so short tokens stay exact.
+    pub chars_per_edit: usize,
+}
+
+impl Fuzziness {
+    pub fn exact() -> Self {
+        Self {
+            max_edits: 0,
+            chars_per_edit: 1,
+        }
+    }
+
+    pub fn from_config() -> Self {
+        let config = config::current();
+        Self {
+            max_edits: config.search.default_max_edits,
+            chars_per_edit: config.search.chars_per_edit,
+        }
+    }
+
+    /// Applies a client requested `max_edits`, capped by config.
+    pub fn with_max_edits(mut self, max_edits: Option<usize>) -> Self {
+        if let Some(requested) = max_edits {
+            self.max_edits = requested.min(config::current().search.max_edits_limit);
+        }
+        self
+    }
+
+    /// Edits allowed for `token`.
+    pub fn edits_for(&self, token: &str) -> usize {
+        self.max_edits
+            .min(token.chars().count() / self.chars_per_edit.max(1))
+    }
+}
+
+impl Default for Fuzziness {
+    fn default() -> Self {
+        Self::from_config()
+    }
+}
+
+/// One step of the distance matrix: `row` holds the distances of every
+/// prefix of `query` to the target read so far, the result is that row
+/// after reading `c`. `previous` is the row before `row` together with the
+/// target character read in between, for transpositions.
+pub fn next_row(
+    query: &[char],
+    row: &[usize],
+    previous: Option<(&[usize], char)>,
+    c: char,
+) -> Vec<usize> {
+    let mut next = Vec::with_capacity(row.len());
+    next.push(row[0] + 1);
+    for i in 1..row.len() {
+        let substitution = row[i - 1] + usize::from(query[i - 1] != c);
+        let mut distance = substitution.min(row[i] + 1).min(next[i - 1] + 1);
+        if let Some((before, last)) = previous {
+            if i > 1 && query[i - 1] == last && query[i - 2] == c {
+                distance = distance.min(before[i - 2] + 1);
+            }
+        }
+        next.push(distance);
+    }
+    next
+}
+
+/// First row of the matrix, before any target character is read.
+pub fn first_row(query: &[char]) -> Vec<usize> {
+    (0..=query.len()).collect()
+}
+
+/// Smallest distance between `query` and any prefix of `target`, or `None`
+/// when it exceeds `max_edits`.
+pub fn prefix_distance(query: &str, target: &str, max_edits: usize) -> Option<usize> {
+    let query: Vec<char> = query.chars().collect();
+    let mut row = first_row(&query);
+    let mut best = row[query.len()];
+    let mut previous: Option<(Vec<usize>, char)> = None;
+    for c in target.chars() {
+        if best == 0 || row.iter().min().is_some_and(|min| *min > max_edits) {
+            break;
+        }
+        let next = next_row(
+            &query,
+            &row,
+            previous.as_ref().map(|(row, c)| (row.as_slice(), *c)),
+            c,
+        );
+        best = best.min(next[query.len()]);
+        previous = Some((std::mem::replace(&mut row, next), c));
+    }
+    (best <= max_edits).then_some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_are_bounded() {
+        assert_eq!(prefix_distance("kalver", "kalverstraat", 0), Some(0));
+        assert_eq!(prefix_distance("kalverstaat", "kalverstraat", 1), Some(1));
+        assert_eq!(prefix_distance("kavlerstraat", "kalverstraat", 1), Some(1));
+        assert_eq!(prefix_distance("kalverstaat", "kalverstraat", 0), None);
+        assert_eq!(prefix_distance("kalvrstaat", "kalverstraat", 1), None);
+        assert_eq!(prefix_distance("kalvrstaat", "kalverstraat", 2), Some(2));
+        assert_eq!(prefix_distance("damrak", "dam", 2), None);
+        assert_eq!(prefix_distance("damrak", "dam", 3), Some(3));
+    }
+
+    #[test]
+    fn short_tokens_allow_fewer_edits() {
+        let fuzziness = Fuzziness {
+            max_edits: 2,
+            chars_per_edit: 4,
+        };
+        assert_eq!(fuzziness.edits_for("dam"), 0);
+        assert_eq!(fuzziness.edits_for("kerk"), 1);
+        assert_eq!(fuzziness.edits_for("kerkpad"), 1);
+        assert_eq!(fuzziness.edits_for("kalverstraat"), 2);
+        assert_eq!(Fuzziness::exact().edits_for("kalverstraat"), 0);
+
+        let limit = config::current().search.max_edits_limit;
+        assert_eq!(fuzziness.with_max_edits(Some(limit + 3)).max_edits, limit);
+        assert_eq!(fuzziness.with_max_edits(Some(0)).max_edits, 0);
+        assert_eq!(fuzziness.with_max_edits(None), fuzziness);
+    }
+}
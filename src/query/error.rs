@@ -0,0 +1,41 @@
+//! Errors from the query API
+//!
+//! Queries and (re)loads go through the global `LOCATION_DATA` lock, which
+//! is poisoned when a thread panics while holding it. Instead of panicking
+//! in turn, the functions return a [`QueryError`] that the HTTP layer maps
+//! to an error response.
+
+use thiserror::Error;
+
+use crate::query::filter::FilterError;
+use crate::query::load::LoadError;
+
+#[derive(Debug, Error)]
+pub enum QueryError {
+    /// A thread panicked while holding the location data lock.
+    #[error("location data is unavailable, a previous access failed")]
+    Poisoned,
+    #[error("failed to load location data: {0}")]
+    Load(#[from] LoadError),
+}
+
+impl QueryError {
+    /// Stable identifier for error responses.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QueryError::Poisoned => "data_unavailable",
+            QueryError::Load(_) => "load_failed",
+        }
+    }
+}
+
+/// Why [`QueryOptions::from_params`](crate::query::QueryOptions::from_params)
+/// rejected the query parameters.
+#[derive(Debug, Error)]
+pub enum OptionsError {
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    /// A parameter with a value outside the ones it takes.
+    #[error("unknown value '{value}' for {name}")]
+    InvalidParameter { name: &'static str, value: String },
+}
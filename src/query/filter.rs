@@ -0,0 +1,573 @@
+//! Structured filter expressions
+//!
+//! The `filter` query parameter takes a small boolean language over `Row`
+//! fields:
+//!
+//! ```text
+//! city:Amsterdam AND province:Noord-Holland AND house_number:[10 TO 20]
+//! (area:Centrum OR area:Oost) AND NOT street:"Van Rijnstraat"
+//! street:kalver*
+//! ```
+//!
+//! Terms are `field:value`, `field:"quoted value"`, `field:prefix*` or a
+//! range `field:[low TO high]` (`{`/`}` for exclusive bounds, `*` for an
+//! open end). `AND`, `OR` and `NOT` are upper case; terms next to each other
+//! are ANDed. Text comparisons go through `query::normalize`, and ranges on
+//! `house_number`, `latitude` and `longitude` are numeric. A house number
+//! value is compared as number and suffix, see `query::house_number`.
+
+use std::fmt;
+
+use crate::config::{self, PresetConfig};
+use crate::fields::Field;
+use crate::query::error::OptionsError;
+use crate::query::house_number::HouseNumber;
+use crate::query::{normalize, postal_code, Row};
+
+/// Parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    Term(Field, Condition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// Normalized value, compared for equality.
+    Equals(String),
+    /// Normalized prefix, from `value*`.
+    Prefix(String),
+    Range(Bound, Bound),
+    /// Parsed house number, any suffix of a bare number matches with
+    /// `any_suffix`.
+    HouseNumber {
+        house_number: HouseNumber,
+        any_suffix: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bound {
+    Unbounded,
+    Inclusive(String),
+    Exclusive(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    /// Byte offset in the expression where parsing failed.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Filter, FilterError> {
+        let mut parser = Parser {
+            input: expression,
+            position: 0,
+        };
+        let filter = parser.or()?;
+        parser.skip_whitespace();
+        if parser.position < expression.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(filter)
+    }
+
+    /// The filter for a preset defined in config under `presets`.
+    pub fn preset(name: &str) -> Result<Filter, OptionsError> {
+        let config = config::current();
+        let preset = config
+            .presets
+            .get(name)
+            .ok_or_else(|| OptionsError::InvalidParameter {
+                name: "preset",
+                value: name.to_string(),
+            })?;
+        Ok(Filter::from_preset(preset)?)
+    }
+
+    /// ANDs the preset parts; cities and postal prefixes are each ORed.
+    pub fn from_preset(preset: &PresetConfig) -> Result<Filter, FilterError> {
+        let mut parts = Vec::new();
+        if !preset.cities.is_empty() {
+            let cities = preset
+                .cities
+                .iter()
+                .map(|city| Filter::equals(Field::City, city))
+                .collect();
+            parts.push(flatten(cities, Filter::Or));
+        }
+        if !preset.postal_prefixes.is_empty() {
+            let prefixes = preset
+                .postal_prefixes
+                .iter()
+                .map(|prefix| {
+                    Filter::Term(
+                        Field::PostalCode,
+                        Condition::Prefix(postal_code::canonical(prefix)),
+                    )
+                })
+                .collect();
+            parts.push(flatten(prefixes, Filter::Or));
+        }
+        if let Some(expression) = &preset.filter {
+            parts.push(Filter::parse(expression)?);
+        }
+        Ok(match parts.len() {
+            // a preset without parts matches everything
+            0 => Filter::And(Vec::new()),
+            _ => flatten(parts, Filter::And),
+        })
+    }
+
+    /// `field` equal to `value`, normalized like a parsed term.
+    pub fn equals(field: Field, value: &str) -> Filter {
+        Filter::Term(field, equals(field, value))
+    }
+
+    /// House number `value`, with `any_suffix` a bare number also matches
+    /// its suffixed addresses (`12` finds `12A` and `12-2`).
+    pub fn house_number(value: &str, any_suffix: bool) -> Filter {
+        match HouseNumber::parse(value) {
+            Some(house_number) => Filter::Term(
+                Field::HouseNumber,
+                Condition::HouseNumber {
+                    house_number,
+                    any_suffix,
+                },
+            ),
+            None => Filter::equals(Field::HouseNumber, value),
+        }
+    }
+
+    /// Combines two optional filters with AND.
+    pub fn and(left: Option<Filter>, right: Option<Filter>) -> Option<Filter> {
+        match (left, right) {
+            (Some(Filter::And(mut filters)), Some(right)) => {
+                filters.push(right);
+                Some(Filter::And(filters))
+            }
+            (Some(left), Some(right)) => Some(Filter::And(vec![left, right])),
+            (left, right) => left.or(right),
+        }
+    }
+
+    pub fn matches(&self, row: &Row) -> bool {
+        match self {
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(row)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(row)),
+            Filter::Not(filter) => !filter.matches(row),
+            Filter::Term(field, condition) => condition.matches(*field, row),
+        }
+    }
+}
+
+impl Condition {
+    fn matches(&self, field: Field, row: &Row) -> bool {
+        match self {
+            Condition::Equals(value) => row_value(field, row) == *value,
+            Condition::Prefix(prefix) => row_value(field, row).starts_with(prefix.as_str()),
+            Condition::Range(low, high) => match numeric(field, row) {
+                Some(number) => {
+                    let parse = |value: &str| value.parse::<f64>().ok();
+                    low.admits(|bound| parse(bound).map(|bound| number.total_cmp(&bound)))
+                        && high.admits(|bound| parse(bound).map(|bound| bound.total_cmp(&number)))
+                }
+                None if is_numeric(field) => false,
+                None => {
+                    let value = row_value(field, row);
+                    low.admits(|bound| Some(value.as_str().cmp(bound)))
+                        && high.admits(|bound| Some(bound.cmp(value.as_str())))
+                }
+            },
+            Condition::HouseNumber {
+                house_number,
+                any_suffix,
+            } => HouseNumber::parse(&row.house_number)
+                .is_some_and(|row| house_number.matches(&row, *any_suffix)),
+        }
+    }
+}
+
+impl Bound {
+    /// `compare` orders the row value against the bound (or the bound
+    /// against the row value for the upper end); `None` never matches.
+    fn admits<F: Fn(&str) -> Option<std::cmp::Ordering>>(&self, compare: F) -> bool {
+        match self {
+            Bound::Unbounded => true,
+            Bound::Inclusive(bound) => compare(bound).is_some_and(|ordering| ordering.is_ge()),
+            Bound::Exclusive(bound) => compare(bound).is_some_and(|ordering| ordering.is_gt()),
+        }
+    }
+}
+
+fn is_numeric(field: Field) -> bool {
+    matches!(
+        field,
+        Field::HouseNumber | Field::Latitude | Field::Longitude
+    )
+}
+
+/// Numeric value for range comparisons; the house number is its leading
+/// digits, so `12A` is 12.
+fn numeric(field: Field, row: &Row) -> Option<f64> {
+    match field {
+        Field::Latitude => Some(row.latitude),
+        Field::Longitude => Some(row.longitude),
+        Field::HouseNumber => {
+            let digits: String = row
+                .house_number
+                .trim()
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            digits.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+fn row_value(field: Field, row: &Row) -> String {
+    match field {
+        Field::Id => normalize::field(field, &row.id()),
+        Field::PostalCode => normalize::field(field, &row.postal_code),
+        Field::Street => normalize::field(field, &row.street),
+        Field::HouseNumber => normalize::field(field, &row.house_number),
+        Field::City => normalize::field(field, &row.city),
+        Field::Area => normalize::field(field, &row.area),
+        Field::Neighborhood => normalize::field(field, &row.neighborhood),
+        Field::Municipality => normalize::field(field, &row.municipality),
+        Field::Province => normalize::field(field, &row.province),
+        Field::Latitude => row.latitude.to_string(),
+        Field::Longitude => row.longitude.to_string(),
+        Field::Country => normalize::field(field, row.country()),
+        Field::Precision => row.precision.name().to_string(),
+        Field::ValidFrom => row
+            .valid_from
+            .map(|date| date.to_string())
+            .unwrap_or_default(),
+        Field::Extra => normalize::field(field, row.extra.as_deref().unwrap_or_default()),
+    }
+}
+
+/// Equality on `field`, structured for parsable house numbers.
+fn equals(field: Field, value: &str) -> Condition {
+    match (field, HouseNumber::parse(value)) {
+        (Field::HouseNumber, Some(house_number)) => Condition::HouseNumber {
+            house_number,
+            any_suffix: false,
+        },
+        _ => Condition::Equals(normalize::field(field, value)),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> FilterError {
+        FilterError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.position = self.input.len() - trimmed.len();
+    }
+
+    /// Consumes `keyword` when it is followed by whitespace, `(` or the end.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let Some(after) = self.rest().strip_prefix(keyword) else {
+            return false;
+        };
+        let boundary = after
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || c == '(');
+        if boundary {
+            self.position += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterError> {
+        let mut filters = vec![self.and()?];
+        while self.keyword("OR") {
+            filters.push(self.and()?);
+        }
+        Ok(flatten(filters, Filter::Or))
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterError> {
+        let mut filters = vec![self.not()?];
+        loop {
+            if self.keyword("AND") {
+                filters.push(self.not()?);
+                continue;
+            }
+            // implicit AND between adjacent terms
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.is_empty() || rest.starts_with(')') || self.peek_keyword("OR") {
+                break;
+            }
+            filters.push(self.not()?);
+        }
+        Ok(flatten(filters, Filter::And))
+    }
+
+    fn peek_keyword(&mut self, keyword: &str) -> bool {
+        let position = self.position;
+        let found = self.keyword(keyword);
+        self.position = position;
+        found
+    }
+
+    fn not(&mut self) -> Result<Filter, FilterError> {
+        if self.keyword("NOT") {
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Filter, FilterError> {
+        self.skip_whitespace();
+        if self.rest().starts_with('(') {
+            self.position += 1;
+            let filter = self.or()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with(')') {
+                return Err(self.error("expected ')'"));
+            }
+            self.position += 1;
+            return Ok(filter);
+        }
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<Filter, FilterError> {
+        let start = self.position;
+        let Some(colon) = self.rest().find(':') else {
+            return Err(self.error("expected field:value"));
+        };
+        let name = &self.rest()[..colon];
+        let field = Field::from_name(name).ok_or_else(|| FilterError {
+            position: start,
+            message: format!("unknown field '{}'", name.trim()),
+        })?;
+        self.position += colon + 1;
+
+        let condition = match self.rest().chars().next() {
+            Some('[' | '{') => self.range(field)?,
+            Some('"') => equals(field, &self.quoted()?),
+            Some(c) if !c.is_whitespace() && c != ')' => {
+                let value = self.bare();
+                match value.strip_suffix('*') {
+                    Some(prefix) => Condition::Prefix(normalize::field(field, prefix)),
+                    None => equals(field, value),
+                }
+            }
+            _ => return Err(self.error("expected a value")),
+        };
+        Ok(Filter::Term(field, condition))
+    }
+
+    fn bare(&mut self) -> &str {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == ')')
+            .unwrap_or(rest.len());
+        let start = self.position;
+        self.position += end;
+        &self.input[start..self.position]
+    }
+
+    fn quoted(&mut self) -> Result<String, FilterError> {
+        // opening quote
+        self.position += 1;
+        let Some(end) = self.rest().find('"') else {
+            return Err(self.error("unterminated quote"));
+        };
+        let value = self.rest()[..end].to_string();
+        self.position += end + 1;
+        Ok(value)
+    }
+
+    fn range(&mut self, field: Field) -> Result<Condition, FilterError> {
+        let inclusive_low = self.rest().starts_with('[');
+        self.position += 1;
+        self.skip_whitespace();
+        let low = self.bound_value(field, inclusive_low);
+        if !self.keyword("TO") {
+            return Err(self.error("expected 'TO' in range"));
+        }
+        self.skip_whitespace();
+        let rest = self.rest();
+        let end = rest
+            .find([']', '}'])
+            .ok_or_else(|| self.error("unterminated range"))?;
+        let value = rest[..end].trim().to_string();
+        let inclusive_high = rest[end..].starts_with(']');
+        self.position += end + 1;
+        let high = make_bound(field, &value, inclusive_high);
+        Ok(Condition::Range(low, high))
+    }
+
+    fn bound_value(&mut self, field: Field, inclusive: bool) -> Bound {
+        let rest = self.rest();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let value = rest[..end].to_string();
+        self.position += end;
+        make_bound(field, &value, inclusive)
+    }
+}
+
+fn make_bound(field: Field, value: &str, inclusive: bool) -> Bound {
+    if value == "*" || value.is_empty() {
+        return Bound::Unbounded;
+    }
+    let value = if is_numeric(field) {
+        value.to_string()
+    } else {
+        normalize::field(field, value)
+    };
+    if inclusive {
+        Bound::Inclusive(value)
+    } else {
+        Bound::Exclusive(value)
+    }
+}
+
+fn flatten(mut filters: Vec<Filter>, combine: fn(Vec<Filter>) -> Filter) -> Filter {
+    if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        combine(filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn city(value: &str) -> Filter {
+        Filter::equals(Field::City, value)
+    }
+
+    fn position(expression: &str) -> usize {
+        Filter::parse(expression).unwrap_err().position
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let street = Filter::equals(Field::Street, "Damrak");
+        assert_eq!(
+            Filter::parse("city:Utrecht OR city:Amsterdam AND street:Damrak").unwrap(),
+            Filter::Or(vec![
+                city("Utrecht"),
+                Filter::And(vec![city("Amsterdam"), street.clone()])
+            ])
+        );
+        assert_eq!(
+            Filter::parse("NOT city:Utrecht street:Damrak").unwrap(),
+            Filter::And(vec![Filter::Not(Box::new(city("Utrecht"))), street])
+        );
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            Filter::parse("(city:Utrecht OR city:Amsterdam) AND NOT(area:Oost)").unwrap(),
+            Filter::And(vec![
+                Filter::Or(vec![city("Utrecht"), city("Amsterdam")]),
+                Filter::Not(Box::new(Filter::equals(Field::Area, "Oost")))
+            ])
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        assert_eq!(
+            Filter::parse(r#"street:"Van Rijnstraat" city:Utrecht"#).unwrap(),
+            Filter::And(vec![
+                Filter::equals(Field::Street, "Van Rijnstraat"),
+                city("Utrecht")
+            ])
+        );
+        assert_eq!(
+            Filter::parse(r#"street:"kalver*""#).unwrap(),
+            Filter::equals(Field::Street, "kalver*")
+        );
+    }
+
+    #[test]
+    fn range_bounds() {
+        assert_eq!(
+            Filter::parse("house_number:[12 TO 14}").unwrap(),
+            Filter::Term(
+                Field::HouseNumber,
+                Condition::Range(
+                    Bound::Inclusive("12".to_string()),
+                    Bound::Exclusive("14".to_string())
+                )
+            )
+        );
+        assert_eq!(
+            Filter::parse("latitude:{* TO 52.1]").unwrap(),
+            Filter::Term(
+                Field::Latitude,
+                Condition::Range(Bound::Unbounded, Bound::Inclusive("52.1".to_string()))
+            )
+        );
+
+        let numbers = |expression: &str| {
+            let filter = Filter::parse(expression).unwrap();
+            let mut numbers: Vec<String> = fixtures::rows()
+                .into_iter()
+                .filter(|row| row.postal_code == "1017GE" && filter.matches(row))
+                .map(|row| row.house_number)
+                .collect();
+            numbers.sort();
+            numbers
+        };
+        assert_eq!(numbers("house_number:[12 TO 14]"), ["12", "14", "14A"]);
+        assert_eq!(numbers("house_number:{12 TO *]"), ["14", "14A"]);
+        assert_eq!(numbers("house_number:[* TO 14}"), ["12"]);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(position("colour:red"), 0);
+        assert_eq!(position("city:Utrecht colour:red"), 13);
+        assert_eq!(position("city:Utrecht AND"), 16);
+        assert_eq!(position("(city:Utrecht"), 13);
+        assert_eq!(position(r#"street:"Van Rijn"#), 8);
+        assert_eq!(position("house_number:[1 20]"), 16);
+        assert_eq!(position("house_number:[1 TO 20"), 19);
+        assert_eq!(position("city:"), 5);
+        assert_eq!(position("city:Utrecht)"), 12);
+    }
+}
@@ -0,0 +1,50 @@
+//! Dutch house numbers
+//!
+//! A house number is a number with an optional suffix: a letter (`12A`), a
+//! unit number (`12-2`, `12/2`) or a word (`12bis`, `12 hs`). The suffix is
+//! compared without case, whitespace and leading separators, so `12-2`,
+//! `12 2` and `12/2` are the same address. Filtering on `12` matches only
+//! the bare number, or every suffix of it with `any_suffix`.
+
+/// Characters between the number and its suffix that carry no meaning.
+const SEPARATORS: [char; 3] = ['-', '/', '_'];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HouseNumber {
+    pub number: u32,
+    /// Uppercase, without whitespace or leading separators, empty for a bare
+    /// number.
+    pub suffix: String,
+}
+
+impl HouseNumber {
+    /// `None` when `value` does not start with a number.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let digits = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let number = value[..digits].parse().ok()?;
+        let suffix = value[digits..]
+            .trim_start_matches(|c: char| c.is_whitespace() || SEPARATORS.contains(&c))
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_uppercase)
+            .collect();
+        Some(Self { number, suffix })
+    }
+
+    /// Whether a row with house number `other` is this address. Without a
+    /// suffix and with `any_suffix`, every suffix of the number matches.
+    pub fn matches(&self, other: &HouseNumber, any_suffix: bool) -> bool {
+        self.number == other.number
+            && (self.suffix == other.suffix || (any_suffix && self.suffix.is_empty()))
+    }
+
+    /// Sort key for listing units: the number, then the bare number, letter
+    /// and word suffixes, then numbered units in numeric order, so `12`,
+    /// `12A`, `12B`, `12-1`, `12-2`, `12-10`.
+    pub fn unit_order(&self) -> (u32, Option<u32>, &str) {
+        (self.number, self.suffix.parse().ok(), &self.suffix)
+    }
+}
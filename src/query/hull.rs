@@ -0,0 +1,52 @@
+//! Convex hull of address positions
+//!
+//! Approximates the area a postal code covers without a boundaries dataset:
+//! the smallest convex polygon around its addresses, computed with Andrew's
+//! monotone chain. Positions are `(longitude, latitude)`, the GeoJSON axis
+//! order, and treated as planar, which is fine at postal code scale.
+
+/// Twice the triangle area, in square degrees, below which three points
+/// count as one line: about a square centimeter, so rounding noise in the
+/// coordinates does not add vertices.
+const COLLINEAR_EPSILON: f64 = 1e-14;
+
+/// Hull vertices counter-clockwise, starting at the lowest longitude, not
+/// closed. Fewer than three vertices when the points are all the same or on
+/// one line.
+pub fn convex_hull(mut points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    points.retain(|(x, y)| x.is_finite() && y.is_finite());
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut lower: Vec<(f64, f64)> = Vec::new();
+    for &point in &points {
+        while lower.len() >= 2
+            && cross(lower[lower.len() - 2], lower[lower.len() - 1], point) <= COLLINEAR_EPSILON
+        {
+            lower.pop();
+        }
+        lower.push(point);
+    }
+    let mut upper: Vec<(f64, f64)> = Vec::new();
+    for &point in points.iter().rev() {
+        while upper.len() >= 2
+            && cross(upper[upper.len() - 2], upper[upper.len() - 1], point) <= COLLINEAR_EPSILON
+        {
+            upper.pop();
+        }
+        upper.push(point);
+    }
+    // each chain ends where the other starts
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+/// Positive when `o -> a -> b` turns counter-clockwise.
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
@@ -0,0 +1,212 @@
+//! CSV load checks
+//!
+//! By default a bad row is skipped and counted so one broken line does not
+//! take the service down. In strict mode (`strict_loading` in config or
+//! `--strict` on the command line) the first problem aborts the load
+//! instead, for deployments where silently dropping addresses is worse than
+//! not starting. `places-cli validate` loads in report mode, which skips
+//! like the default but keeps every problem.
+
+use csv::StringRecord;
+use std::fmt;
+
+use crate::config;
+use crate::query::postal_code::PostalCodeError;
+
+/// Columns every data file must have.
+pub const REQUIRED_COLUMNS: [&str; 10] = [
+    "postal_code",
+    "street",
+    "house_number",
+    "city",
+    "area",
+    "neighborhood",
+    "municipality",
+    "province",
+    "latitude",
+    "longitude",
+];
+
+/// Columns added to the schema later, see `Row`.
+pub const OPTIONAL_COLUMNS: [&str; 4] = ["country", "precision", "valid_from", "extra"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Skip bad rows and log a warning.
+    Lenient,
+    /// Fail on the first bad row.
+    Strict,
+    /// Skip bad rows like `Lenient` and keep every problem, for
+    /// `places-cli validate`.
+    Report,
+}
+
+impl LoadMode {
+    pub fn from_config() -> Self {
+        if config::current().strict_loading {
+            LoadMode::Strict
+        } else {
+            LoadMode::Lenient
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadIssue {
+    /// The file or folder could not be read.
+    Unreadable(String),
+    /// The header lacks required columns or has columns the schema does not
+    /// know.
+    SchemaMismatch {
+        missing: Vec<String>,
+        unknown: Vec<String>,
+    },
+    /// The row does not parse into a `Row`.
+    Malformed(String),
+    /// Latitude or longitude is empty or not a finite number.
+    MissingCoordinate,
+    /// The postal code cannot exist, see [`crate::query::postal_code`].
+    InvalidPostalCode(PostalCodeError),
+    /// Another row already has this postal code and house number.
+    DuplicateId(String),
+    /// The folder holds no loadable rows at all.
+    Empty,
+}
+
+impl LoadIssue {
+    /// Stable identifier for reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LoadIssue::Unreadable(_) => "unreadable",
+            LoadIssue::SchemaMismatch { .. } => "schema_mismatch",
+            LoadIssue::Malformed(_) => "malformed",
+            LoadIssue::MissingCoordinate => "missing_coordinate",
+            LoadIssue::InvalidPostalCode(_) => "invalid_postal_code",
+            LoadIssue::DuplicateId(_) => "duplicate_id",
+            LoadIssue::Empty => "empty",
+        }
+    }
+}
+
+impl fmt::Display for LoadIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadIssue::Unreadable(message) => write!(f, "unreadable: {}", message),
+            LoadIssue::SchemaMismatch { missing, unknown } => write!(
+                f,
+                "schema mismatch: missing columns [{}], unknown columns [{}]",
+                missing.join(", "),
+                unknown.join(", ")
+            ),
+            LoadIssue::Malformed(message) => write!(f, "malformed row: {}", message),
+            LoadIssue::MissingCoordinate => write!(f, "missing coordinate"),
+            LoadIssue::InvalidPostalCode(e) => write!(f, "invalid postal code: {}", e),
+            LoadIssue::DuplicateId(id) => write!(f, "duplicate id {}", id),
+            LoadIssue::Empty => write!(f, "no rows"),
+        }
+    }
+}
+
+/// A load problem with where it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    pub path: String,
+    /// 1-based line in the file, `None` for file level problems.
+    pub line: Option<u64>,
+    pub issue: LoadIssue,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path, line, self.issue),
+            None => write!(f, "{}: {}", self.path, self.issue),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Compares a header against the schema. `None` when it has every required
+/// column and nothing unknown.
+pub fn check_headers(headers: &StringRecord) -> Option<LoadIssue> {
+    let missing: Vec<String> = REQUIRED_COLUMNS
+        .iter()
+        .filter(|column| !headers.iter().any(|header| header == **column))
+        .map(|column| column.to_string())
+        .collect();
+    let unknown: Vec<String> = headers
+        .iter()
+        .filter(|header| !REQUIRED_COLUMNS.contains(header) && !OPTIONAL_COLUMNS.contains(header))
+        .map(str::to_string)
+        .collect();
+    if missing.is_empty() && unknown.is_empty() {
+        None
+    } else {
+        Some(LoadIssue::SchemaMismatch { missing, unknown })
+    }
+}
+
+/// Whether the latitude and longitude cells of `record` hold finite
+/// numbers.
+pub fn has_coordinates(headers: &StringRecord, record: &StringRecord) -> bool {
+    ["latitude", "longitude"].iter().all(|column| {
+        headers
+            .iter()
+            .position(|header| header == *column)
+            .and_then(|i| record.get(i))
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .is_some_and(f64::is_finite)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields::{FieldSet, Projected, Projection};
+    use crate::query::LocationData;
+
+    #[test]
+    fn optional_columns_are_parsed_and_projected() {
+        let load = |name: &str, csv: &str| {
+            let path =
+                std::env::temp_dir().join(format!("places-{}-{}.csv", std::process::id(), name));
+            std::fs::write(&path, csv).unwrap();
+            let mut data = LocationData::new();
+            data.load_from_csv(path.to_str().unwrap(), LoadMode::Strict)
+                .unwrap();
+            std::fs::remove_file(&path).ok();
+            let row = data.lookup_by_postal_code("1017GE").unwrap()[0].clone();
+            let projection = Projection {
+                fields: FieldSet::parse("country,precision,valid_from,extra").unwrap(),
+                ..Projection::from_config()
+            };
+            serde_json::to_value(Projected::new(&row, &projection)).unwrap()
+        };
+        let header = "postal_code,street,house_number,city,area,neighborhood,municipality,province,latitude,longitude";
+        let address = "1017GE,Kerkstraat,12,Amsterdam,Centrum,Grachtengordel,Amsterdam,Noord-Holland,52.36,4.88";
+
+        let old = load("old", &format!("{}\n{}\n", header, address));
+        assert_eq!(
+            old,
+            serde_json::json!({ "country": "NL", "precision": "address" })
+        );
+
+        let new = load(
+            "new",
+            &format!(
+                "{},country,precision,valid_from,extra\n{},BE,street,2020-01-31,bag:0363\n",
+                header, address
+            ),
+        );
+        assert_eq!(
+            new,
+            serde_json::json!({
+                "country": "BE",
+                "precision": "street",
+                "valid_from": "2020-01-31",
+                "extra": "bag:0363"
+            })
+        );
+    }
+}
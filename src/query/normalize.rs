@@ -0,0 +1,146 @@
+//! Query normalization
+//!
+//! The same rules are used to build the index keys at load time, to look
+//! queries up and to build cache keys, so `"1017 ge"` and `"1017GE"` land in
+//! the same index bucket and share a cache entry. [`field`] picks the rule
+//! for a row field; index keys, query parameters and filter comparisons all
+//! go through it, so `" kerkStraat "` behaves like `"kerkstraat"` everywhere.
+
+use std::collections::HashMap;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::fields::Field;
+use crate::query::{aliases, postal_code, registry};
+
+/// Query parameters matched against a row field, normalized by [`field`].
+const FIELD_PARAMS: [(&str, Field); 8] = [
+    ("postal_code", Field::PostalCode),
+    ("street", Field::Street),
+    ("house_number", Field::HouseNumber),
+    ("city", Field::City),
+    ("area", Field::Area),
+    ("neighborhood", Field::Neighborhood),
+    ("municipality", Field::Municipality),
+    ("province", Field::Province),
+];
+
+/// Folds free text for comparison. `search.normalizer` picks the
+/// implementation by name from the [`registry`], so deployments can add
+/// their own rules.
+///
+/// [`registry`]: crate::query::registry
+pub trait Normalizer: Send + Sync {
+    fn text(&self, value: &str) -> String;
+}
+
+/// The built-in `default` normalizer, see [`fold`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Folding;
+
+impl Normalizer for Folding {
+    fn text(&self, value: &str) -> String {
+        fold(value)
+    }
+}
+
+/// Free text through the configured [`Normalizer`]. Every rule below that
+/// folds names goes through it.
+pub fn text(value: &str) -> String {
+    registry::normalizer().text(value)
+}
+
+/// Lowercases, strips accents and collapses whitespace:
+/// `"  Laan van Nieuw  Oost-Indië "` becomes `"laan van nieuw oost-indie"`.
+/// Compatibility forms are folded too (NFKD), so full-width letters and
+/// ligatures like `ﬁ` match their plain spelling, and letters without a
+/// decomposition (`ø`, `ł`, `ß`, ...) are spelled out, see [`fold_letter`].
+pub fn fold(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    for c in value
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+    {
+        match fold_letter(c) {
+            Some(folded) => stripped.push_str(folded),
+            None => stripped.push(c),
+        }
+    }
+    collapse_whitespace(&stripped)
+}
+
+/// ASCII spelling of lowercase letters that carry a diacritic but do not
+/// decompose into a base letter and a combining mark.
+fn fold_letter(c: char) -> Option<&'static str> {
+    let folded = match c {
+        'ø' => "o",
+        'đ' | 'ð' => "d",
+        'ł' => "l",
+        'ħ' => "h",
+        'ı' => "i",
+        'ŧ' => "t",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        _ => return None,
+    };
+    Some(folded)
+}
+
+/// Canonical house number: uppercase without whitespace, `"12 a"` becomes
+/// `"12A"`.
+pub fn house_number(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Folded current name of a municipality, resolving merges and renames
+/// through the alias table: `"Haarlemmerliede en Spaarnwoude"` becomes
+/// `"haarlemmermeer"`.
+pub fn municipality(value: &str) -> String {
+    text(aliases::current().resolve(value))
+}
+
+/// Trims and replaces every run of whitespace with a single space.
+pub fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Normalizes a value of `field`, for both sides of every comparison:
+/// postal codes with [`postal_code::canonical`], house numbers with
+/// [`house_number`], municipalities with [`municipality`], coordinates
+/// trimmed and everything else with [`text`].
+pub fn field(field: Field, value: &str) -> String {
+    match field {
+        Field::PostalCode => postal_code::canonical(value),
+        Field::HouseNumber => house_number(value),
+        Field::Municipality => municipality(value),
+        Field::Latitude | Field::Longitude | Field::ValidFrom => value.trim().to_string(),
+        _ => text(value),
+    }
+}
+
+/// Normalizes a single query parameter by name. Parameters without a rule
+/// are kept as they are so the cache never merges queries that return
+/// different results.
+pub fn param(name: &str, value: &str) -> String {
+    match FIELD_PARAMS.iter().find(|(param, _)| *param == name) {
+        Some((_, param_field)) => field(*param_field, value),
+        None => value.to_string(),
+    }
+}
+
+/// Normalized query parameters in a stable (sorted) order.
+pub fn params(params: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut normalized: Vec<(String, String)> = params
+        .iter()
+        .map(|(name, value)| (name.clone(), param(name, value)))
+        .collect();
+    normalized.sort();
+    normalized
+}
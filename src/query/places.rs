@@ -0,0 +1,152 @@
+//! Neighborhood and area index
+//!
+//! Maps each neighborhood and area to the postal codes it covers, so rows
+//! are looked up through `postal_map` instead of being stored a third time,
+//! and keeps per-city listings with address counts.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::query::{normalize, Row};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlaceKind {
+    Neighborhood,
+    Area,
+}
+
+impl PlaceKind {
+    pub const ALL: [PlaceKind; 2] = [PlaceKind::Neighborhood, PlaceKind::Area];
+
+    /// Query parameter and response section name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlaceKind::Neighborhood => "neighborhood",
+            PlaceKind::Area => "area",
+        }
+    }
+
+    pub fn of<'a>(&self, row: &'a Row) -> &'a str {
+        match self {
+            PlaceKind::Neighborhood => &row.neighborhood,
+            PlaceKind::Area => &row.area,
+        }
+    }
+}
+
+/// Neighborhood or area with the number of addresses in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaceCount {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CityPlaces {
+    city: String,
+    neighborhoods: BTreeMap<String, usize>,
+    areas: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct PlaceIndex {
+    /// Folded place name to the sorted postal codes it covers.
+    postal_codes: HashMap<(PlaceKind, String), Vec<String>>,
+    /// Folded city name to its places.
+    cities: HashMap<String, CityPlaces>,
+}
+
+impl PlaceIndex {
+    /// Builds the index from `(canonical postal code, rows)` pairs.
+    pub fn build<'a, I>(postal_codes: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a String, &'a Vec<Row>)>,
+    {
+        let mut covered: HashMap<(PlaceKind, String), BTreeSet<String>> = HashMap::new();
+        let mut cities: HashMap<String, CityPlaces> = HashMap::new();
+        for (postal_code, rows) in postal_codes {
+            for row in rows {
+                let city = cities
+                    .entry(normalize::text(&row.city))
+                    .or_insert_with(|| CityPlaces {
+                        city: row.city.clone(),
+                        ..CityPlaces::default()
+                    });
+                for kind in PlaceKind::ALL {
+                    let name = kind.of(row);
+                    if name.is_empty() {
+                        continue;
+                    }
+                    covered
+                        .entry((kind, normalize::text(name)))
+                        .or_default()
+                        .insert(postal_code.clone());
+                    let listing = match kind {
+                        PlaceKind::Neighborhood => &mut city.neighborhoods,
+                        PlaceKind::Area => &mut city.areas,
+                    };
+                    *listing.entry(name.to_string()).or_default() += 1;
+                }
+            }
+        }
+
+        Self {
+            postal_codes: covered
+                .into_iter()
+                .map(|(key, postal_codes)| (key, postal_codes.into_iter().collect()))
+                .collect(),
+            cities,
+        }
+    }
+
+    /// Postal codes with at least one address in the named place.
+    pub fn postal_codes(&self, kind: PlaceKind, name: &str) -> &[String] {
+        self.postal_codes
+            .get(&(kind, normalize::text(name)))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Display name of the city and its places of `kind`, sorted by name.
+    /// `None` for an unknown city.
+    pub fn list(&self, city: &str, kind: PlaceKind) -> Option<(&str, Vec<PlaceCount>)> {
+        let places = self.cities.get(&normalize::text(city))?;
+        let listing = match kind {
+            PlaceKind::Neighborhood => &places.neighborhoods,
+            PlaceKind::Area => &places.areas,
+        };
+        let counts = listing
+            .iter()
+            .map(|(name, count)| PlaceCount {
+                name: name.clone(),
+                count: *count,
+            })
+            .collect();
+        Some((&places.city, counts))
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        let postal_codes: usize = self
+            .postal_codes
+            .iter()
+            .map(|((_, name), codes)| {
+                name.capacity() + codes.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        let cities: usize = self
+            .cities
+            .iter()
+            .map(|(key, places)| {
+                key.capacity()
+                    + places.city.capacity()
+                    + places
+                        .neighborhoods
+                        .keys()
+                        .chain(places.areas.keys())
+                        .map(String::capacity)
+                        .sum::<usize>()
+            })
+            .sum();
+        postal_codes + cities
+    }
+}
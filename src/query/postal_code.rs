@@ -0,0 +1,158 @@
+//! Dutch postal codes
+//!
+//! A postal code is four digits from 1000 up and two letters, written
+//! `1234 AB` on envelopes and `1234AB` here. The letter pairs `SA`, `SD` and
+//! `SS` are never issued. The same rules apply when loading data and to
+//! client input, so a code that cannot exist is rejected with a 400 instead
+//! of silently finding nothing.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PostalCodeError {
+    /// Nothing left after removing separators.
+    Empty,
+    /// Not a postal code or the start of one.
+    Malformed(String),
+    /// The digits start with 0, the lowest code is 1000.
+    LeadingZero(String),
+    /// The letters are `SA`, `SD` or `SS`.
+    UnusedLetters(String),
+}
+
+impl PostalCodeError {
+    /// Stable identifier for error responses and load reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PostalCodeError::Empty => "empty",
+            PostalCodeError::Malformed(_) => "malformed",
+            PostalCodeError::LeadingZero(_) => "leading_zero",
+            PostalCodeError::UnusedLetters(_) => "unused_letters",
+        }
+    }
+}
+
+impl fmt::Display for PostalCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostalCodeError::Empty => write!(f, "postal code is empty"),
+            PostalCodeError::Malformed(code) => {
+                write!(f, "{} is not a postal code or the start of one", code)
+            }
+            PostalCodeError::LeadingZero(code) => {
+                write!(f, "{} starts with 0, postal codes start at 1000", code)
+            }
+            PostalCodeError::UnusedLetters(code) => {
+                write!(f, "{} uses letters that are never issued", code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PostalCodeError {}
+
+/// A postal code as a client may send it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PostalQuery {
+    /// `1234AB`
+    Full(String),
+    /// The start of a postal code as typed so far, `1`, `12`, `1234` or
+    /// `1234A`: every code starting with it.
+    Prefix(String),
+}
+
+impl PostalQuery {
+    pub fn as_str(&self) -> &str {
+        match self {
+            PostalQuery::Full(code) | PostalQuery::Prefix(code) => code,
+        }
+    }
+}
+
+/// Uppercase without separators, `"1234 ab"` and `"1234-AB"` both become
+/// `"1234AB"`. Does not validate, see [`parse`].
+pub fn canonical(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '_' | '-'))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// A complete postal code in canonical form.
+pub fn parse(value: &str) -> Result<String, PostalCodeError> {
+    match parse_query(value)? {
+        PostalQuery::Full(code) => Ok(code),
+        PostalQuery::Prefix(code) => Err(PostalCodeError::Malformed(code)),
+    }
+}
+
+/// A complete postal code or a prefix of one, in canonical form.
+pub fn parse_query(value: &str) -> Result<PostalQuery, PostalCodeError> {
+    let code = canonical(value);
+    if code.is_empty() {
+        return Err(PostalCodeError::Empty);
+    }
+    if !code.is_ascii() {
+        return Err(PostalCodeError::Malformed(code));
+    }
+    let (digits, letters) = code.split_at(code.len().min(4));
+    if !digits.bytes().all(|b| b.is_ascii_digit())
+        || (digits.len() < 4 && !letters.is_empty())
+        || letters.len() > 2
+        || !letters.bytes().all(|b| b.is_ascii_uppercase())
+    {
+        return Err(PostalCodeError::Malformed(code));
+    }
+    if digits.starts_with('0') {
+        return Err(PostalCodeError::LeadingZero(code));
+    }
+    if matches!(letters, "SA" | "SD" | "SS") {
+        return Err(PostalCodeError::UnusedLetters(code));
+    }
+    Ok(if letters.len() == 2 {
+        PostalQuery::Full(code)
+    } else {
+        PostalQuery::Prefix(code)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_codes_and_prefixes() {
+        assert_eq!(
+            parse_query(" 1017 ge "),
+            Ok(PostalQuery::Full("1017GE".to_string()))
+        );
+        assert_eq!(
+            parse_query("1017-g"),
+            Ok(PostalQuery::Prefix("1017G".to_string()))
+        );
+        for prefix in ["1", "10", "1017"] {
+            assert_eq!(
+                parse_query(prefix),
+                Ok(PostalQuery::Prefix(prefix.to_string()))
+            );
+        }
+        assert_eq!(parse("1017_ge"), Ok("1017GE".to_string()));
+        assert_eq!(
+            parse("1017"),
+            Err(PostalCodeError::Malformed("1017".to_string()))
+        );
+    }
+
+    #[test]
+    fn impossible_codes_are_rejected() {
+        let kind = |value: &str| parse_query(value).unwrap_err().kind();
+        assert_eq!(kind(" - "), "empty");
+        assert_eq!(kind("0123AB"), "leading_zero");
+        assert_eq!(kind("1234SS"), "unused_letters");
+        assert_eq!(kind("12A"), "malformed");
+        assert_eq!(kind("1234ABC"), "malformed");
+        assert_eq!(kind("1234É"), "malformed");
+        assert_eq!(kind("12345"), "malformed");
+    }
+}
@@ -0,0 +1,206 @@
+//! Street result ranking
+//!
+//! Turns a street's [`MatchScore`] into one relevance number in `(0, 1]` so
+//! results can be ordered across streets and the order can be explained in
+//! the response. From strongest to weakest the penalties are: skipped
+//! stopwords, typos, how the query matches ([`MatchKind`]: the whole street,
+//! its start, or somewhere inside), addresses outside the preferred city
+//! (`city_bias`), token order and position, and finally street length, so
+//! shorter streets come first among otherwise equal matches.
+//!
+//! That order is the [`Lexical`] [`Ranker`]. `search.ranking.strategy`
+//! selects another built-in one, [`DistanceBiased`] or [`Popularity`], and
+//! code embedding the crate can pass its own through
+//! `QueryOptions::ranker`.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::config::{self, RankingStrategy};
+use crate::query::street_index::MatchScore;
+use crate::query::{haversine_distance, normalize, tokenize, Row};
+
+/// Penalty for each query stopword the street lacks.
+const MISSING_WEIGHT: f64 = 8.0;
+/// Penalty per typo.
+const TYPO_WEIGHT: f64 = 4.0;
+/// Penalty per step from [`MatchKind::Exact`] down to `Substring`.
+const KIND_WEIGHT: f64 = 2.0;
+/// Penalty for a row outside the preferred city. Larger than one match kind
+/// step, smaller than a typo.
+const CITY_BIAS_WEIGHT: f64 = 3.0;
+/// Penalty per character the matched street tokens extend past the query.
+const EXTENSION_WEIGHT: f64 = 0.1;
+/// Penalty per character of the street name.
+const LENGTH_WEIGHT: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// The query is the whole street name.
+    Exact,
+    /// The query matches the leading street tokens, in order.
+    Prefix,
+    /// The query matches tokens further into the street or out of order.
+    Substring,
+}
+
+impl MatchKind {
+    /// `street` is a normalized `street_map` key, `score` its match score.
+    pub fn classify(query: &str, street: &str, score: &MatchScore) -> Self {
+        if score.offset > 0 || score.distance > 0 {
+            MatchKind::Substring
+        } else if score.typos == 0
+            && score.extension == 0
+            && tokenize::tokens(query) == tokenize::tokens(street)
+        {
+            MatchKind::Exact
+        } else {
+            MatchKind::Prefix
+        }
+    }
+
+    fn rank(&self) -> f64 {
+        match self {
+            MatchKind::Exact => 0.0,
+            MatchKind::Prefix => 1.0,
+            MatchKind::Substring => 2.0,
+        }
+    }
+}
+
+/// Penalty of a street match before any city bias. Lower is better.
+pub fn street_penalty(query: &str, street: &str, score: &MatchScore) -> f64 {
+    let kind = MatchKind::classify(query, street, score);
+    MISSING_WEIGHT * score.missing as f64
+        + TYPO_WEIGHT * score.typos as f64
+        + KIND_WEIGHT * kind.rank()
+        + (score.distance + score.offset) as f64
+        + EXTENSION_WEIGHT * score.extension as f64
+        + LENGTH_WEIGHT * street.chars().count() as f64
+}
+
+/// Relevance of a row on a street with `penalty`. `in_preferred_city` is
+/// `None` without a city bias.
+pub fn relevance(penalty: f64, in_preferred_city: Option<bool>) -> f64 {
+    let bias = match in_preferred_city {
+        Some(false) => CITY_BIAS_WEIGHT,
+        _ => 0.0,
+    };
+    1.0 / (1.0 + penalty + bias)
+}
+
+/// What a ranker knows about a row's match besides the row and the query.
+#[derive(Debug, Clone, Copy)]
+pub struct RankContext<'a> {
+    /// Normalized `street_map` key of the row's street.
+    pub street: &'a str,
+    pub score: &'a MatchScore,
+    /// Addresses on the street.
+    pub street_rows: usize,
+    /// Normalized preferred city, see `city_bias`.
+    pub city_bias: Option<&'a str>,
+}
+
+impl RankContext<'_> {
+    /// `None` without a city bias.
+    fn in_preferred_city(&self, row: &Row) -> Option<bool> {
+        self.city_bias
+            .map(|city| normalize::text(&row.city) == city)
+    }
+}
+
+/// Orders street results. Rows are sorted by `score`, highest first, and
+/// the score is reported per entry.
+pub trait Ranker: Debug + Send + Sync {
+    /// Relevance of `row` for `query`, in `(0, 1]`.
+    fn score(&self, row: &Row, query: &str, context: &RankContext) -> f64;
+}
+
+/// Match quality only, the order described at the top of this module.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lexical;
+
+impl Ranker for Lexical {
+    fn score(&self, row: &Row, query: &str, context: &RankContext) -> f64 {
+        let penalty = street_penalty(query, context.street, context.score);
+        relevance(penalty, context.in_preferred_city(row))
+    }
+}
+
+/// [`Lexical`] plus a penalty growing with the distance to `origin`, for
+/// deployments serving one region.
+#[derive(Debug, Clone, Copy)]
+pub struct DistanceBiased {
+    pub origin: (f64, f64),
+    /// Distance in km penalized like one match kind step.
+    pub scale_km: f64,
+}
+
+impl Ranker for DistanceBiased {
+    fn score(&self, row: &Row, query: &str, context: &RankContext) -> f64 {
+        let (latitude, longitude) = self.origin;
+        let distance_km = haversine_distance(latitude, longitude, row.latitude, row.longitude);
+        let penalty = street_penalty(query, context.street, context.score)
+            + KIND_WEIGHT * distance_km / self.scale_km.max(f64::EPSILON);
+        relevance(penalty, context.in_preferred_city(row))
+    }
+}
+
+/// [`Lexical`] plus a penalty that shrinks as the street has more
+/// addresses, so long, well-known streets come before side streets.
+#[derive(Debug, Clone, Copy)]
+pub struct Popularity {
+    /// Penalty of a street with a single address.
+    pub weight: f64,
+}
+
+impl Ranker for Popularity {
+    fn score(&self, row: &Row, query: &str, context: &RankContext) -> f64 {
+        let size = (context.street_rows.max(1) as f64).ln_1p() / std::f64::consts::LN_2;
+        let penalty = street_penalty(query, context.street, context.score) + self.weight / size;
+        relevance(penalty, context.in_preferred_city(row))
+    }
+}
+
+/// Another ranker with the `city_bias` penalty also applied to rows off a
+/// preferred street or outside a preferred country, for state a client
+/// keeps across queries such as the street it picked last.
+#[derive(Debug, Clone)]
+pub struct Preferring {
+    pub inner: Arc<dyn Ranker>,
+    /// Normalized `street_map` key.
+    pub street: Option<String>,
+    /// Normalized country.
+    pub country: Option<String>,
+}
+
+impl Ranker for Preferring {
+    fn score(&self, row: &Row, query: &str, context: &RankContext) -> f64 {
+        let score = self.inner.score(row, query, context);
+        let off_street = self
+            .street
+            .as_ref()
+            .is_some_and(|street| street != context.street);
+        let off_country = self
+            .country
+            .as_ref()
+            .is_some_and(|country| normalize::text(row.country()) != *country);
+        let penalty = CITY_BIAS_WEIGHT * (off_street as u8 + off_country as u8) as f64;
+        1.0 / (1.0 / score + penalty)
+    }
+}
+
+/// The ranker selected by `search.ranking`.
+pub fn from_config() -> Arc<dyn Ranker> {
+    let ranking = &config::current().search.ranking;
+    match (ranking.strategy, ranking.origin) {
+        (RankingStrategy::Lexical, _) | (RankingStrategy::Distance, None) => Arc::new(Lexical),
+        (RankingStrategy::Distance, Some(origin)) => Arc::new(DistanceBiased {
+            origin,
+            scale_km: ranking.distance_scale_km,
+        }),
+        (RankingStrategy::Popularity, _) => Arc::new(Popularity {
+            weight: ranking.popularity_weight,
+        }),
+    }
+}
@@ -0,0 +1,188 @@
+//! Municipality and province index
+//!
+//! Maps each municipality and province to the streets and postal codes with
+//! at least one address in it, so `municipality=` and `province=` narrow the
+//! candidates before rows are collected instead of filtering every match.
+//! Street names and postal codes can cross a border, so rows from the
+//! narrowed candidates are still checked with [`RegionFilter::matches`].
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::query::{normalize, Row};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    Municipality,
+    Province,
+}
+
+impl RegionKind {
+    pub const ALL: [RegionKind; 2] = [RegionKind::Municipality, RegionKind::Province];
+
+    /// Query parameter name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegionKind::Municipality => "municipality",
+            RegionKind::Province => "province",
+        }
+    }
+
+    pub fn of<'a>(&self, row: &'a Row) -> &'a str {
+        match self {
+            RegionKind::Municipality => &row.municipality,
+            RegionKind::Province => &row.province,
+        }
+    }
+
+    /// Municipalities go through the alias table, so `'s-Gravenhage` and
+    /// `Den Haag` are the same region.
+    pub fn normalize(&self, name: &str) -> String {
+        match self {
+            RegionKind::Municipality => normalize::municipality(name),
+            RegionKind::Province => normalize::text(name),
+        }
+    }
+}
+
+/// Requested municipality and province, normalized. Both must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionFilter {
+    pub municipality: Option<String>,
+    pub province: Option<String>,
+}
+
+impl RegionFilter {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let get = |kind: RegionKind| {
+            params
+                .get(kind.name())
+                .map(|name| kind.normalize(name))
+                .filter(|name| !name.is_empty())
+        };
+        Self {
+            municipality: get(RegionKind::Municipality),
+            province: get(RegionKind::Province),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.municipality.is_none() && self.province.is_none()
+    }
+
+    /// The requested regions as `(kind, normalized name)` pairs.
+    pub fn parts(&self) -> impl Iterator<Item = (RegionKind, &str)> {
+        [
+            (RegionKind::Municipality, &self.municipality),
+            (RegionKind::Province, &self.province),
+        ]
+        .into_iter()
+        .filter_map(|(kind, name)| Some((kind, name.as_deref()?)))
+    }
+
+    pub fn matches(&self, row: &Row) -> bool {
+        self.parts()
+            .all(|(kind, name)| kind.normalize(kind.of(row)) == name)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RegionIndex {
+    /// Normalized region name to the sorted `StreetIndex` ids of streets with
+    /// an address in it.
+    streets: HashMap<(RegionKind, String), Vec<u32>>,
+    /// Normalized region name to the sorted postal codes with an address in it.
+    postal_codes: HashMap<(RegionKind, String), Vec<String>>,
+}
+
+impl RegionIndex {
+    /// Builds the index from the rows of each street by `StreetIndex` id.
+    /// Postal codes are taken from the same rows, in canonical form.
+    pub fn build<'a, I>(streets: I) -> Self
+    where
+        I: IntoIterator<Item = (u32, &'a Vec<Row>)>,
+    {
+        let mut street_ids: HashMap<(RegionKind, String), BTreeSet<u32>> = HashMap::new();
+        let mut postal_codes: HashMap<(RegionKind, String), BTreeSet<String>> = HashMap::new();
+        for (id, rows) in streets {
+            for row in rows {
+                for kind in RegionKind::ALL {
+                    let name = kind.of(row);
+                    if name.is_empty() {
+                        continue;
+                    }
+                    let key = (kind, kind.normalize(name));
+                    street_ids.entry(key.clone()).or_default().insert(id);
+                    postal_codes
+                        .entry(key)
+                        .or_default()
+                        .insert(row.postal_code.clone());
+                }
+            }
+        }
+
+        Self {
+            streets: street_ids
+                .into_iter()
+                .map(|(key, ids)| (key, ids.into_iter().collect()))
+                .collect(),
+            postal_codes: postal_codes
+                .into_iter()
+                .map(|(key, codes)| (key, codes.into_iter().collect()))
+                .collect(),
+        }
+    }
+
+    /// Street ids with an address in every requested region, sorted. `None`
+    /// when `filter` is empty, i.e. every street qualifies.
+    pub fn streets(&self, filter: &RegionFilter) -> Option<Vec<u32>> {
+        intersect(filter.parts().map(|(kind, name)| {
+            self.streets
+                .get(&(kind, name.to_string()))
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+        }))
+    }
+
+    /// Postal codes with an address in every requested region, sorted.
+    /// `None` when `filter` is empty.
+    pub fn postal_codes(&self, filter: &RegionFilter) -> Option<Vec<String>> {
+        intersect(filter.parts().map(|(kind, name)| {
+            self.postal_codes
+                .get(&(kind, name.to_string()))
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+        }))
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        let streets: usize = self
+            .streets
+            .iter()
+            .map(|((_, name), ids)| name.capacity() + ids.capacity() * std::mem::size_of::<u32>())
+            .sum();
+        let postal_codes: usize = self
+            .postal_codes
+            .iter()
+            .map(|((_, name), codes)| {
+                name.capacity() + codes.iter().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        streets + postal_codes
+    }
+}
+
+/// Intersection of sorted lists, `None` when there are none.
+fn intersect<'a, T, I>(lists: I) -> Option<Vec<T>>
+where
+    T: Ord + Clone + 'a,
+    I: IntoIterator<Item = &'a [T]>,
+{
+    let mut lists: Vec<&[T]> = lists.into_iter().collect();
+    lists.sort_by_key(|list| list.len());
+    let mut lists = lists.into_iter();
+    let mut result = lists.next()?.to_vec();
+    for list in lists {
+        result.retain(|item| list.binary_search(item).is_ok());
+    }
+    Some(result)
+}
@@ -0,0 +1,87 @@
+//! Normalizer and tokenizer registry
+//!
+//! [`Normalizer`]s and [`Tokenizer`]s are registered under a name and the
+//! config picks one of each with `search.normalizer` and
+//! `search.tokenizer`. The built-ins are `default` (see
+//! [`normalize::fold`]) and `dutch` and `simple` (see [`tokenize`]). A
+//! deployment with its own rules, e.g. Flemish street abbreviations,
+//! implements the trait and registers it before the data is loaded:
+//!
+//! ```ignore
+//! registry::register_tokenizer("flemish", Flemish);
+//! ```
+//!
+//! An unknown name falls back to the built-in default with a warning.
+//!
+//! [`normalize::fold`]: crate::query::normalize::fold
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::warn;
+
+use crate::config;
+use crate::query::normalize::{Folding, Normalizer};
+use crate::query::tokenize::{self, Dutch, Tokenizer};
+
+pub const DEFAULT_NORMALIZER: &str = "default";
+pub const DEFAULT_TOKENIZER: &str = "dutch";
+
+type Registry<T> = RwLock<HashMap<String, Arc<T>>>;
+
+lazy_static::lazy_static! {
+    static ref NORMALIZERS: Registry<dyn Normalizer> = {
+        let mut normalizers: HashMap<String, Arc<dyn Normalizer>> = HashMap::new();
+        normalizers.insert(DEFAULT_NORMALIZER.to_string(), Arc::new(Folding));
+        RwLock::new(normalizers)
+    };
+    static ref TOKENIZERS: Registry<dyn Tokenizer> = {
+        let mut tokenizers: HashMap<String, Arc<dyn Tokenizer>> = HashMap::new();
+        tokenizers.insert(DEFAULT_TOKENIZER.to_string(), Arc::new(Dutch));
+        tokenizers.insert("simple".to_string(), Arc::new(tokenize::Simple));
+        RwLock::new(tokenizers)
+    };
+    /// Unknown names already warned about, so a bad config logs once.
+    static ref WARNED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Registers `normalizer` as `name`, replacing any earlier one.
+pub fn register_normalizer(name: &str, normalizer: impl Normalizer + 'static) {
+    NORMALIZERS
+        .write()
+        .expect("Failed to acquire write lock")
+        .insert(name.to_string(), Arc::new(normalizer));
+}
+
+/// Registers `tokenizer` as `name`, replacing any earlier one.
+pub fn register_tokenizer(name: &str, tokenizer: impl Tokenizer + 'static) {
+    TOKENIZERS
+        .write()
+        .expect("Failed to acquire write lock")
+        .insert(name.to_string(), Arc::new(tokenizer));
+}
+
+fn lookup<T: ?Sized>(registry: &Registry<T>, kind: &str, name: &str, default: &str) -> Arc<T> {
+    let registry = registry.read().expect("Failed to acquire read lock");
+    if let Some(found) = registry.get(name) {
+        return found.clone();
+    }
+    let mut warned = WARNED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if warned.insert(format!("{}:{}", kind, name)) {
+        warn!("Unknown {} '{}', using '{}'", kind, name, default);
+    }
+    registry[default].clone()
+}
+
+/// The normalizer named by `search.normalizer`.
+pub fn normalizer() -> Arc<dyn Normalizer> {
+    let name = &config::current().search.normalizer;
+    lookup(&NORMALIZERS, "normalizer", name, DEFAULT_NORMALIZER)
+}
+
+/// The tokenizer named by `search.tokenizer`.
+pub fn tokenizer() -> Arc<dyn Tokenizer> {
+    let name = &config::current().search.tokenizer;
+    lookup(&TOKENIZERS, "tokenizer", name, DEFAULT_TOKENIZER)
+}
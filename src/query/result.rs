@@ -0,0 +1,85 @@
+//! Typed search results
+//!
+//! The `query_*_typed` functions return a [`SearchResult`] instead of a JSON
+//! envelope, for library users who want the rows rather than string keys
+//! into a `Value`. The JSON returning functions are built on top of them, so
+//! both always agree.
+
+use crate::fields::{Projected, Projection};
+use crate::query::{ResultMeta, Row, Timings};
+
+/// One matched address, owned so it outlives the read lock on the data.
+#[derive(Debug, Clone)]
+pub struct SearchEntry {
+    pub row: Row,
+    /// Position reported instead of the row's own, e.g. the street centroid
+    /// when the coordinate policy snaps to streets.
+    pub position: Option<(f64, f64)>,
+    /// How the entry is rendered in a response.
+    pub projection: Projection,
+    /// Relevance between 0 and 1, for street searches.
+    pub score: Option<f64>,
+    /// Kilometres from the queried position to the reported one, for
+    /// coordinate searches.
+    pub distance: Option<f64>,
+}
+
+impl SearchEntry {
+    pub fn new(projected: Projected<'_>) -> Self {
+        Self {
+            row: projected.row.clone(),
+            position: projected.position,
+            projection: Projection {
+                fields: projected.fields,
+                coordinates: projected.coordinates,
+            },
+            score: None,
+            distance: None,
+        }
+    }
+
+    /// The entry as it is serialized in responses.
+    pub fn projected(&self) -> Projected<'_> {
+        Projected::new(&self.row, &self.projection).with_position(self.position)
+    }
+
+    /// Latitude and longitude as reported, after the coordinate policy.
+    pub fn coordinates(&self) -> (f64, f64) {
+        self.projected().coordinates()
+    }
+}
+
+/// Entries of one query with their truncation metadata.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub entries: Vec<SearchEntry>,
+    pub meta: ResultMeta,
+    /// Stage durations. `serialize_ms` covers building the entries, plus
+    /// the JSON in the JSON variants.
+    pub timings: Timings,
+}
+
+impl SearchResult {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// House numbers of the entries, in result order.
+    pub fn house_numbers(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .map(|entry| entry.row.house_number.as_str())
+            .collect()
+    }
+
+    /// Whether every entry is on the same street. `false` when empty.
+    pub fn consistent_street(&self) -> bool {
+        let Some(first) = self.entries.first() else {
+            return false;
+        };
+        let street = first.row.street_key();
+        self.entries
+            .iter()
+            .all(|entry| entry.row.street_key() == street)
+    }
+}
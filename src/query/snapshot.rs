@@ -0,0 +1,358 @@
+//! Binary dataset snapshots
+//!
+//! A snapshot holds the rows of a loaded dataset, so a server can start
+//! from one file instead of parsing every CSV shard. A `data_dir` holding
+//! `.snapshot` files is loaded from those, and its CSV files are skipped;
+//! `places-cli snapshot` writes them. Snapshot rows get the same checks as
+//! CSV rows.
+//!
+//! The format describes itself: the magic bytes, the format version as a
+//! little endian `u32`, the column names, the row count as a `u64` and then
+//! every cell of every row. Counts are `u32`s and every string is its
+//! length as a `u32` followed by its UTF-8 bytes. Rows are mapped onto
+//! [`Row`] by column name, so a snapshot written before `Row` gained a
+//! column still loads, with that column's default.
+//!
+//! Changes the column names cannot cover bump [`FORMAT_VERSION`] and add a
+//! step to [`MIGRATIONS`] upgrading the previous version's columns and cells.
+//! Older snapshots are read through every step after their version;
+//! [`upgrade`] writes them back in the current format.
+
+use csv::StringRecord;
+use std::fs;
+use std::io::{self, Write};
+use thiserror::Error;
+
+use crate::query::load::{OPTIONAL_COLUMNS, REQUIRED_COLUMNS};
+use crate::query::Row;
+
+/// Extension of snapshot files in `data_dir`.
+pub const EXTENSION: &str = "snapshot";
+
+const MAGIC: &[u8; 8] = b"PLACESNP";
+
+/// Version written by [`write`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Upgrades a snapshot by one format version: `MIGRATIONS[0]` turns a
+/// version 1 snapshot into version 2 and so on.
+type Migration = fn(Snapshot) -> Result<Snapshot, SnapshotError>;
+
+/// One step per format version before [`FORMAT_VERSION`].
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [];
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("not a snapshot")]
+    NotASnapshot,
+    #[error("format version {0} is newer than this build reads ({FORMAT_VERSION})")]
+    Newer(u32),
+    #[error("format version {0} is not a known version")]
+    UnknownVersion(u32),
+    #[error("file ends early")]
+    Truncated,
+    #[error("a string is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("row {index}: {message}")]
+    Row { index: usize, message: String },
+}
+
+/// The columns and cells of a snapshot, as read before they are mapped
+/// onto rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub version: u32,
+    pub columns: StringRecord,
+    pub rows: Vec<StringRecord>,
+}
+
+impl Snapshot {
+    /// The cells of `rows` in the columns of the current `Row`.
+    pub fn from_rows<'a>(rows: impl IntoIterator<Item = &'a Row>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            columns: REQUIRED_COLUMNS.iter().chain(&OPTIONAL_COLUMNS).collect(),
+            rows: rows.into_iter().map(cells).collect(),
+        }
+    }
+
+    /// Brings an older snapshot up to [`FORMAT_VERSION`].
+    fn migrate(mut self) -> Result<Self, SnapshotError> {
+        if self.version > FORMAT_VERSION {
+            return Err(SnapshotError::Newer(self.version));
+        }
+        let first = (self.version as usize)
+            .checked_sub(1)
+            .ok_or(SnapshotError::UnknownVersion(self.version))?;
+        for migration in &MIGRATIONS[first..] {
+            let version = self.version;
+            self = migration(self)?;
+            self.version = version + 1;
+        }
+        Ok(self)
+    }
+
+    /// The rows, each mapped by column name. Columns the snapshot lacks
+    /// get `Row`'s defaults.
+    pub fn to_rows(&self) -> impl Iterator<Item = Result<Row, SnapshotError>> + '_ {
+        self.rows.iter().enumerate().map(|(index, record)| {
+            record
+                .deserialize(Some(&self.columns))
+                .map_err(|e| SnapshotError::Row {
+                    index,
+                    message: e.to_string(),
+                })
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(self.version.to_le_bytes());
+        write_record(&mut bytes, &self.columns);
+        bytes.extend((self.rows.len() as u64).to_le_bytes());
+        for row in &self.rows {
+            for cell in row {
+                write_str(&mut bytes, cell);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a snapshot of any known version and migrates it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = reader.u32()?;
+        if version > FORMAT_VERSION {
+            return Err(SnapshotError::Newer(version));
+        }
+        let columns = reader.record()?;
+        let count = reader.u64()?;
+        let mut rows = Vec::new();
+        for _ in 0..count {
+            let cells = (0..columns.len())
+                .map(|_| reader.str())
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push(StringRecord::from(cells));
+        }
+        Self {
+            version,
+            columns,
+            rows,
+        }
+        .migrate()
+    }
+}
+
+/// A row's cells in [`Snapshot::from_rows`]'s column order, empty for
+/// unset optional fields.
+fn cells(row: &Row) -> StringRecord {
+    StringRecord::from(vec![
+        row.postal_code.clone(),
+        row.street.clone(),
+        row.house_number.clone(),
+        row.city.clone(),
+        row.area.clone(),
+        row.neighborhood.clone(),
+        row.municipality.clone(),
+        row.province.clone(),
+        row.latitude.to_string(),
+        row.longitude.to_string(),
+        row.country.clone().unwrap_or_default(),
+        row.precision.name().to_string(),
+        row.valid_from
+            .map(|date| date.to_string())
+            .unwrap_or_default(),
+        row.extra.clone().unwrap_or_default(),
+    ])
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend((value.len() as u32).to_le_bytes());
+    bytes.extend(value.as_bytes());
+}
+
+fn write_record(bytes: &mut Vec<u8>, record: &StringRecord) {
+    bytes.extend((record.len() as u32).to_le_bytes());
+    for field in record {
+        write_str(bytes, field);
+    }
+}
+
+/// Cursor over the bytes of a snapshot.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn str(&mut self) -> Result<&'a str, SnapshotError> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).map_err(|_| SnapshotError::InvalidUtf8)
+    }
+
+    fn record(&mut self) -> Result<StringRecord, SnapshotError> {
+        let len = self.u32()?;
+        let fields = (0..len)
+            .map(|_| self.str())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(StringRecord::from(fields))
+    }
+}
+
+/// Writes `rows` to `path` in the current format.
+pub fn write<'a>(path: &str, rows: impl IntoIterator<Item = &'a Row>) -> Result<(), SnapshotError> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(&Snapshot::from_rows(rows).to_bytes())?;
+    Ok(())
+}
+
+/// Reads the snapshot at `path`, migrated to the current format.
+pub fn read(path: &str) -> Result<Snapshot, SnapshotError> {
+    Snapshot::from_bytes(&fs::read(path)?)
+}
+
+/// Rewrites the snapshot at `path` in the current format. Returns the
+/// version it was written in; a current snapshot is left alone.
+pub fn upgrade(path: &str) -> Result<u32, SnapshotError> {
+    let bytes = fs::read(path)?;
+    let mut reader = Reader(&bytes);
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    let version = reader.u32()?;
+    if version != FORMAT_VERSION {
+        let snapshot = Snapshot::from_bytes(&bytes)?;
+        fs::write(path, snapshot.to_bytes())?;
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::query::load::{LoadIssue, LoadMode};
+    use crate::query::LocationData;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("places-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rows_survive_a_round_trip() {
+        let rows = fixtures::rows();
+        let bytes = Snapshot::from_rows(&rows).to_bytes();
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        let read: Vec<Row> = snapshot.to_rows().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            serde_json::to_value(read).unwrap(),
+            serde_json::to_value(rows).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_columns_get_defaults() {
+        let mut snapshot = Snapshot::from_rows(&fixtures::rows()[..1]);
+        // as written before `Row` had the optional columns
+        snapshot.columns = REQUIRED_COLUMNS.iter().collect();
+        for row in &mut snapshot.rows {
+            *row = row.iter().take(REQUIRED_COLUMNS.len()).collect();
+        }
+        let read = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        let row = read.to_rows().next().unwrap().unwrap();
+        assert_eq!(row.id(), fixtures::rows()[0].id());
+        assert_eq!(row.country, None);
+        assert_eq!(row.extra, None);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let mut snapshot = Snapshot::from_rows(&fixtures::rows());
+        snapshot.version = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Snapshot::from_bytes(&snapshot.to_bytes()),
+            Err(SnapshotError::Newer(_))
+        ));
+        snapshot.version = 0;
+        assert!(matches!(
+            Snapshot::from_bytes(&snapshot.to_bytes()),
+            Err(SnapshotError::UnknownVersion(0))
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(b"PLACESNP\x01"),
+            Err(SnapshotError::Truncated)
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(b"id,street\n"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+    }
+
+    #[test]
+    fn rows_are_checked_like_csv_rows() {
+        let mut rows = fixtures::rows();
+        rows.push(rows[0].clone());
+        let mut impossible = rows[0].clone();
+        impossible.postal_code = "0123AB".to_string();
+        rows.push(impossible);
+        let dir = temp_dir("checked-snapshot");
+        let path = dir.join("places.snapshot");
+        write(path.to_str().unwrap(), &rows).unwrap();
+
+        let mut data = LocationData::new();
+        let error = data
+            .load_from_snapshot(path.to_str().unwrap(), LoadMode::Strict)
+            .unwrap_err();
+        assert!(matches!(error.issue, LoadIssue::DuplicateId(_)));
+
+        let mut data = LocationData::new();
+        data.load_from_snapshot(path.to_str().unwrap(), LoadMode::Report)
+            .unwrap();
+        let kinds: Vec<_> = data.load_issues().iter().map(|e| e.issue.kind()).collect();
+        assert_eq!(kinds, ["duplicate_id", "invalid_postal_code"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn folders_with_a_snapshot_skip_their_csv_files() {
+        let dir = temp_dir("snapshot-and-csv");
+        let rows = fixtures::rows();
+        write(dir.join("places.snapshot").to_str().unwrap(), &rows).unwrap();
+        fs::write(
+            dir.join("places.csv"),
+            "postal_code,street,house_number,city,area,neighborhood,municipality,province,latitude,longitude\n",
+        )
+        .unwrap();
+
+        let mut data = LocationData::new();
+        data.load_all(dir.to_str().unwrap(), LoadMode::Strict)
+            .unwrap();
+        assert_eq!(data.rows().count(), rows.len());
+        assert_eq!(data.summary().files.len(), 1);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,168 @@
+//! Spatial index for coordinate search
+//!
+//! Row positions live in an R-tree as points on the unit sphere. The
+//! straight-line (chord) distance the tree orders by grows with the
+//! great-circle distance, so nearest-neighbor traversal visits rows in
+//! exactly the order the haversine distance would sort them. Entries point
+//! back into `street_map` instead of copying rows.
+
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+
+/// Position of a row in `street_map`: the street's position in the
+/// `StreetIndex` and the row's offset within that street.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowRef {
+    pub street: u32,
+    pub row: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    point: [f64; 3],
+    row: RowRef,
+}
+
+impl RTreeObject for Entry {
+    type Envelope = AABB<[f64; 3]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.point)
+    }
+}
+
+impl PointDistance for Entry {
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        self.point
+            .iter()
+            .zip(point)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    tree: RTree<Entry>,
+}
+
+impl SpatialIndex {
+    /// Bulk loads `(latitude, longitude, row)` triples. Rows without finite
+    /// coordinates are left out.
+    pub fn build<I: IntoIterator<Item = (f64, f64, RowRef)>>(rows: I) -> Self {
+        let entries = rows
+            .into_iter()
+            .filter(|(latitude, longitude, _)| latitude.is_finite() && longitude.is_finite())
+            .map(|(latitude, longitude, row)| Entry {
+                point: unit_sphere(latitude, longitude),
+                row,
+            })
+            .collect();
+        Self {
+            tree: RTree::bulk_load(entries),
+        }
+    }
+
+    /// Rows ordered by distance to the given position, nearest first.
+    pub fn nearest(&self, latitude: f64, longitude: f64) -> impl Iterator<Item = RowRef> + '_ {
+        self.tree
+            .nearest_neighbor_iter(&unit_sphere(latitude, longitude))
+            .map(|entry| entry.row)
+    }
+
+    /// Rows that may lie in the latitude/longitude rectangle: the tree is
+    /// queried with a box around the rectangle, so a few just outside it
+    /// come along and callers filter on the row position.
+    pub fn candidates_in_bbox(
+        &self,
+        min: (f64, f64),
+        max: (f64, f64),
+    ) -> impl Iterator<Item = RowRef> + '_ {
+        self.tree
+            .locate_in_envelope(&bounding_box(min, max))
+            .map(|entry| entry.row)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+
+    /// Leaf entries only, inner nodes add a few percent.
+    pub fn estimated_memory_bytes(&self) -> usize {
+        self.tree.size() * std::mem::size_of::<Entry>()
+    }
+}
+
+fn unit_sphere(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
+
+/// Smallest axis-aligned box on the unit sphere's space containing the
+/// rectangle between `min` and `max` (latitude, longitude), with
+/// `min <= max` in both.
+fn bounding_box(min: (f64, f64), max: (f64, f64)) -> AABB<[f64; 3]> {
+    let (min_lat, min_lon) = (min.0.to_radians(), min.1.to_radians());
+    let (max_lat, max_lon) = (max.0.to_radians(), max.1.to_radians());
+    let contains_lon = |angle: f64| {
+        [
+            angle,
+            angle - 360f64.to_radians(),
+            angle + 360f64.to_radians(),
+        ]
+        .iter()
+        .any(|angle| (min_lon..=max_lon).contains(angle))
+    };
+
+    let cos_lat = {
+        let ends = [min_lat.cos(), max_lat.cos()];
+        let high = if min_lat <= 0.0 && 0.0 <= max_lat {
+            1.0
+        } else {
+            ends[0].max(ends[1])
+        };
+        (ends[0].min(ends[1]), high)
+    };
+    let interval = |ends: [f64; 2], high_at: f64, low_at: f64| {
+        let high = if contains_lon(high_at) {
+            1.0
+        } else {
+            ends[0].max(ends[1])
+        };
+        let low = if contains_lon(low_at) {
+            -1.0
+        } else {
+            ends[0].min(ends[1])
+        };
+        (low, high)
+    };
+    let cos_lon = interval([min_lon.cos(), max_lon.cos()], 0.0, 180f64.to_radians());
+    let sin_lon = interval(
+        [min_lon.sin(), max_lon.sin()],
+        90f64.to_radians(),
+        -90f64.to_radians(),
+    );
+    let product = |a: (f64, f64), b: (f64, f64)| {
+        let values = [a.0 * b.0, a.0 * b.1, a.1 * b.0, a.1 * b.1];
+        (
+            values.iter().copied().fold(f64::INFINITY, f64::min),
+            values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        )
+    };
+
+    let x = product(cos_lat, cos_lon);
+    let y = product(cos_lat, sin_lon);
+    // small margin so rows on the rectangle's edge survive rounding
+    let margin = 1e-12;
+    AABB::from_corners(
+        [x.0 - margin, y.0 - margin, min_lat.sin() - margin],
+        [x.1 + margin, y.1 + margin, max_lat.sin() + margin],
+    )
+}
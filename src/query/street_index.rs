@@ -0,0 +1,395 @@
+//! Token index over street names
+//!
+//! Every street is tokenized once at load time and its tokens are added to a
+//! [`PrefixTrie`] pointing back to the streets containing them. A query is
+//! answered by looking up each query token as a prefix, intersecting the resulting street sets (AND) and
+//! ranking the survivors by how close together and in which order the
+//! tokens appear, so `"nieuw oost"` finds `Laan van Nieuw Oost-Indië` ahead
+//! of a street where the two words are far apart. Configured stopwords
+//! (`van`, `de`, `het`, ...) are optional in queries and do not count as a
+//! head start in streets, so `"rijnstraat"` ranks `Van Rijnstraat` first.
+//! Longer tokens may contain typos within the request's [`Fuzziness`], each
+//! edit ranks the street below exact matches. Streets are indexed under
+//! each of their spelling [`variants`], a match on any of them counts.
+
+use std::borrow::Cow;
+
+use crate::deadline::Deadline;
+use crate::query::edit_distance::{prefix_distance, Fuzziness};
+use crate::query::tokenize::{self, Stopwords};
+use crate::query::trie::PrefixTrie;
+use crate::query::variants;
+use crate::query::ScanProgress;
+
+/// Query tokens with more candidate positions than this in a single street
+/// are not fully permuted when scoring; the best so far is kept.
+const MAX_ASSIGNMENTS: usize = 4096;
+
+#[derive(Debug, Default)]
+pub struct StreetIndex {
+    /// `street_map` key and its tokens, once per spelling variant with the
+    /// original first, addressed by position.
+    streets: Vec<(String, Vec<Vec<String>>)>,
+    /// Token prefix to the positions in `streets` containing a token with
+    /// that prefix, sorted.
+    postings: PrefixTrie,
+}
+
+/// How well a street matches, lower is better. Fields are compared in
+/// declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MatchScore {
+    /// Query stopwords the street does not contain.
+    pub missing: usize,
+    /// Edits needed to turn the query tokens into street token prefixes.
+    pub typos: usize,
+    /// Tokens between and out of order relative to the query.
+    pub distance: usize,
+    /// Street tokens before the first match, not counting stopwords, so a
+    /// match right after a leading particle ranks like a match at the start.
+    pub offset: usize,
+    /// Characters the matched street tokens extend past the query tokens.
+    pub extension: usize,
+}
+
+impl StreetIndex {
+    pub fn build<'a, I: IntoIterator<Item = &'a String>>(streets: I) -> Self {
+        let mut index = Self::default();
+        let spellings = variants::current();
+        for street in streets {
+            let id = u32::try_from(index.streets.len()).expect("more than u32::MAX streets");
+            let tokens = spellings.expand(tokenize::tokens(street));
+            for token in tokens.iter().flatten() {
+                index.postings.insert(token, id);
+            }
+            index.streets.push((street.clone(), tokens));
+        }
+        index.postings.shrink_to_fit();
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.streets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streets.is_empty()
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        let streets: usize = self
+            .streets
+            .iter()
+            .map(|(key, tokens)| {
+                key.capacity() + tokens.iter().flatten().map(String::capacity).sum::<usize>()
+            })
+            .sum();
+        streets + self.postings.estimated_memory_bytes()
+    }
+
+    /// `street_map` keys in position order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.streets.iter().map(|(key, _)| key.as_str())
+    }
+
+    /// `street_map` key of the street at `id`.
+    pub fn key(&self, id: u32) -> Option<&str> {
+        self.streets.get(id as usize).map(|(key, _)| key.as_str())
+    }
+
+    /// Nodes in the token trie.
+    pub fn trie_nodes(&self) -> usize {
+        self.postings.node_count()
+    }
+
+    /// Streets containing every token of `query` (each as a prefix, up to
+    /// `fuzziness` typos), best match first. Stopwords in the query are
+    /// optional unless the query has nothing else. Scoring stops at
+    /// `deadline`; progress is reported over the candidates left after
+    /// intersection. `within`, sorted ids, restricts the candidates.
+    pub fn search_until(
+        &self,
+        query: &str,
+        fuzziness: Fuzziness,
+        within: Option<&[u32]>,
+        deadline: Deadline,
+    ) -> (Vec<(&str, MatchScore)>, ScanProgress) {
+        let stopwords = Stopwords::from_config();
+        let query = tokenize::tokens(query);
+        let required: Vec<String> = query
+            .iter()
+            .filter(|token| !stopwords.contains(token))
+            .cloned()
+            .collect();
+        let (mut candidates, stopwords) = if required.is_empty() {
+            (self.candidates(&query, fuzziness), Stopwords::default())
+        } else {
+            (self.candidates(&required, fuzziness), stopwords)
+        };
+        if let Some(within) = within {
+            candidates.retain(|id| within.binary_search(id).is_ok());
+        }
+
+        let total = candidates.len();
+        let mut progress = ScanProgress::complete(total);
+        let mut result = Vec::new();
+        for (i, id) in candidates.into_iter().enumerate() {
+            if deadline.expired_at(i) {
+                progress.scanned = i;
+                break;
+            }
+            let (street, tokens) = &self.streets[id as usize];
+            let best = tokens
+                .iter()
+                .filter_map(|tokens| score(&query, tokens, &stopwords, fuzziness))
+                .min();
+            if let Some(score) = best {
+                result.push((street.as_str(), score));
+            }
+        }
+        result.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+        (result, progress)
+    }
+
+    fn candidates(&self, query: &[String], fuzziness: Fuzziness) -> Vec<u32> {
+        if query.is_empty() {
+            return self.postings.get("").to_vec();
+        }
+
+        let mut lists: Vec<Cow<[u32]>> = query
+            .iter()
+            .map(|token| self.postings.get_within(token, fuzziness.edits_for(token)))
+            .collect();
+        // intersect starting from the rarest token
+        lists.sort_by_key(|list| list.len());
+        let mut lists = lists.into_iter();
+        let mut result = lists.next().unwrap_or_default().into_owned();
+        for list in lists {
+            result.retain(|id| list.binary_search(id).is_ok());
+            if result.is_empty() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// Scores `target` against `query`, assigning each query token to a distinct
+/// target token it is a prefix of, within `fuzziness`. Stopword tokens in
+/// the query must match exactly and may stay unassigned, at the cost of
+/// `missing`. `None` when no assignment exists.
+pub fn score(
+    query: &[String],
+    target: &[String],
+    stopwords: &Stopwords,
+    fuzziness: Fuzziness,
+) -> Option<MatchScore> {
+    let positions: Vec<Vec<Candidate>> = query
+        .iter()
+        .map(|prefix| {
+            let stopword = stopwords.contains(prefix);
+            let edits = if stopword {
+                0
+            } else {
+                fuzziness.edits_for(prefix)
+            };
+            let mut positions: Vec<Candidate> = target
+                .iter()
+                .enumerate()
+                .filter_map(|(i, token)| {
+                    typos(prefix, token, edits).map(|typos| Candidate {
+                        position: Some(i),
+                        typos,
+                    })
+                })
+                .collect();
+            if stopword {
+                positions.push(Candidate {
+                    position: None,
+                    typos: 0,
+                });
+            }
+            positions
+        })
+        .collect();
+
+    let mut best = None;
+    let mut chosen = Vec::with_capacity(query.len());
+    let mut budget = MAX_ASSIGNMENTS;
+    let search = Assignment {
+        query,
+        target,
+        stopwords,
+        positions: &positions,
+    };
+    search.assign(&mut chosen, &mut best, &mut budget);
+    best
+}
+
+/// Edits to make `prefix` a prefix of `token`, `None` above `max_edits`.
+fn typos(prefix: &str, token: &str, max_edits: usize) -> Option<usize> {
+    if token.starts_with(prefix) {
+        Some(0)
+    } else if max_edits > 0 {
+        prefix_distance(prefix, token, max_edits)
+    } else {
+        None
+    }
+}
+
+/// A target token a query token can be assigned to, `position: None` for
+/// leaving a stopword unassigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Candidate {
+    position: Option<usize>,
+    typos: usize,
+}
+
+struct Assignment<'a> {
+    query: &'a [String],
+    target: &'a [String],
+    stopwords: &'a Stopwords,
+    positions: &'a [Vec<Candidate>],
+}
+
+impl Assignment<'_> {
+    fn assign(
+        &self,
+        chosen: &mut Vec<Candidate>,
+        best: &mut Option<MatchScore>,
+        budget: &mut usize,
+    ) {
+        if *budget == 0 {
+            return;
+        }
+        let Some(options) = self.positions.get(chosen.len()) else {
+            *budget -= 1;
+            let score = self.score(chosen);
+            if best.is_none_or(|best| score < best) {
+                *best = Some(score);
+            }
+            return;
+        };
+        for &candidate in options {
+            if candidate.position.is_some()
+                && chosen
+                    .iter()
+                    .any(|chosen| chosen.position == candidate.position)
+            {
+                continue;
+            }
+            chosen.push(candidate);
+            self.assign(chosen, best, budget);
+            chosen.pop();
+        }
+    }
+
+    fn score(&self, chosen: &[Candidate]) -> MatchScore {
+        let matched: Vec<(usize, &String)> = chosen
+            .iter()
+            .zip(self.query)
+            .filter_map(|(candidate, prefix)| candidate.position.map(|position| (position, prefix)))
+            .collect();
+        let missing = chosen.len() - matched.len();
+        let typos = chosen.iter().map(|candidate| candidate.typos).sum();
+
+        let min = matched.iter().map(|(p, _)| *p).min().unwrap_or(0);
+        let max = matched.iter().map(|(p, _)| *p).max().unwrap_or(0);
+        let gaps = if matched.is_empty() {
+            0
+        } else {
+            max + 1 - min - matched.len()
+        };
+        let inversions = matched
+            .iter()
+            .enumerate()
+            .map(|(i, (a, _))| matched[i + 1..].iter().filter(|(b, _)| b < a).count())
+            .sum::<usize>();
+        // leading particles ("van", "de") do not push a match back
+        let offset = self.target[..min]
+            .iter()
+            .filter(|token| !self.stopwords.contains(token))
+            .count();
+        let extension = matched
+            .iter()
+            .map(|(position, prefix)| self.target[*position].len().saturating_sub(prefix.len()))
+            .sum();
+        MatchScore {
+            missing,
+            typos,
+            distance: gaps + inversions,
+            offset,
+            extension,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(streets: &[&str]) -> StreetIndex {
+        let streets: Vec<String> = streets.iter().map(|street| street.to_string()).collect();
+        StreetIndex::build(&streets)
+    }
+
+    fn search<'a>(index: &'a StreetIndex, query: &str, fuzziness: Fuzziness) -> Vec<&'a str> {
+        let (result, progress) = index.search_until(query, fuzziness, None, Deadline::none());
+        assert!(progress.is_complete());
+        result.into_iter().map(|(street, _)| street).collect()
+    }
+
+    const TYPO: Fuzziness = Fuzziness {
+        max_edits: 1,
+        chars_per_edit: 4,
+    };
+
+    #[test]
+    fn every_token_must_match_as_a_prefix() {
+        let index = index(&["kerkstraat", "kerkplein", "oude kerk", "damrak"]);
+        assert_eq!(
+            search(&index, "kerk", TYPO),
+            ["kerkplein", "kerkstraat", "oude kerk"]
+        );
+        assert_eq!(search(&index, "oud kerk", TYPO), ["oude kerk"]);
+        assert!(search(&index, "kerk damrak", TYPO).is_empty());
+        assert!(search(&index, "singel", TYPO).is_empty());
+    }
+
+    #[test]
+    fn close_and_ordered_tokens_rank_first() {
+        let index = index(&["oost nieuwstraat", "laan van nieuw oost-indie"]);
+        assert_eq!(
+            search(&index, "nieuw oost", TYPO),
+            ["laan van nieuw oost-indie", "oost nieuwstraat"]
+        );
+    }
+
+    #[test]
+    fn stopwords_are_optional_and_no_head_start() {
+        let index = index(&["rijnstraatweg", "van rijnstraat"]);
+        assert_eq!(
+            search(&index, "rijnstraat", TYPO),
+            ["van rijnstraat", "rijnstraatweg"]
+        );
+        assert_eq!(search(&index, "van rijnstraat", TYPO)[0], "van rijnstraat");
+    }
+
+    #[test]
+    fn typos_rank_below_exact_matches() {
+        let streets = index(&["kerkstrat", "kerkstraat"]);
+        assert_eq!(
+            search(&streets, "kerkstraa", TYPO),
+            ["kerkstraat", "kerkstrat"]
+        );
+        let (result, _) = streets.search_until("kerkstaat", TYPO, None, Deadline::none());
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|(_, score)| score.typos == 1));
+        assert!(search(&streets, "kerkstaat", Fuzziness::exact()).is_empty());
+
+        let within = [1];
+        let (result, _) = streets.search_until("kerk", TYPO, Some(&within), Deadline::none());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "kerkstraat");
+    }
+}
@@ -0,0 +1,165 @@
+//! Tokenizer for street and place names
+//!
+//! Names are folded with [`normalize::text`] and split on whitespace,
+//! hyphens and punctuation. Elided Dutch articles become full words (`'t`
+//! is `het`, `'s` is `des`), other apostrophes are dropped (`auto's` is
+//! `autos`), and known alternative names are rewritten to one canonical
+//! token sequence, so `'s-Gravenhage` and `Den Haag` tokenize the same.
+//! Abbreviations from `search.abbreviations` are written out at the end of
+//! a compound (`kerkstr` is `kerkstraat`, `dorpsln` is `dorpslaan`), those
+//! from `search.word_abbreviations` only as a whole word ending in a period
+//! (`burg.` is `burgemeester`), so a half typed `burg` still finds
+//! `Burgwal`. Street tokens are built at load time, a changed table applies
+//! to them from the next data reload.
+
+use std::collections::HashSet;
+
+use crate::config::{self, SearchConfig};
+use crate::query::{normalize, registry};
+
+/// Alternative names and the token sequence they are rewritten to.
+const EQUIVALENTS: &[(&[&str], &[&str])] = &[
+    (&["des", "gravenhage"], &["den", "haag"]),
+    (&["des", "hertogenbosch"], &["den", "bosch"]),
+];
+
+/// Splits names into tokens. `search.tokenizer` picks the implementation
+/// by name from the [`registry`]; street tokens are built with it at load
+/// time and queries are split with it.
+///
+/// [`registry`]: crate::query::registry
+pub trait Tokenizer: Send + Sync {
+    fn tokens(&self, value: &str) -> Vec<String>;
+}
+
+/// The built-in `dutch` tokenizer, the rules in the module docs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Dutch;
+
+impl Tokenizer for Dutch {
+    fn tokens(&self, value: &str) -> Vec<String> {
+        dutch_tokens(value)
+    }
+}
+
+/// The built-in `simple` tokenizer: folded text split on whitespace,
+/// hyphens and punctuation, without articles, equivalents or
+/// abbreviations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Simple;
+
+impl Tokenizer for Simple {
+    fn tokens(&self, value: &str) -> Vec<String> {
+        normalize::text(value)
+            .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+            .filter(|token| !token.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Splits `value` with the configured [`Tokenizer`].
+pub fn tokens(value: &str) -> Vec<String> {
+    registry::tokenizer().tokens(value)
+}
+
+/// Splits `value` into normalized tokens:
+/// `"'s-Gravenhage"` becomes `["den", "haag"]`,
+/// `"Laan van Nieuw Oost-Indië"` becomes `["laan", "van", "nieuw", "oost", "indie"]`.
+fn dutch_tokens(value: &str) -> Vec<String> {
+    let folded = normalize::text(&value.replace(['\u{2019}', '\u{2018}', '`'], "'"));
+    let search = &config::current().search;
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for (i, c) in folded.char_indices() {
+        if c.is_whitespace() || c == '-' || (c.is_ascii_punctuation() && c != '\'') {
+            tokens.extend(token(&folded[start..i], c == '.', search));
+            start = i + c.len_utf8();
+        }
+    }
+    tokens.extend(token(&folded[start..], false, search));
+    apply_equivalents(tokens)
+}
+
+/// `abbreviated` is set when `piece` was followed by a period.
+fn token(piece: &str, abbreviated: bool, search: &SearchConfig) -> Option<String> {
+    let token = match piece {
+        "'t" => "het".to_string(),
+        "'s" => "des".to_string(),
+        _ => piece.replace('\'', ""),
+    };
+    (!token.is_empty()).then(|| expand(token, abbreviated, search))
+}
+
+/// Writes out an abbreviated `token`, see the module docs.
+fn expand(token: String, abbreviated: bool, search: &SearchConfig) -> String {
+    if abbreviated {
+        if let Some(word) = search
+            .word_abbreviations
+            .get(&token)
+            .or_else(|| search.abbreviations.get(&token))
+        {
+            return word.clone();
+        }
+    }
+    // the longest abbreviation wins, `pln` over `ln`
+    let longest = search
+        .abbreviations
+        .iter()
+        .filter(|(abbreviation, _)| {
+            token.len() > abbreviation.len() && token.ends_with(abbreviation.as_str())
+        })
+        .max_by_key(|(abbreviation, _)| abbreviation.len());
+    match longest {
+        Some((abbreviation, word)) => {
+            format!("{}{}", &token[..token.len() - abbreviation.len()], word)
+        }
+        None => token,
+    }
+}
+
+fn apply_equivalents(tokens: Vec<String>) -> Vec<String> {
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    'outer: while i < tokens.len() {
+        for (from, to) in EQUIVALENTS {
+            if starts_with(&tokens[i..], from) {
+                result.extend(to.iter().map(|t| t.to_string()));
+                i += from.len();
+                continue 'outer;
+            }
+        }
+        result.push(tokens[i].clone());
+        i += 1;
+    }
+    result
+}
+
+fn starts_with(tokens: &[String], prefix: &[&str]) -> bool {
+    tokens.len() >= prefix.len() && tokens.iter().zip(prefix).all(|(a, b)| a == b)
+}
+
+/// Particles such as `van` or `de` that street names often start with,
+/// tokenized the same way as queries so `'t` and `het` are one stopword.
+#[derive(Debug, Clone, Default)]
+pub struct Stopwords(HashSet<String>);
+
+impl Stopwords {
+    pub fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(words: I) -> Self {
+        Self(
+            words
+                .into_iter()
+                .flat_map(|word| tokens(word.as_ref()))
+                .collect(),
+        )
+    }
+
+    /// Stopwords from `search.stopwords` in the config.
+    pub fn from_config() -> Self {
+        Self::new(&config::current().search.stopwords)
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.0.contains(token)
+    }
+}
@@ -0,0 +1,177 @@
+//! Prefix trie over index tokens
+//!
+//! Every node keeps the ids of all entries whose key passes through it, so a
+//! prefix lookup walks `prefix` one character at a time and ends at a ready,
+//! sorted posting list. Short prefixes like `"k"` cost the same as long ones
+//! instead of merging the postings of every token they cover. Typo
+//! tolerant lookups walk the trie with an edit distance row per node and
+//! stop descending once every entry of the row is over the budget.
+
+use std::borrow::Cow;
+
+use crate::query::edit_distance::{first_row, next_row};
+
+/// Trie from string keys to sorted, deduplicated `u32` ids.
+#[derive(Debug)]
+pub struct PrefixTrie {
+    /// Node 0 is the root.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    /// Child per next character, sorted by character.
+    children: Vec<(char, u32)>,
+    /// Ids of every key with this node's prefix.
+    ids: Vec<u32>,
+}
+
+impl Default for PrefixTrie {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+        }
+    }
+}
+
+impl PrefixTrie {
+    /// Adds `id` under `key`. Ids must be inserted in non-decreasing order,
+    /// which keeps every posting list sorted without a merge step.
+    pub fn insert(&mut self, key: &str, id: u32) {
+        let mut node = 0;
+        push_id(&mut self.nodes[node].ids, id);
+        for c in key.chars() {
+            node = match self.nodes[node]
+                .children
+                .binary_search_by_key(&c, |(child, _)| *child)
+            {
+                Ok(i) => self.nodes[node].children[i].1 as usize,
+                Err(i) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(Node::default());
+                    self.nodes[node].children.insert(i, (c, child as u32));
+                    child
+                }
+            };
+            push_id(&mut self.nodes[node].ids, id);
+        }
+    }
+
+    /// Sorted ids of every key starting with `prefix`.
+    pub fn get(&self, prefix: &str) -> &[u32] {
+        let mut node = &self.nodes[0];
+        for c in prefix.chars() {
+            match node.children.binary_search_by_key(&c, |(child, _)| *child) {
+                Ok(i) => node = &self.nodes[node.children[i].1 as usize],
+                Err(_) => return &[],
+            }
+        }
+        &node.ids
+    }
+
+    /// Sorted ids of every key with a prefix within `max_edits` of
+    /// `prefix`.
+    pub fn get_within(&self, prefix: &str, max_edits: usize) -> Cow<'_, [u32]> {
+        if max_edits == 0 {
+            return Cow::Borrowed(self.get(prefix));
+        }
+        let query: Vec<char> = prefix.chars().collect();
+        let mut ids = Vec::new();
+        let walk = Walk {
+            trie: self,
+            query: &query,
+            max_edits,
+        };
+        walk.visit(0, &first_row(&query), None, &mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        Cow::Owned(ids)
+    }
+
+    /// Releases the slack left by building, call once after the last insert.
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        for node in &mut self.nodes {
+            node.children.shrink_to_fit();
+            node.ids.shrink_to_fit();
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn estimated_memory_bytes(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + self
+                .nodes
+                .iter()
+                .map(|node| {
+                    node.children.capacity() * std::mem::size_of::<(char, u32)>()
+                        + node.ids.capacity() * std::mem::size_of::<u32>()
+                })
+                .sum::<usize>()
+    }
+}
+
+fn push_id(ids: &mut Vec<u32>, id: u32) {
+    if ids.last() != Some(&id) {
+        ids.push(id);
+    }
+}
+
+/// Depth-first, edit distance bounded walk for [`PrefixTrie::get_within`].
+struct Walk<'a> {
+    trie: &'a PrefixTrie,
+    query: &'a [char],
+    max_edits: usize,
+}
+
+impl Walk<'_> {
+    /// `row` holds the distances of the query prefixes to the path leading
+    /// to `node`, `previous` the parent's row and the character between.
+    fn visit(
+        &self,
+        node: usize,
+        row: &[usize],
+        previous: Option<(&[usize], char)>,
+        ids: &mut Vec<u32>,
+    ) {
+        let node = &self.trie.nodes[node];
+        if row[self.query.len()] <= self.max_edits {
+            // the whole query matched, so does every key below
+            ids.extend_from_slice(&node.ids);
+            return;
+        }
+        if row.iter().all(|distance| *distance > self.max_edits) {
+            return;
+        }
+        for &(c, child) in &node.children {
+            let next = next_row(self.query, row, previous, c);
+            self.visit(child as usize, &next, Some((row, c)), ids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trie_answers_prefixes_with_sorted_ids() {
+        let mut trie = PrefixTrie::default();
+        for (id, key) in ["kerk", "kerkstraat", "kade", "kerkstraat"]
+            .iter()
+            .enumerate()
+        {
+            trie.insert(key, id as u32);
+        }
+        assert_eq!(trie.get("ker"), [0, 1, 3]);
+        assert_eq!(trie.get("kerkstraat"), [1, 3]);
+        assert_eq!(trie.get(""), [0, 1, 2, 3]);
+        assert!(trie.get("kerkstraatweg").is_empty());
+        assert_eq!(trie.get_within("kede", 1).as_ref(), [2]);
+        assert_eq!(trie.get_within("kerkstaat", 0).as_ref(), [] as [u32; 0]);
+        assert_eq!(trie.get_within("kerkstaat", 1).as_ref(), [1, 3]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Street name spelling variants
+//!
+//! Dutch street names come in historical and modern spellings: `IJssel` and
+//! `Yssel`, `Sint` and `St`. Each street is indexed under every spelling the
+//! rules produce, so a query in either one finds it. A rule is a pair of
+//! interchangeable spellings; whole-word rules only replace complete tokens
+//! (`sint` and `st`), others replace inside tokens (`ij` and `y`). The rules
+//! are a CSV file with a `spelling,variant,whole_word` header, configured as
+//! `street_variants_path`, and replace the built-in [`DEFAULT_RULES`]. They
+//! are re-read on SIGHUP and apply to the street index from the next data
+//! reload.
+
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+use crate::query::normalize;
+
+/// Spellings for a street, including the original.
+const MAX_VARIANTS: usize = 8;
+
+/// Rules used when no `street_variants_path` is configured.
+pub const DEFAULT_RULES: &[(&str, &str, bool)] = &[("ij", "y", false), ("sint", "st", true)];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VariantRule {
+    pub spelling: String,
+    pub variant: String,
+    #[serde(default)]
+    pub whole_word: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpellingVariants {
+    /// Rules with both spellings folded with [`normalize::text`].
+    rules: Vec<VariantRule>,
+}
+
+impl Default for SpellingVariants {
+    fn default() -> Self {
+        Self::from_rules(
+            DEFAULT_RULES
+                .iter()
+                .map(|&(spelling, variant, whole_word)| VariantRule {
+                    spelling: spelling.to_string(),
+                    variant: variant.to_string(),
+                    whole_word,
+                }),
+        )
+    }
+}
+
+impl SpellingVariants {
+    pub fn from_rules<I: IntoIterator<Item = VariantRule>>(rules: I) -> Self {
+        let rules = rules
+            .into_iter()
+            .map(|rule| VariantRule {
+                spelling: normalize::text(&rule.spelling),
+                variant: normalize::text(&rule.variant),
+                whole_word: rule.whole_word,
+            })
+            .filter(|rule| {
+                !rule.spelling.is_empty()
+                    && !rule.variant.is_empty()
+                    && rule.spelling != rule.variant
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn load_from_csv<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_path(path.as_ref())?;
+        let rules = rdr
+            .deserialize::<VariantRule>()
+            .collect::<Result<Vec<_>, _>>()?;
+        info!(
+            "Loaded {} street spelling rules from {}",
+            rules.len(),
+            path.as_ref().display()
+        );
+        Ok(Self::from_rules(rules))
+    }
+
+    /// `tokens` followed by every other spelling the rules produce, in both
+    /// directions and combined, at most [`MAX_VARIANTS`] in total.
+    pub fn expand(&self, tokens: Vec<String>) -> Vec<Vec<String>> {
+        let mut variants = vec![tokens];
+        for rule in &self.rules {
+            let known = variants.len();
+            for i in 0..known {
+                for (from, to) in [
+                    (&rule.spelling, &rule.variant),
+                    (&rule.variant, &rule.spelling),
+                ] {
+                    if variants.len() >= MAX_VARIANTS {
+                        return variants;
+                    }
+                    let Some(variant) = replace(&variants[i], from, to, rule.whole_word) else {
+                        continue;
+                    };
+                    if !variants.contains(&variant) {
+                        variants.push(variant);
+                    }
+                }
+            }
+        }
+        variants
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// `tokens` with every `from` replaced by `to`, `None` when nothing changed.
+fn replace(tokens: &[String], from: &str, to: &str, whole_word: bool) -> Option<Vec<String>> {
+    let mut changed = false;
+    let replaced = tokens
+        .iter()
+        .map(|token| {
+            if whole_word && token == from {
+                changed = true;
+                to.to_string()
+            } else if !whole_word && token.contains(from) {
+                changed = true;
+                token.replace(from, to)
+            } else {
+                token.clone()
+            }
+        })
+        .collect();
+    changed.then_some(replaced)
+}
+
+lazy_static::lazy_static! {
+    static ref VARIANTS: RwLock<Arc<SpellingVariants>> = RwLock::new(Arc::default());
+}
+
+/// Snapshot of the active rules.
+pub fn current() -> Arc<SpellingVariants> {
+    VARIANTS
+        .read()
+        .map(|variants| variants.clone())
+        .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+}
+
+pub fn set(variants: SpellingVariants) {
+    let variants = Arc::new(variants);
+    match VARIANTS.write() {
+        Ok(mut guard) => *guard = variants,
+        Err(poisoned) => *poisoned.into_inner() = variants,
+    }
+}
+
+/// Loads the rules from `path`, or restores the built-in ones when no path
+/// is configured. On error the previous rules stay active.
+pub fn load(path: Option<&str>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let variants = match path {
+        Some(path) => SpellingVariants::load_from_csv(path)?,
+        None => SpellingVariants::default(),
+    };
+    set(variants);
+    Ok(())
+}
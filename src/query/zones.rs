@@ -0,0 +1,225 @@
+//! Delivery zones
+//!
+//! Named areas operators upload through `PUT /admin/zones/{name}`, as
+//! postal code prefixes, polygons or both, so `/zone_lookup` can tell which
+//! zones an address is in. Polygons are rings of `[longitude, latitude]`
+//! vertices, the GeoJSON order, treated as planar like [`hull`]. With
+//! `zones_path` configured the zones are kept in that JSON file, read at
+//! startup and rewritten on every change; otherwise they live until the
+//! process exits.
+//!
+//! [`hull`]: crate::query::hull
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+use crate::query::postal_code;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Zone {
+    /// Postal codes starting with one of these are in the zone, e.g.
+    /// `"1017"`.
+    pub postal_prefixes: Vec<String>,
+    /// Rings of `[longitude, latitude]` vertices, closed or not. A position
+    /// in any of them is in the zone.
+    pub polygons: Vec<Vec<[f64; 2]>>,
+}
+
+/// Why an address is in a zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneMatch {
+    PostalPrefix,
+    Polygon,
+}
+
+impl Zone {
+    /// The zone with canonical prefixes, or why it can't be used.
+    pub fn validated(mut self) -> Result<Self, String> {
+        if self.postal_prefixes.is_empty() && self.polygons.is_empty() {
+            return Err("a zone needs postal_prefixes or polygons".to_string());
+        }
+        for prefix in &mut self.postal_prefixes {
+            *prefix = postal_code::canonical(prefix);
+            postal_code::parse_query(prefix).map_err(|e| e.to_string())?;
+        }
+        for (i, ring) in self.polygons.iter().enumerate() {
+            if ring.len() < 3 {
+                return Err(format!("polygon {} needs at least 3 vertices", i));
+            }
+            if ring.iter().flatten().any(|value| !value.is_finite()) {
+                return Err(format!("polygon {} has a vertex that is not a number", i));
+            }
+        }
+        Ok(self)
+    }
+
+    /// How `postal_code` or `position` (latitude, longitude) falls in the
+    /// zone, prefixes first.
+    pub fn matches(
+        &self,
+        postal_code: Option<&str>,
+        position: Option<(f64, f64)>,
+    ) -> Option<ZoneMatch> {
+        let by_prefix = postal_code.is_some_and(|code| {
+            self.postal_prefixes
+                .iter()
+                .any(|prefix| code.starts_with(prefix.as_str()))
+        });
+        if by_prefix {
+            return Some(ZoneMatch::PostalPrefix);
+        }
+        let (latitude, longitude) = position?;
+        self.polygons
+            .iter()
+            .any(|ring| contains(ring, longitude, latitude))
+            .then_some(ZoneMatch::Polygon)
+    }
+}
+
+/// Whether `(x, y)` is inside `ring`, by counting the edges a ray to the
+/// east crosses.
+fn contains(ring: &[[f64; 2]], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = ring[ring.len() - 1];
+    for &vertex in ring {
+        let ([x1, y1], [x2, y2]) = (previous, vertex);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+        previous = vertex;
+    }
+    inside
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Zones {
+    by_name: BTreeMap<String, Zone>,
+}
+
+impl Zones {
+    pub fn get(&self, name: &str) -> Option<&Zone> {
+        self.by_name.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Zone)> {
+        self.by_name
+            .iter()
+            .map(|(name, zone)| (name.as_str(), zone))
+    }
+
+    /// Zones containing the address, in name order.
+    pub fn containing(
+        &self,
+        postal_code: Option<&str>,
+        position: Option<(f64, f64)>,
+    ) -> Vec<(&str, ZoneMatch)> {
+        self.iter()
+            .filter_map(|(name, zone)| Some((name, zone.matches(postal_code, position)?)))
+            .collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ZONES: RwLock<Arc<Zones>> = RwLock::new(Arc::default());
+}
+
+/// Snapshot of the uploaded zones.
+pub fn current() -> Arc<Zones> {
+    ZONES
+        .read()
+        .map(|zones| zones.clone())
+        .unwrap_or_else(|poisoned| poisoned.into_inner().clone())
+}
+
+/// Applies `change` to a copy of the zones, writes it to `path` when one
+/// is configured and only then makes it current, so a failed write changes
+/// nothing.
+fn update(
+    path: Option<&str>,
+    change: impl FnOnce(&mut Zones),
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut guard = ZONES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut zones = Zones::clone(&guard);
+    change(&mut zones);
+    if let Some(path) = path {
+        fs::write(path, serde_json::to_vec_pretty(&zones.by_name)?)?;
+    }
+    *guard = Arc::new(zones);
+    Ok(())
+}
+
+/// Adds or replaces the zone `name`.
+pub fn put(path: Option<&str>, name: &str, zone: Zone) -> Result<(), Box<dyn Error + Send + Sync>> {
+    update(path, |zones| {
+        zones.by_name.insert(name.to_string(), zone);
+    })
+}
+
+/// Removes the zone `name`, `false` when there was none.
+pub fn remove(path: Option<&str>, name: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut removed = false;
+    update(path, |zones| removed = zones.by_name.remove(name).is_some())?;
+    Ok(removed)
+}
+
+/// Loads the zones kept at `path`; a file that does not exist yet means
+/// none. On error the previous zones stay active.
+pub fn load(path: Option<&str>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let by_name: BTreeMap<String, Zone> = match path.map(fs::read) {
+        Some(Ok(bytes)) => serde_json::from_slice(&bytes)?,
+        Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => BTreeMap::new(),
+    };
+    let by_name = by_name
+        .into_iter()
+        .map(|(name, zone)| Ok((name, zone.validated()?)))
+        .collect::<Result<_, String>>()?;
+    let zones = Zones { by_name };
+    info!("Loaded {} delivery zones", zones.len());
+    *ZONES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(zones);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_match_by_prefix_or_polygon() {
+        let zone = Zone {
+            postal_prefixes: vec!["1017 g".to_string()],
+            polygons: vec![vec![[4.0, 52.0], [5.0, 52.0], [5.0, 53.0], [4.0, 53.0]]],
+        }
+        .validated()
+        .unwrap();
+        assert_eq!(zone.postal_prefixes, ["1017G"]);
+        assert_eq!(
+            zone.matches(Some("1017GE"), None),
+            Some(ZoneMatch::PostalPrefix)
+        );
+        assert_eq!(
+            zone.matches(Some("3511LX"), Some((52.09, 4.5))),
+            Some(ZoneMatch::Polygon)
+        );
+        assert_eq!(zone.matches(Some("3511LX"), Some((52.09, 5.12))), None);
+        assert!(Zone::default().validated().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Replayable query log
+//!
+//! When `query_log.path` is set, every request of a group with `query_log`
+//! enabled is appended to that file as one JSON line (timestamp, method,
+//! endpoint, query parameters, status). Credentials are left out: the
+//! `key` parameter is not written. The `replay` command (see
+//! [`crate::replay`]) sends a log to another instance, e.g. one running a
+//! new dataset version, and reports the responses that changed.
+
+use actix_web::dev::ServiceResponse;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::sync::Mutex;
+use tracing::error;
+
+use crate::api::google;
+use crate::config;
+
+/// Query parameters that carry credentials, never written to the log.
+const CREDENTIAL_PARAMS: [&str; 1] = [google::KEY_PARAM];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QueryLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    /// Request path, e.g. `/search`.
+    pub endpoint: String,
+    pub params: BTreeMap<String, String>,
+    /// Status the logging instance answered with.
+    pub status: u16,
+}
+
+impl QueryLogEntry {
+    /// Path and query string to send when replaying.
+    pub fn path_and_query(&self) -> String {
+        if self.params.is_empty() {
+            return self.endpoint.clone();
+        }
+        let query = self
+            .params
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        format!("{}?{}", self.endpoint, query)
+    }
+}
+
+/// Percent-encodes everything but unreserved characters.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Open log file and the path it was opened for, so a SIGHUP that changes
+/// `query_log.path` switches files.
+struct Writer {
+    path: String,
+    file: LineWriter<File>,
+}
+
+lazy_static::lazy_static! {
+    static ref WRITER: Mutex<Option<Writer>> = Mutex::new(None);
+}
+
+/// The parameters of `query` worth replaying.
+fn params(query: &str) -> BTreeMap<String, String> {
+    let mut params = actix_web::web::Query::<BTreeMap<String, String>>::from_query(query)
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    params.retain(|name, _| {
+        !CREDENTIAL_PARAMS
+            .iter()
+            .any(|credential| name.eq_ignore_ascii_case(credential))
+    });
+    params
+}
+
+/// Appends the request behind `res` to the query log, if one is configured.
+pub fn record<B>(res: &ServiceResponse<B>) {
+    let Some(path) = config::current().query_log.path.clone() else {
+        return;
+    };
+    let req = res.request();
+    let entry = QueryLogEntry {
+        timestamp: Utc::now(),
+        method: req.method().to_string(),
+        endpoint: req.path().to_string(),
+        params: params(req.query_string()),
+        status: res.status().as_u16(),
+    };
+    if let Err(e) = append(&path, &entry) {
+        error!("Failed to write query log {}: {}", path, e);
+    }
+}
+
+fn append(path: &str, entry: &QueryLogEntry) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut writer = WRITER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if writer.as_ref().is_none_or(|writer| writer.path != path) {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *writer = Some(Writer {
+            path: path.to_string(),
+            file: LineWriter::new(file),
+        });
+    }
+    if let Some(writer) = writer.as_mut() {
+        writer.file.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// Reads a query log. Malformed lines are an error, with their line number.
+pub fn read(path: &str) -> Result<Vec<QueryLogEntry>, Box<dyn Error + Send + Sync>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry =
+            serde_json::from_str(&line).map_err(|e| format!("{}:{}: {}", path, i + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_keys_are_not_logged() {
+        let params = params("input=kerkstraat&key=secret-1&KEY=secret-2&limit=5");
+        assert_eq!(params.keys().collect::<Vec<_>>(), vec!["input", "limit"]);
+        assert!(!serde_json::to_string(&params).unwrap().contains("secret"));
+    }
+}
@@ -0,0 +1,86 @@
+//! SIGHUP handling: re-read the config file and, when enabled, reload the
+//! location data without restarting the process.
+
+use tracing::{error, info};
+
+use crate::cache::response_cache::{flush_all, retain_loaded_version, RESPONSE_CACHE};
+use crate::config;
+use crate::logging::set_log_level;
+use crate::query::{aliases, canary, reload_location_data, variants};
+
+/// Re-reads the config, applies the log level, reloads the municipality
+/// aliases and street spelling variants and optionally reloads the data
+/// folder, which indexes streets under the new variants, then reruns the
+/// canaries. Errors are logged and the previous state stays active.
+pub async fn reload_from_config() {
+    let config = match config::reload() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to reload config, keeping previous: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = set_log_level(&config.log_level) {
+        error!("Failed to apply log level '{}': {}", config.log_level, e);
+    }
+
+    if let Err(e) = aliases::load(config.municipality_aliases_path.as_deref()) {
+        error!(
+            "Failed to reload municipality aliases, keeping previous: {}",
+            e
+        );
+    }
+
+    if let Err(e) = variants::load(config.street_variants_path.as_deref()) {
+        error!(
+            "Failed to reload street spelling variants, keeping previous: {}",
+            e
+        );
+    }
+
+    if config.reload_data_on_sighup {
+        let data_dir = config.data_dir.clone();
+        let result = tokio::task::spawn_blocking(move || reload_location_data(&data_dir)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to reload location data, keeping previous: {}", e),
+            Err(e) => error!("Data reload task failed: {}", e),
+        }
+    }
+
+    // the canaries may have changed even when the data did not
+    canary::run();
+
+    // cached responses depend on the config (presets, fields), aliases and data
+    flush_all(&RESPONSE_CACHE).await;
+    if let Err(e) = retain_loaded_version() {
+        error!("Failed to drop cache counts of unloaded data: {}", e);
+    }
+
+    info!("Reload complete");
+}
+
+/// Spawns a task that calls [`reload_from_config`] on every SIGHUP.
+#[cfg(unix)]
+pub fn spawn_sighup_listener() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("Failed to install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading config");
+            reload_from_config().await;
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_sighup_listener() {}
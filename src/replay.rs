@@ -0,0 +1,187 @@
+//! `replay` command
+//!
+//! Sends the queries of a query log (see [`crate::query_log`]) to an
+//! instance and prints every query whose result changed as one JSON line.
+//! With `--baseline` both instances are queried and their responses
+//! compared, e.g. the current and the next dataset version; without it only
+//! the status is compared against the one in the log.
+//!
+//! ```text
+//! places-cli replay queries.jsonl --target http://new:4444 [--baseline http://old:4444]
+//! ```
+
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+
+use crate::query_log::{self, QueryLogEntry};
+
+pub const USAGE: &str =
+    "usage: places-cli replay <query_log.jsonl> --target <url> [--baseline <url>]";
+
+/// Response fields that differ between runs without the result changing.
+const VOLATILE_FIELDS: &[&str] = &["timings", "latency"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayArgs {
+    pub log: String,
+    pub target: String,
+    pub baseline: Option<String>,
+}
+
+impl ReplayArgs {
+    /// Parses the arguments following `replay`.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut log = None;
+        let mut target = None;
+        let mut baseline = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = args.next().cloned(),
+                "--baseline" => baseline = args.next().cloned(),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+                _ if log.is_none() => log = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        Ok(Self {
+            log: log.ok_or("missing query log")?,
+            target: target.ok_or("missing --target")?,
+            baseline,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplaySummary {
+    pub replayed: usize,
+    pub identical: usize,
+    pub changed: usize,
+    pub failed: usize,
+}
+
+/// One query whose outcome differs.
+#[derive(Debug, Serialize)]
+struct Change<'a> {
+    endpoint: &'a str,
+    params: &'a std::collections::BTreeMap<String, String>,
+    expected_status: u16,
+    actual_status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<Value>,
+}
+
+struct Outcome {
+    status: u16,
+    body: Value,
+}
+
+async fn fetch(
+    client: &reqwest::Client,
+    base: &str,
+    entry: &QueryLogEntry,
+) -> Result<Outcome, reqwest::Error> {
+    let url = format!("{}{}", base.trim_end_matches('/'), entry.path_and_query());
+    let response = client.get(url).send().await?;
+    let status = response.status().as_u16();
+    let text = response.text().await?;
+    let mut body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    strip_volatile(&mut body);
+    Ok(Outcome { status, body })
+}
+
+fn strip_volatile(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for field in VOLATILE_FIELDS {
+                map.remove(*field);
+            }
+            map.values_mut().for_each(strip_volatile);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_volatile),
+        _ => {}
+    }
+}
+
+/// Replays the log and prints changes to stdout, one JSON object per line.
+pub async fn replay(args: &ReplayArgs) -> Result<ReplaySummary, Box<dyn Error + Send + Sync>> {
+    let entries = query_log::read(&args.log)?;
+    let client = reqwest::Client::new();
+    let mut summary = ReplaySummary::default();
+
+    for entry in entries.iter().filter(|entry| entry.method == "GET") {
+        summary.replayed += 1;
+        let actual = match fetch(&client, &args.target, entry).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("{}: {}", entry.path_and_query(), e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        let expected = match &args.baseline {
+            Some(baseline) => match fetch(&client, baseline, entry).await {
+                Ok(outcome) => Some(outcome),
+                Err(e) => {
+                    eprintln!("{}: {}", entry.path_and_query(), e);
+                    summary.failed += 1;
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let expected_status = expected.as_ref().map_or(entry.status, |e| e.status);
+        let same = expected_status == actual.status
+            && expected.as_ref().is_none_or(|e| e.body == actual.body);
+        if same {
+            summary.identical += 1;
+            continue;
+        }
+        summary.changed += 1;
+        let change = Change {
+            endpoint: &entry.endpoint,
+            params: &entry.params,
+            expected_status,
+            actual_status: actual.status,
+            expected: expected.map(|e| e.body),
+            actual: args.baseline.as_ref().map(|_| actual.body),
+        };
+        println!("{}", serde_json::to_string(&change)?);
+    }
+    Ok(summary)
+}
+
+/// Entry point for `replay`, returns the exit
+/// code: 0 when nothing changed, 1 when something did, 2 on errors.
+pub async fn run(args: &[String]) -> i32 {
+    let args = match ReplayArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    match replay(&args).await {
+        Ok(summary) => {
+            eprintln!(
+                "replayed {} queries: {} identical, {} changed, {} failed",
+                summary.replayed, summary.identical, summary.changed, summary.failed
+            );
+            if summary.failed > 0 {
+                2
+            } else if summary.changed > 0 {
+                1
+            } else {
+                0
+            }
+        }
+        Err(e) => {
+            eprintln!("replay failed: {}", e);
+            2
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Startup summary and environment report, logged once the data is loaded
+//! and served on `/admin/info`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, OnceLock};
+use tracing::{info, warn};
+
+use crate::config::{self, Config};
+use crate::query::error::QueryError;
+use crate::query::{dataset_summary, DatasetSummary};
+
+static STARTED_AT: OnceLock<DateTime<Utc>> = OnceLock::new();
+
+#[derive(Debug, Serialize)]
+pub struct EnvironmentReport {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub started_at: Option<DateTime<Utc>>,
+    pub uptime_secs: i64,
+    pub features: Vec<&'static str>,
+    pub config: Arc<Config>,
+    pub dataset: DatasetSummary,
+}
+
+/// Records the process start time. Later calls are ignored.
+pub fn mark_started() {
+    STARTED_AT.get_or_init(Utc::now);
+}
+
+/// Cargo features this binary was compiled with.
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "redis") {
+        features.push("redis");
+    }
+    if cfg!(feature = "replay") {
+        features.push("replay");
+    }
+    if cfg!(feature = "swagger-ui") {
+        features.push("swagger-ui");
+    }
+    if cfg!(feature = "grpc") {
+        features.push("grpc");
+    }
+    features
+}
+
+pub fn environment_report() -> Result<EnvironmentReport, QueryError> {
+    let started_at = STARTED_AT.get().copied();
+    Ok(EnvironmentReport {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        started_at,
+        uptime_secs: started_at.map_or(0, |started| (Utc::now() - started).num_seconds()),
+        features: enabled_features(),
+        config: config::current(),
+        dataset: dataset_summary()?,
+    })
+}
+
+/// Logs the environment report in a human readable form.
+pub fn log_startup_summary() {
+    let report = match environment_report() {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("No startup summary: {}", e);
+            return;
+        }
+    };
+    let dataset = &report.dataset;
+
+    info!("{} v{} starting", report.name, report.version);
+    info!("Features: {:?}", report.features);
+    info!(
+        "Config: {}",
+        serde_json::to_string(&report.config).unwrap_or_default()
+    );
+    for file in &dataset.files {
+        info!(
+            "Loaded {}: {} rows, {} skipped, {} ms",
+            file.path, file.rows, file.skipped, file.load_ms
+        );
+    }
+    info!(
+        "Dataset: {} rows in {} files, {} postal codes, {} streets, indexes built in {} ms, ~{} MiB",
+        dataset.total_rows,
+        dataset.files.len(),
+        dataset.postal_codes,
+        dataset.streets,
+        dataset.index_build_ms,
+        dataset.estimated_memory_bytes / (1024 * 1024)
+    );
+}
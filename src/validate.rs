@@ -0,0 +1,181 @@
+//! `validate` command
+//!
+//! Loads a data folder with every check strict loading applies, without
+//! starting the server, and prints a JSON report to stdout. Meant as a gate
+//! in the data pipeline before a new dataset goes to production: the exit
+//! code is 0 when the dataset would pass `--strict`, 1 when it would not.
+//! When the folder holds the generator's report, the row count of every
+//! shard is checked against it too, so a truncated copy is caught.
+//!
+//! ```text
+//! places-cli validate ./data_split
+//! ```
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::generator::{GeneratorReport, REPORT_FILE_NAME};
+use crate::query::load::{LoadError, LoadMode};
+use crate::query::{DatasetSummary, LocationData};
+
+pub const USAGE: &str = "usage: places-cli validate <data_dir> [--max-issues <n>]";
+
+/// Issues listed in the report unless `--max-issues` says otherwise. The
+/// counts always cover all of them.
+const DEFAULT_MAX_ISSUES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidateArgs {
+    pub data_dir: String,
+    pub max_issues: usize,
+}
+
+impl ValidateArgs {
+    /// Parses the arguments following `validate`.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut data_dir = None;
+        let mut max_issues = DEFAULT_MAX_ISSUES;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--max-issues" => {
+                    max_issues = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--max-issues needs a number")?;
+                }
+                _ if arg.starts_with("--") || data_dir.is_some() => {
+                    return Err(format!("unexpected argument {}", arg));
+                }
+                _ => data_dir = Some(arg.clone()),
+            }
+        }
+        Ok(Self {
+            data_dir: data_dir.ok_or("missing <data_dir>")?,
+            max_issues,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Issue {
+    pub path: String,
+    pub line: Option<u64>,
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<&LoadError> for Issue {
+    fn from(error: &LoadError) -> Self {
+        Self {
+            path: error.path.clone(),
+            line: error.line,
+            kind: error.issue.kind(),
+            message: error.issue.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    pub data_dir: String,
+    pub valid: bool,
+    /// `None` when the folder could not be read at all.
+    pub dataset: Option<DatasetSummary>,
+    /// Number of issues per kind.
+    pub issue_counts: BTreeMap<&'static str, usize>,
+    pub issues: Vec<Issue>,
+    /// Issues left out of `issues` because of `--max-issues`.
+    pub issues_truncated: usize,
+}
+
+/// Loads `args.data_dir` in report mode and collects every problem.
+pub fn validate(args: &ValidateArgs) -> ValidationReport {
+    let mut data = LocationData::new();
+    let (dataset, errors) = match data.load_all(&args.data_dir, LoadMode::Report) {
+        Ok(()) => (Some(data.summary()), data.load_issues().to_vec()),
+        Err(e) => (None, vec![e]),
+    };
+
+    let mut issues: Vec<Issue> = errors.iter().map(Issue::from).collect();
+    if let Some(dataset) = &dataset {
+        issues.extend(check_generator_report(&args.data_dir, dataset));
+    }
+
+    let mut issue_counts = BTreeMap::new();
+    for issue in &issues {
+        *issue_counts.entry(issue.kind).or_default() += 1;
+    }
+    let issues_truncated = issues.len().saturating_sub(args.max_issues);
+    issues.truncate(args.max_issues);
+    ValidationReport {
+        data_dir: args.data_dir.clone(),
+        valid: issue_counts.is_empty(),
+        dataset,
+        issue_counts,
+        issues,
+        issues_truncated,
+    }
+}
+
+/// Compares the loaded row count of every shard with the generator report
+/// in `data_dir`, if there is one.
+fn check_generator_report(data_dir: &str, dataset: &DatasetSummary) -> Vec<Issue> {
+    let path = Path::new(data_dir).join(REPORT_FILE_NAME);
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let issue = |kind, message| Issue {
+        path: path.display().to_string(),
+        line: None,
+        kind,
+        message,
+    };
+    let report: GeneratorReport = match serde_json::from_str(&contents) {
+        Ok(report) => report,
+        Err(e) => return vec![issue("generator_report_unreadable", e.to_string())],
+    };
+
+    report
+        .shards
+        .iter()
+        .filter_map(|shard| {
+            let loaded = dataset
+                .files
+                .iter()
+                .find(|file| Path::new(&file.path).file_name() == Some(shard.file.as_ref()))
+                .map_or(0, |file| file.rows);
+            (loaded != shard.rows).then(|| {
+                issue(
+                    "generator_report_mismatch",
+                    format!(
+                        "{}: {} rows loaded, the generator wrote {}",
+                        shard.file, loaded, shard.rows
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Entry point for `validate`, returns the exit code: 0 for a valid
+/// dataset, 1 for an invalid one, 2 on usage errors.
+pub fn run(args: &[String]) -> i32 {
+    let args = match ValidateArgs::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let report = validate(&args);
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("validate failed: {}", e);
+            return 2;
+        }
+    }
+    i32::from(!report.valid)
+}